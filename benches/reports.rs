@@ -1,7 +1,9 @@
 use chrono::{Days, Utc};
 use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
 use liwan::app::Liwan;
-use liwan::app::reports::{self, DateRange, Dimension, GraphInterval, Metric};
+use liwan::app::reports::{
+    self, DateRange, Dimension, DimensionTableOptions, GraphInterval, Metric, ReportQuery, SessionTimeout, WeekStart,
+};
 use liwan::config::Config;
 use std::time::Duration;
 
@@ -30,10 +32,21 @@ fn benchmark_reports(c: &mut Criterion) {
         start: Utc::now().checked_sub_days(Days::new(365)).expect("failed to build range start"),
         end: Utc::now(),
     };
-    let day_buckets = reports::build_graph_buckets(&range, GraphInterval::Day, Some("UTC"))
-        .expect("failed to build day buckets for benchmark");
+    let day_buckets =
+        reports::build_graph_buckets(&range, GraphInterval::Day, Some("UTC"), WeekStart::Monday, usize::MAX)
+            .expect("failed to build day buckets for benchmark")
+            .expect("too many buckets");
 
     let conn = app.events_conn().expect("failed to get events connection");
+    let query = ReportQuery {
+        entities: &entities,
+        event: "pageview",
+        range: &range,
+        filters: &[],
+        session_timeout: SessionTimeout::DEFAULT,
+        approximate: false,
+        shared_visitor_groups: false,
+    };
 
     {
         let mut group = c.benchmark_group("report_meta");
@@ -50,12 +63,9 @@ fn benchmark_reports(c: &mut Criterion) {
     {
         let mut group = c.benchmark_group("overall_report");
         configure_group(&mut group);
-        for metric in [Metric::Views, Metric::UniqueVisitors, Metric::BounceRate, Metric::AvgTimeOnSite] {
-            group.bench_with_input(BenchmarkId::new("metric", format!("{metric:?}")), &metric, |b, metric| {
-                b.iter(|| {
-                    reports::overall_report(&conn, &entities, "pageview", &range, &day_buckets, &[], metric)
-                        .expect("overall_report failed")
-                });
+        for metric in Metric::all() {
+            group.bench_with_input(BenchmarkId::new("metric", format!("{metric:?}")), metric, |b, metric| {
+                b.iter(|| reports::overall_report(&conn, &query, &day_buckets, metric).expect("overall_report failed"));
             });
         }
         group.finish();
@@ -65,7 +75,7 @@ fn benchmark_reports(c: &mut Criterion) {
         let mut group = c.benchmark_group("overall_stats");
         configure_group(&mut group);
         group.bench_function("all_metrics", |b| {
-            b.iter(|| reports::overall_stats(&conn, &entities, "pageview", &range, &[]).expect("overall_stats failed"));
+            b.iter(|| reports::overall_stats(&conn, &query).expect("overall_stats failed"));
         });
         group.finish();
     }
@@ -74,13 +84,14 @@ fn benchmark_reports(c: &mut Criterion) {
         let mut group = c.benchmark_group("dimension_report");
         configure_group(&mut group);
         let dimension = Dimension::Url;
-        for metric in [Metric::Views, Metric::UniqueVisitors, Metric::BounceRate, Metric::AvgTimeOnSite] {
+        let options = DimensionTableOptions { limit: Some(10), include_other: true, ..Default::default() };
+        for metric in Metric::all() {
             group.bench_with_input(
                 BenchmarkId::new("dim_metric", format!("{dimension:?}/{metric:?}")),
-                &metric,
+                metric,
                 |b, metric| {
                     b.iter(|| {
-                        reports::dimension_report(&conn, &entities, "pageview", &range, &dimension, &[], metric)
+                        reports::dimension_report(&conn, &query, &dimension, metric, &options)
                             .expect("dimension_report failed")
                     });
                 },
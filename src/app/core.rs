@@ -19,4 +19,4 @@ pub use users::LiwanUsers;
 mod geoip;
 
 #[cfg(feature = "geoip")]
-pub use geoip::{LiwanGeoIP, keep_updated};
+pub use geoip::{GeoIpCheckResult, GeoIpDatabaseStatus, LiwanGeoIP, keep_updated};
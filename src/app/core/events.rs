@@ -1,13 +1,16 @@
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
 
 use anyhow::{Context, Result};
 use arc_swap::ArcSwap;
 use chrono::{DateTime, Local, NaiveTime, TimeZone, Utc};
+use duckdb::types::Value;
 use duckdb::{Connection, Result as DuckResult, params};
 use rand::distr::{SampleString, StandardUniform};
 use tokio::sync::mpsc::Receiver;
 
 use crate::app::models::{Event, GeoDetail, ResolvedCollectionSettings, event_params};
+use crate::app::reports::{ReportCache, invalidate_rollups, rebuild_entity_rollups};
 use crate::app::{DuckDBPool, SqlitePool};
 use crate::utils::duckdb::{ParamVec, repeat_vars};
 
@@ -17,6 +20,21 @@ pub struct LiwanEvents {
     sqlite: SqlitePool,
     daily_salt: Arc<ArcSwap<(String, DateTime<Utc>)>>,
     visitor_group_rotation_hour: u8,
+    visitor_times: Arc<Mutex<VisitorTimes>>,
+    report_cache: Arc<ReportCache>,
+}
+
+/// How far back the previous event of a visitor group is looked for when calculating session intervals
+const VISITOR_LOOKBACK_HOURS: i64 = 24;
+
+/// Last event time of recently active visitor groups
+///
+/// Used to calculate session intervals of new events without rescanning the events table.
+/// Visitor groups that aren't in memory, e.g. after a restart, are looked up in the database.
+#[derive(Debug)]
+struct VisitorTimes {
+    last_event: HashMap<String, DateTime<Utc>>,
+    pruned_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -26,17 +44,30 @@ pub struct PruneStats {
     pub cleared_utm_events: u64,
     pub cleared_geo_events: u64,
     pub cleared_session_events: u64,
+    pub cleared_language_events: u64,
 }
 
 impl LiwanEvents {
-    pub fn try_new(duckdb: DuckDBPool, sqlite: SqlitePool, visitor_group_rotation_hour: u8) -> Result<Self> {
+    pub fn try_new(
+        duckdb: DuckDBPool,
+        sqlite: SqlitePool,
+        report_cache: Arc<ReportCache>,
+        visitor_group_rotation_hour: u8,
+    ) -> Result<Self> {
         let daily_salt: (String, DateTime<Utc>) = {
             tracing::debug!("Loading visitor group salt");
             sqlite.get()?.query_row("select salt, updated_at from salts where id = 1", [], |row| {
                 Ok((row.get(0)?, row.get(1)?))
             })?
         };
-        Ok(Self { duckdb, sqlite, daily_salt: ArcSwap::new(daily_salt.into()).into(), visitor_group_rotation_hour })
+        Ok(Self {
+            duckdb,
+            sqlite,
+            daily_salt: ArcSwap::new(daily_salt.into()).into(),
+            visitor_group_rotation_hour,
+            visitor_times: Arc::new(Mutex::new(VisitorTimes::new())),
+            report_cache,
+        })
     }
 
     /// Get the visitor group salt, generating a new one after the daily local rotation time
@@ -61,27 +92,107 @@ impl LiwanEvents {
 
     /// Append events in a batch and update session timing fields when needed
     pub fn append(&self, events: impl Iterator<Item = Event>) -> Result<()> {
-        let conn = self.duckdb.get()?;
-        let mut first_event_time = None;
-        let mut session_entities = Vec::new();
-        let mut appender = conn.appender("events").context("Failed to get DuckDB appender")?;
-        for event in events {
-            if event.track_sessions {
-                if first_event_time.is_none_or(|first_event_time| event.created_at < first_event_time) {
-                    first_event_time = Some(event.created_at);
+        let mut conn = self.duckdb.get()?;
+        self.write_batch(&mut conn, events.collect())
+    }
+
+    /// Write a batch of events, calculating session intervals from the previous event of each visitor group
+    ///
+    /// The events and their session updates are written in a single transaction. The last event times in memory are
+    /// only updated once it committed, so a failed batch doesn't affect the intervals of later ones.
+    fn write_batch(&self, conn: &mut Connection, mut events: Vec<Event>) -> Result<()> {
+        events.sort_by_key(|event| event.created_at);
+        let first_session_event = events.iter().find(|event| event.track_sessions).map(|event| event.created_at);
+
+        let mut visitor_times = self.visitor_times.lock().expect("visitor times poisoned");
+        if let Some(first_session_event) = first_session_event {
+            let mut missing = events
+                .iter()
+                .filter(|event| event.track_sessions && !visitor_times.last_event.contains_key(&event.visitor_group_id))
+                .map(|event| event.visitor_group_id.as_str())
+                .collect::<Vec<_>>();
+            missing.sort_unstable();
+            missing.dedup();
+            visitor_times.load(conn, &missing, first_session_event).context("Failed to load visitor event times")?;
+        }
+
+        // (time_from_last_event, time_to_next_event) for each event in the batch
+        let mut intervals = vec![(None, None); events.len()];
+        // previous events that are already in the database and need their time_to_next_event updated
+        let mut previous_events = Vec::new();
+        let mut last_in_batch: HashMap<&str, usize> = HashMap::new();
+        let mut out_of_order = HashSet::new();
+        // events are sorted, so this is the earliest out-of-order event
+        let mut first_out_of_order = None;
+
+        for (idx, event) in events.iter().enumerate() {
+            if !event.track_sessions {
+                continue;
+            }
+
+            let visitor = event.visitor_group_id.as_str();
+            let last = match last_in_batch.get(visitor) {
+                Some(&previous) => Some(events[previous].created_at),
+                None => visitor_times.last_event.get(visitor).copied(),
+            };
+            match last {
+                Some(last) if event.created_at < last => {
+                    out_of_order.insert(visitor);
+                    first_out_of_order.get_or_insert(event.created_at);
+                    continue;
                 }
-                if !session_entities.contains(&event.entity_id) {
-                    session_entities.push(event.entity_id.clone());
+                Some(last) => {
+                    let interval = event.created_at - last;
+                    intervals[idx].0 = Some(interval);
+                    match last_in_batch.get(visitor) {
+                        Some(&previous) => intervals[previous].1 = Some(interval),
+                        None => previous_events.push((visitor, last, interval)),
+                    }
                 }
+                None => {}
             }
-            appender.append_row(event_params![event]).context("Failed to append event to DuckDB")?;
+
+            last_in_batch.insert(visitor, idx);
         }
 
+        let tx = conn.transaction().context("Failed to start DuckDB transaction")?;
+        let mut appender = tx.appender("events").context("Failed to get DuckDB appender")?;
+        for (event, (time_from_last_event, time_to_next_event)) in events.iter().zip(intervals) {
+            let time_from_last_event = time_from_last_event.and_then(interval_value);
+            let time_to_next_event = time_to_next_event.and_then(interval_value);
+            appender
+                .append_row(event_params![event, time_from_last_event, time_to_next_event])
+                .context("Failed to append event to DuckDB")?;
+        }
         appender.flush().context("Failed to flush events to DuckDB")?;
-        if let Some(first_event_time) = first_event_time {
-            update_event_times(&conn, first_event_time, &session_entities)
-                .context("Failed to update event times in DuckDB")?;
+        drop(appender);
+
+        update_previous_events(&tx, &previous_events).context("Failed to update event times in DuckDB")?;
+
+        if let Some(first_out_of_order) = first_out_of_order {
+            let out_of_order = out_of_order.into_iter().collect::<Vec<_>>();
+            recalculate_event_times(&tx, &out_of_order, first_out_of_order)
+                .context("Failed to recalculate event times in DuckDB")?;
         }
+
+        tx.commit().context("Failed to commit events to DuckDB")?;
+
+        for (visitor, &idx) in &last_in_batch {
+            visitor_times.last_event.insert((*visitor).to_string(), events[idx].created_at);
+        }
+        visitor_times.prune();
+
+        // backfilled events make already aggregated rollup buckets stale
+        if let Some(first_event) = events.first()
+            && let Err(err) = invalidate_rollups(conn, first_event.created_at)
+        {
+            tracing::error!(error = ?err, "Failed to invalidate rollups");
+        }
+
+        let mut entities = events.iter().map(|event| event.entity_id.as_str()).collect::<Vec<_>>();
+        entities.sort_unstable();
+        entities.dedup();
+        self.report_cache.invalidate_entities(entities);
         Ok(())
     }
 
@@ -108,28 +219,9 @@ impl LiwanEvents {
                 break Ok(());
             }
 
-            let mut first_event_time = None;
-            let mut session_entities = Vec::new();
             let mut insert_events = || -> Result<()> {
-                let conn = conn.get().context("Failed to get DuckDB connection")?;
-                let mut appender = conn.appender("events").context("Failed to get DuckDB appender")?;
-                for event in buffer.drain(..count) {
-                    if event.track_sessions {
-                        if first_event_time.is_none_or(|first_event_time| event.created_at < first_event_time) {
-                            first_event_time = Some(event.created_at);
-                        }
-                        if !session_entities.contains(&event.entity_id) {
-                            session_entities.push(event.entity_id.clone());
-                        }
-                    }
-                    appender.append_row(event_params![event]).context("Failed to append event to DuckDB")?;
-                }
-
-                appender.flush().context("Failed to flush events to DuckDB")?;
-                if let Some(first_event_time) = first_event_time {
-                    update_event_times(&conn, first_event_time, &session_entities)?;
-                }
-                Ok(())
+                let mut conn = conn.get().context("Failed to get DuckDB connection")?;
+                self.write_batch(&mut conn, buffer.drain(..count).collect())
             };
 
             match insert_events() {
@@ -146,7 +238,7 @@ impl LiwanEvents {
         settings: &ResolvedCollectionSettings,
         dry_run: bool,
     ) -> Result<PruneStats> {
-        let conn = self.duckdb.get()?;
+        let mut conn = self.duckdb.get()?;
         let mut stats = PruneStats {
             total_events: count_rows(&conn, "select count(*) from events where entity_id = ?", params![entity_id])?,
             ..Default::default()
@@ -183,22 +275,27 @@ impl LiwanEvents {
 
         match settings.track_geo {
             GeoDetail::None => {
-                let sql = "entity_id = ? and (country is not null or city is not null)";
+                let sql = "entity_id = ? and (country is not null or city is not null or region is not null or continent is not null or time_zone is not null or asn is not null or asn_org is not null)";
                 stats.cleared_geo_events =
                     count_rows(&conn, &format!("select count(*) from events where {sql}"), params![entity_id])?;
                 if !dry_run {
                     conn.execute(
-                        &format!("update events set country = null, city = null where {sql}"),
+                        &format!(
+                            "update events set country = null, city = null, region = null, continent = null, time_zone = null, asn = null, asn_org = null where {sql}"
+                        ),
                         params![entity_id],
                     )?;
                 }
             }
             GeoDetail::Country => {
-                let sql = "entity_id = ? and city is not null";
+                let sql = "entity_id = ? and (city is not null or region is not null or time_zone is not null)";
                 stats.cleared_geo_events =
                     count_rows(&conn, &format!("select count(*) from events where {sql}"), params![entity_id])?;
                 if !dry_run {
-                    conn.execute(&format!("update events set city = null where {sql}"), params![entity_id])?;
+                    conn.execute(
+                        &format!("update events set city = null, region = null, time_zone = null where {sql}"),
+                        params![entity_id],
+                    )?;
                 }
             }
             GeoDetail::City => {}
@@ -216,6 +313,22 @@ impl LiwanEvents {
             }
         }
 
+        if !settings.track_language {
+            let sql = "entity_id = ? and language is not null";
+            stats.cleared_language_events =
+                count_rows(&conn, &format!("select count(*) from events where {sql}"), params![entity_id])?;
+            if !dry_run {
+                conn.execute(&format!("update events set language = null where {sql}"), params![entity_id])?;
+            }
+        }
+
+        if !dry_run {
+            // deleted events and cleared session times invalidate the last event times in memory
+            self.visitor_times.lock().expect("visitor times poisoned").last_event.clear();
+            rebuild_entity_rollups(&mut conn, entity_id).context("Failed to rebuild rollups")?;
+            self.report_cache.clear();
+        }
+
         Ok(stats)
     }
 }
@@ -235,49 +348,138 @@ fn should_rotate_salt(updated_at: DateTime<Utc>, rotation_hour: u8) -> bool {
     updated_at < latest_rotation.with_timezone(&Utc)
 }
 
+impl VisitorTimes {
+    fn new() -> Self {
+        Self { last_event: HashMap::new(), pruned_at: Utc::now() }
+    }
+
+    /// Load the last event time of visitor groups that aren't in memory
+    fn load(&mut self, conn: &Connection, visitor_group_ids: &[&str], since: DateTime<Utc>) -> DuckResult<()> {
+        if visitor_group_ids.is_empty() {
+            return Ok(());
+        }
+
+        let visitor_vars = repeat_vars(visitor_group_ids.len());
+        let mut params = ParamVec::new();
+        params.extend(visitor_group_ids);
+        params.push(since - chrono::Duration::hours(VISITOR_LOOKBACK_HOURS));
+
+        let mut stmt = conn.prepare(&format!(
+            "--sql
+            select visitor_group_id, max(created_at)
+            from events
+            where visitor_group_id in ({visitor_vars}) and created_at >= ?::timestamp
+            group by visitor_group_id;
+        "
+        ))?;
+        let rows = stmt.query_map(duckdb::params_from_iter(params), |row| Ok((row.get(0)?, row.get(1)?)))?;
+        for row in rows {
+            let (visitor_group_id, last_event) = row?;
+            self.last_event.insert(visitor_group_id, last_event);
+        }
+        Ok(())
+    }
+
+    /// Forget visitor groups without recent events, at most once an hour
+    fn prune(&mut self) {
+        let now = Utc::now();
+        if now - self.pruned_at < chrono::Duration::hours(1) {
+            return;
+        }
+
+        let cutoff = now - chrono::Duration::hours(VISITOR_LOOKBACK_HOURS);
+        self.last_event.retain(|_, last_event| *last_event >= cutoff);
+        self.pruned_at = now;
+    }
+}
+
 fn count_rows(conn: &Connection, sql: &str, params: impl duckdb::Params) -> DuckResult<u64> {
     conn.query_row(sql, params, |row| row.get(0))
 }
 
-fn update_event_times(conn: &Connection, from_time: DateTime<Utc>, entities: &[String]) -> DuckResult<()> {
-    if entities.is_empty() {
+/// Set `time_to_next_event` of events already in the database that were followed by a new event
+fn update_previous_events(
+    conn: &Connection,
+    previous_events: &[(&str, DateTime<Utc>, chrono::Duration)],
+) -> DuckResult<()> {
+    if previous_events.is_empty() {
         return Ok(());
     }
 
-    let entity_vars = repeat_vars(entities.len());
-    // this can probably be simplified, sadly the where clause can't contain window functions
-    let sql = format!("--sql
+    let values =
+        previous_events.iter().map(|_| "(?, ?::timestamp, to_microseconds(?::bigint))").collect::<Vec<_>>().join(", ");
+    let mut params = ParamVec::new();
+    for (visitor_group_id, created_at, interval) in previous_events {
+        params.push(*visitor_group_id);
+        params.push(*created_at);
+        params.push(interval.num_microseconds().unwrap_or(i64::MAX));
+    }
+
+    conn.execute(
+        &format!(
+            "--sql
+            update events
+                set time_to_next_event = v.time_to_next_event
+                from (values {values}) v(visitor_group_id, created_at, time_to_next_event)
+                where events.visitor_group_id = v.visitor_group_id and events.created_at = v.created_at;
+        "
+        ),
+        duckdb::params_from_iter(params),
+    )?;
+    Ok(())
+}
+
+/// Convert a session interval to a DuckDB interval, skipping negative ones
+fn interval_value(interval: chrono::Duration) -> Option<Value> {
+    if interval < chrono::Duration::zero() {
+        return None;
+    }
+    Some(Value::Interval { months: 0, days: 0, nanos: interval.num_nanoseconds().unwrap_or(i64::MAX) })
+}
+
+/// Recalculate the session intervals of the given visitor groups from their events after `from_time`
+///
+/// Only needed for events that arrive out of order. Previous events are looked for up to [`VISITOR_LOOKBACK_HOURS`]
+/// before `from_time`, like for events in order, so only recent events of the visitor groups are scanned.
+fn recalculate_event_times(conn: &Connection, visitor_group_ids: &[&str], from_time: DateTime<Utc>) -> DuckResult<()> {
+    if visitor_group_ids.is_empty() {
+        return Ok(());
+    }
+
+    let visitor_vars = repeat_vars(visitor_group_ids.len());
+    let sql = format!(
+        "--sql
         with
-            filtered_events as (
-                select *
-                from events
-                where entity_id in ({entity_vars}) and (created_at >= ?::timestamp or visitor_group_id in (
-                    select visitor_group_id
-                    from events
-                    where entity_id in ({entity_vars}) and created_at >= now()::timestamp - interval '24 hours' and created_at < ?::timestamp and time_to_next_event is null
-                ))
-            ),
             cte as (
                 select
                     visitor_group_id,
                     created_at,
                     created_at - lag(created_at) over (partition by visitor_group_id order by created_at) as time_from_last_event,
-                    lead(created_at) over (partition by visitor_group_id order by created_at) - created_at as time_to_next_event
-                from filtered_events
+                    lead(created_at) over (partition by visitor_group_id order by created_at) as next_created_at
+                from events
+                where visitor_group_id in ({visitor_vars}) and created_at >= ?::timestamp
             )
         update events
             set
-                time_from_last_event = cte.time_from_last_event,
-                time_to_next_event = cte.time_to_next_event
+                -- the last event before `from_time` only gets a new next event
+                time_from_last_event = case when cte.created_at >= ?::timestamp then cte.time_from_last_event else events.time_from_last_event end,
+                time_to_next_event = cte.next_created_at - cte.created_at
             from cte
-            where events.visitor_group_id = cte.visitor_group_id and events.created_at = cte.created_at;
-    ");
+            where
+                events.visitor_group_id = cte.visitor_group_id and events.created_at = cte.created_at and
+                events.created_at >= ?::timestamp and (cte.created_at >= ?::timestamp or cte.next_created_at >= ?::timestamp);
+    "
+    );
 
+    let lookback = from_time - chrono::Duration::hours(VISITOR_LOOKBACK_HOURS);
     let mut params = ParamVec::new();
-    params.extend(entities);
+    params.extend(visitor_group_ids);
+    params.push(lookback);
     params.push(from_time);
-    params.extend(entities);
+    params.push(lookback);
     params.push(from_time);
+    params.push(from_time);
+
     conn.execute(&sql, duckdb::params_from_iter(params))?;
     Ok(())
 }
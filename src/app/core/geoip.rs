@@ -1,4 +1,3 @@
-use std::io::{self, Read};
 use std::net::IpAddr;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
@@ -7,41 +6,77 @@ use std::time::Duration;
 
 use anyhow::{Context, Result, anyhow};
 use arc_swap::ArcSwapOption;
-use futures_lite::StreamExt;
-use md5::Digest;
-use tokio_tar::Archive;
-use tokio_util::io::StreamReader;
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 
-const BASE_URL: &str = "https://updates.maxmind.com";
-const METADATA_ENDPOINT: &str = "/geoip/updates/metadata?edition_id=";
-const DOWNLOAD_ENDPOINT: &str = "/geoip/databases/";
+mod source;
+use source::{DEFAULT_MAXMIND_BASE_URL, GeoIpSource, checksum_path};
+
+const DEFAULT_ASN_EDITION: &str = "GeoLite2-ASN";
 
 #[derive(Default)]
 pub struct LookupResult {
     pub city: Option<String>,
     pub country_code: Option<String>,
+    /// English name of the largest subdivision, e.g. a US state
+    pub region: Option<String>,
+    pub continent_code: Option<String>,
+    /// IANA time zone of the location, e.g. `Europe/Berlin`
+    pub time_zone: Option<String>,
+    /// Autonomous system number, only available if an ASN database is configured
+    pub asn: Option<u32>,
+    /// Autonomous system organization, e.g. `Amazon.com, Inc.`
+    pub asn_org: Option<String>,
 }
 
-pub struct LiwanGeoIP {
+/// Outcome of the last update check of a database
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case", tag = "status", content = "error")]
+pub enum GeoIpCheckResult {
+    Updated,
+    UpToDate,
+    Failed(String),
+}
+
+/// Current state of a GeoIP database
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GeoIpDatabaseStatus {
+    pub edition: String,
+    pub path: String,
+    /// Where updates are fetched from: `maxmind`, `url`, `directory` or `none`
+    pub source: String,
+    pub loaded: bool,
+    /// Database type from the file metadata, e.g. `GeoLite2-City`
+    pub database_type: Option<String>,
+    /// Build time from the file metadata
+    pub build_epoch: Option<DateTime<Utc>>,
+    pub last_reload: Option<DateTime<Utc>>,
+    /// Error of the last reload from disk, cleared once a reload succeeds
+    pub reload_error: Option<String>,
+    /// Time of the last update check, also of previous runs
+    pub last_check: Option<DateTime<Utc>>,
+    pub last_check_result: Option<GeoIpCheckResult>,
+    /// Whether an update is currently in progress
+    pub updating: bool,
+}
+
+/// A single `.mmdb` database file that is kept up to date
+struct GeoIpDatabase {
     reader: ArcSwapOption<maxminddb::Reader<Vec<u8>>>,
     downloading: AtomicBool,
-    geoip: crate::config::GeoIpConfig,
+    edition: String,
     path: PathBuf,
+    /// Where updates are fetched from, `None` if the file is managed externally
+    source: Option<GeoIpSource>,
+    last_reload: ArcSwapOption<DateTime<Utc>>,
+    reload_error: ArcSwapOption<String>,
+    last_check: ArcSwapOption<(DateTime<Utc>, GeoIpCheckResult)>,
 }
 
-impl LiwanGeoIP {
-    pub fn try_new(config: crate::config::Config) -> Result<Self> {
-        let geoip = config.geoip;
-        if geoip.maxmind_account_id.is_none() && geoip.maxmind_license_key.is_none() && geoip.maxmind_db_path.is_none()
-        {
-            tracing::trace!("GeoIP support disabled, skipping...");
-            return Ok(Self::noop());
-        }
-
-        let edition = &geoip.maxmind_edition;
-        let default_path = PathBuf::from(config.data_dir.clone()).join(format!("./geoip/{edition}.mmdb"));
-        let path = geoip.maxmind_db_path.as_ref().map_or(default_path, PathBuf::from);
-
+impl GeoIpDatabase {
+    fn open(edition: &str, path: PathBuf, source: Option<GeoIpSource>) -> Result<Self> {
         if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent)?;
         }
@@ -50,130 +85,334 @@ impl LiwanGeoIP {
             return Err(anyhow!("Invalid GeoIP database path file extension, expected '.mmdb'"));
         }
 
-        tracing::info!(database = geoip.maxmind_db_path, "GeoIP support enabled, loading database");
+        tracing::info!(edition, path = ?path, "Loading GeoIP database");
+        let reader = if path.exists() {
+            let reader = maxminddb::Reader::open_readfile(path.clone())
+                .with_context(|| format!("Failed to open GeoIP database {edition}"))?;
+            Some(reader.into())
+        } else {
+            None
+        };
+        let last_reload = reader.is_some().then(|| Utc::now().into());
+        let last_check = read_last_check(&path).map(Into::into);
 
-        let reader = path.exists().then(|| {
-            maxminddb::Reader::open_readfile(path.clone()).expect("Failed to open GeoIP database file").into()
-        });
+        Ok(Self {
+            reader: ArcSwapOption::new(reader),
+            downloading: Default::default(),
+            edition: edition.to_string(),
+            path,
+            source,
+            last_reload: ArcSwapOption::new(last_reload),
+            reload_error: ArcSwapOption::empty(),
+            last_check: ArcSwapOption::new(last_check),
+        })
+    }
 
-        Ok(Self { geoip, reader: ArcSwapOption::new(reader), path, downloading: Default::default() })
+    fn status(&self) -> GeoIpDatabaseStatus {
+        let reader = self.reader();
+        let last_check = self.last_check.load_full();
+
+        GeoIpDatabaseStatus {
+            edition: self.edition.clone(),
+            path: self.path.to_string_lossy().to_string(),
+            source: match self.source {
+                Some(GeoIpSource::MaxMind { .. }) => "maxmind",
+                Some(GeoIpSource::Url { .. }) => "url",
+                Some(GeoIpSource::Directory { .. }) => "directory",
+                None => "none",
+            }
+            .to_string(),
+            loaded: reader.is_some(),
+            database_type: reader.as_ref().map(|reader| reader.metadata().database_type.clone()),
+            build_epoch: reader
+                .as_ref()
+                .and_then(|reader| i64::try_from(reader.metadata().build_epoch).ok())
+                .and_then(|epoch| DateTime::from_timestamp(epoch, 0)),
+            last_reload: self.last_reload.load_full().map(|time| *time),
+            reload_error: self.reload_error.load_full().map(|error| error.to_string()),
+            last_check: last_check.as_ref().map(|check| check.0),
+            last_check_result: last_check.map(|check| check.1.clone()),
+            updating: self.downloading.load(Ordering::Acquire),
+        }
     }
 
-    fn noop() -> Self {
-        Self {
-            geoip: Default::default(),
-            reader: ArcSwapOption::new(None),
-            downloading: Default::default(),
-            path: PathBuf::new(),
+    fn reader(&self) -> Option<Arc<maxminddb::Reader<Vec<u8>>>> {
+        self.reader.load_full()
+    }
+
+    /// Check for updates and install the latest database if available
+    async fn check_for_updates(&self) -> Result<()> {
+        if self.downloading.swap(true, Ordering::Acquire) {
+            return Ok(());
+        }
+
+        let result = self.update().await;
+        if self.source.is_some() {
+            let check = match &result {
+                Ok(true) => GeoIpCheckResult::Updated,
+                Ok(false) => GeoIpCheckResult::UpToDate,
+                Err(e) => GeoIpCheckResult::Failed(format!("{e:#}")),
+            };
+            let last_check = (Utc::now(), check);
+            // persisted next to the database, so the `geoip` command can show checks of the running server
+            if let Err(e) = write_last_check(&self.path, &last_check) {
+                tracing::warn!(edition = %self.edition, error = ?e, "Failed to persist GeoIP update check");
+            }
+            self.last_check.store(Some(last_check.into()));
         }
+
+        self.downloading.store(false, Ordering::Release);
+        result.map(|_| ())
     }
 
-    /// Lookup an IP address in the loaded GeoIP database
-    pub fn lookup(&self, ip: &IpAddr) -> Result<LookupResult> {
-        let Some(reader) = &*self.reader.load() else {
-            return Ok(Default::default());
+    /// Fetch and install the latest database, returning whether it was updated
+    async fn update(&self) -> Result<bool> {
+        let Some(source) = &self.source else {
+            return Ok(false);
         };
 
-        let lookup = reader.lookup(*ip)?.decode::<maxminddb::geoip2::City>().context("failed to decode data")?;
-        let city = lookup.as_ref().and_then(|lookup| lookup.city.names.english.map(|v| v.to_string()));
-        let country_code = lookup.and_then(|lookup| lookup.country.iso_code.map(|v| v.to_string()));
-        Ok(LookupResult { city, country_code })
+        let edition = self.edition.as_str();
+        let Some(update) = source.fetch(edition, &self.path).await? else {
+            tracing::debug!(edition, "GeoIP database is up to date");
+            return Ok(false);
+        };
+
+        // make sure the new file is a valid database before replacing the current one
+        let reader = match maxminddb::Reader::open_readfile(&update.file) {
+            Ok(reader) => reader,
+            Err(e) => {
+                std::fs::remove_file(&update.file)?;
+                return Err(anyhow::Error::from(e).context(format!("Invalid GeoIP database {edition}")));
+            }
+        };
+
+        // move the new file to the correct path
+        std::fs::copy(&update.file, &self.path)?;
+        std::fs::remove_file(&update.file)?;
+        std::fs::write(checksum_path(&self.path), &update.checksum)?;
+        self.reader.store(Some(reader.into()));
+        self.last_reload.store(Some(Utc::now().into()));
+
+        let path = std::fs::canonicalize(&self.path)?;
+        tracing::info!(edition, path = ?path, "GeoIP database updated successfully");
+        Ok(true)
     }
 
-    /// Check for updates and download the latest database if available
-    pub async fn check_for_updates(&self) -> Result<()> {
-        if self.downloading.swap(true, Ordering::Acquire) {
+    /// Reload the database from disk (as long as no update is currently in progress and the file exists)
+    fn reload(&self) -> Result<()> {
+        if self.downloading.load(Ordering::Acquire) || !self.path.exists() {
             return Ok(());
         }
 
-        let maxmind_edition = &self.geoip.maxmind_edition;
-        let maxmind_account_id =
-            self.geoip.maxmind_account_id.as_ref().ok_or_else(|| anyhow!("MaxMind account ID not found"))?.to_string();
-        let maxmind_license_key =
-            self.geoip.maxmind_license_key.as_deref().ok_or_else(|| anyhow!("MaxMind license key not found"))?;
-
-        let db_exists = self.path.exists();
-        let db_md5 = if db_exists { file_md5(&self.path)? } else { String::new() };
-
-        let mut update = !db_exists;
-        if db_exists {
-            match get_latest_md5(maxmind_edition, &maxmind_account_id, maxmind_license_key).await {
-                Ok(latest_md5) => {
-                    if latest_md5 != db_md5 {
-                        tracing::info!("GeoIP database outdated, downloading...");
-                        update = true;
-                    }
-                }
+        match maxminddb::Reader::open_readfile(self.path.clone()) {
+            Ok(reader) => {
+                self.reader.store(Some(reader.into()));
+                self.last_reload.store(Some(Utc::now().into()));
+                self.reload_error.store(None);
+                Ok(())
+            }
+            Err(e) => {
+                let e = anyhow::Error::from(e).context(format!("Invalid GeoIP database {}", self.edition));
+                self.reload_error.store(Some(format!("{e:#}").into()));
+                Err(e)
+            }
+        }
+    }
+
+    /// File to watch for drop-in updates, if the database is sourced from a local directory
+    fn watched_source_file(&self) -> Option<PathBuf> {
+        match &self.source {
+            Some(GeoIpSource::Directory { dir, .. }) => source::directory_file(dir, &self.edition),
+            _ => None,
+        }
+    }
+}
+
+pub struct LiwanGeoIP {
+    city: Option<GeoIpDatabase>,
+    asn: Option<GeoIpDatabase>,
+}
+
+impl LiwanGeoIP {
+    pub fn try_new(config: crate::config::Config) -> Result<Self> {
+        let geoip = config.geoip;
+        let geoip_dir = PathBuf::from(config.data_dir.clone()).join("./geoip");
+
+        let credentials = match (&geoip.maxmind_account_id, &geoip.maxmind_license_key) {
+            (Some(account_id), Some(license_key)) => Some((account_id.to_string(), license_key.clone())),
+            _ => None,
+        };
+        let maxmind = (credentials.is_some() || geoip.maxmind_base_url.is_some()).then(|| GeoIpSource::MaxMind {
+            base_url: geoip.maxmind_base_url.clone().unwrap_or_else(|| DEFAULT_MAXMIND_BASE_URL.to_string()),
+            credentials,
+        });
+        // a per-database URL takes precedence over a shared directory, which takes precedence over MaxMind
+        let shared = geoip
+            .db_dir
+            .as_ref()
+            .map(|dir| GeoIpSource::Directory {
+                dir: PathBuf::from(dir),
+                allow_unverified: geoip.allow_unverified_downloads,
+            })
+            .or(maxmind);
+        let source_for = |url: &Option<String>, checksum_url: &Option<String>| match url {
+            Some(url) if checksum_url.is_none() && !geoip.allow_unverified_downloads => Err(anyhow!(
+                "GeoIP database URL {url} has no checksum URL, set one or enable `allow_unverified_downloads`"
+            )),
+            Some(url) => Ok(Some(GeoIpSource::Url { url: url.clone(), checksum_url: checksum_url.clone() })),
+            None => Ok(shared.clone()),
+        };
+
+        let city_source = source_for(&geoip.db_url, &geoip.db_checksum_url)?;
+        let city = if city_source.is_some() || geoip.maxmind_db_path.is_some() {
+            let edition = &geoip.maxmind_edition;
+            let default_path = geoip_dir.join(format!("{edition}.mmdb"));
+            let path = geoip.maxmind_db_path.as_ref().map_or(default_path, PathBuf::from);
+            Some(GeoIpDatabase::open(edition, path, city_source)?)
+        } else {
+            None
+        };
+
+        // the ASN database is only enabled if explicitly configured
+        let asn_source = match (&geoip.asn_db_url, &geoip.maxmind_asn_edition) {
+            (Some(_), _) => source_for(&geoip.asn_db_url, &geoip.asn_db_checksum_url)?,
+            (None, Some(_)) => shared.clone(),
+            (None, None) => None,
+        };
+        let asn = if asn_source.is_some() || geoip.maxmind_asn_db_path.is_some() {
+            let edition = geoip.maxmind_asn_edition.as_deref().unwrap_or(DEFAULT_ASN_EDITION);
+            let default_path = geoip_dir.join(format!("{edition}.mmdb"));
+            let path = geoip.maxmind_asn_db_path.as_ref().map_or(default_path, PathBuf::from);
+            // a broken ASN database only disables ASN lookups
+            match GeoIpDatabase::open(edition, path, asn_source) {
+                Ok(db) => Some(db),
                 Err(e) => {
-                    tracing::warn!(error = ?e, "Failed to get latest MaxMind database MD5 hash, skipping update");
+                    tracing::error!(edition, error = ?e, "Failed to load GeoIP ASN database, ASN lookups are disabled");
+                    None
                 }
-            };
+            }
+        } else if geoip.maxmind_asn_edition.is_some() {
+            return Err(anyhow!("GeoIP ASN edition is set, but no database source or path is configured"));
         } else {
-            tracing::info!("GeoIP database doesn't exist, attempting to download...");
+            None
+        };
+
+        if city.is_none() && asn.is_none() {
+            tracing::trace!("GeoIP support disabled, skipping...");
         }
 
-        if update {
-            let file = match download_maxmind_db(maxmind_edition, &maxmind_account_id, maxmind_license_key).await {
-                Ok(file) => file,
-                Err(e) => {
-                    tracing::warn!(error = ?e, "Failed to download GeoIP database, skipping update");
-                    self.downloading.store(false, Ordering::Release);
-                    return Ok(());
-                }
-            };
+        Ok(Self { city, asn })
+    }
 
-            // close the current reader to free up the file
-            self.reader.swap(None);
+    fn databases(&self) -> impl Iterator<Item = &GeoIpDatabase> {
+        self.city.iter().chain(self.asn.iter())
+    }
+
+    /// Lookup an IP address in the loaded GeoIP databases
+    pub fn lookup(&self, ip: &IpAddr) -> Result<LookupResult> {
+        let mut result = LookupResult::default();
 
-            // move the downloaded file to the correct path
-            std::fs::copy(&file, &self.path)?;
-            std::fs::remove_file(file)?;
+        if let Some(reader) = self.city.as_ref().and_then(GeoIpDatabase::reader)
+            && let Some(lookup) =
+                reader.lookup(*ip)?.decode::<maxminddb::geoip2::City>().context("failed to decode data")?
+        {
+            result.city = lookup.city.names.english.map(|v| v.to_string());
+            result.country_code = lookup.country.iso_code.map(|v| v.to_string());
+            result.region =
+                lookup.subdivisions.first().and_then(|subdivision| subdivision.names.english).map(|v| v.to_string());
+            result.continent_code = lookup.continent.code.map(|v| v.to_string());
+            result.time_zone = lookup.location.time_zone.map(|v| v.to_string());
+        }
 
-            let path = std::fs::canonicalize(&self.path)?;
-            tracing::info!(path = ?path, "GeoIP database updated successfully");
+        if let Some(reader) = self.asn.as_ref().and_then(GeoIpDatabase::reader)
+            && let Some(lookup) =
+                reader.lookup(*ip)?.decode::<maxminddb::geoip2::Asn>().context("failed to decode data")?
+        {
+            result.asn = lookup.autonomous_system_number;
+            result.asn_org = lookup.autonomous_system_organization.map(|v| v.to_string());
         }
 
-        self.downloading.store(false, Ordering::Release);
-        Ok(())
+        Ok(result)
     }
 
-    /// Reload the database from disk (as long as no update is currently in progress)
-    pub fn reload(&self) -> Result<()> {
-        if self.downloading.load(Ordering::Acquire) {
-            return Ok(());
+    /// Current state of all configured databases
+    pub fn status(&self) -> Vec<GeoIpDatabaseStatus> {
+        self.databases().map(GeoIpDatabase::status).collect()
+    }
+
+    /// Whether an update of any database is currently in progress
+    pub fn is_updating(&self) -> bool {
+        self.databases().any(|db| db.downloading.load(Ordering::Acquire))
+    }
+
+    /// Check for updates and install the latest databases if available
+    pub async fn check_for_updates(&self) -> Result<()> {
+        let mut result = Ok(());
+        for db in self.databases() {
+            if let Err(e) = db.check_for_updates().await {
+                tracing::warn!(edition = %db.edition, error = ?e, "Failed to update GeoIP database");
+                result = Err(e);
+            }
         }
+        result
+    }
 
-        let reader = maxminddb::Reader::open_readfile(self.path.clone())?;
-        self.reader.store(Some(reader.into()));
-        Ok(())
+    /// Reload the databases from disk (as long as no update is currently in progress)
+    ///
+    /// Databases are reloaded independently, failures are reported in the returned status of each database.
+    pub fn reload(&self) -> Vec<GeoIpDatabaseStatus> {
+        for db in self.databases() {
+            if let Err(e) = db.reload() {
+                tracing::warn!(edition = %db.edition, error = ?e, "Failed to reload GeoIP database");
+            }
+        }
+        self.status()
     }
 }
 
-/// Keep the GeoIP database refreshed and reload it after local file changes
+/// Keep the GeoIP databases refreshed and reload them after local file changes
 pub async fn keep_updated(geoip: Arc<LiwanGeoIP>) {
-    let path: PathBuf = geoip.path.clone();
-    let mut last_meta = get_file_meta(&path);
+    let databases: Vec<&GeoIpDatabase> = geoip.databases().collect();
+    if databases.is_empty() {
+        return;
+    }
+
+    let mut last_meta: Vec<_> = databases.iter().map(|db| get_file_meta(&db.path)).collect();
+    let mut last_source_meta: Vec<_> =
+        databases.iter().map(|db| db.watched_source_file().and_then(|path| get_file_meta(&path))).collect();
 
     let mut file_interval = tokio::time::interval(Duration::from_secs(60));
     let mut daily_interval = tokio::time::interval(Duration::from_secs(24 * 60 * 60));
-    let enable_updates = geoip.geoip.maxmind_account_id.is_some() && geoip.geoip.maxmind_license_key.is_some();
 
     loop {
         tokio::select! {
             _ = file_interval.tick() => {
                 // just a simple polling based file watcher so we don't need to add a bunch of dependencies
-                let meta = get_file_meta(&path);
-                if meta != last_meta {
-                    if let Err(e) = geoip.reload() {
-                        tracing::error!(error = ?e, "Failed to reload GeoIP database");
+                for (i, db) in databases.iter().enumerate() {
+                    let source_meta = db.watched_source_file().and_then(|path| get_file_meta(&path));
+                    if source_meta != last_source_meta[i] {
+                        tracing::info!(edition = %db.edition, "GeoIP database source file changed, updating...");
+                        if let Err(e) = db.check_for_updates().await {
+                            tracing::error!(edition = %db.edition, error = ?e, "Failed to update GeoIP database");
+                        }
+                        last_source_meta[i] = source_meta;
+                    }
+
+                    let meta = get_file_meta(&db.path);
+                    if meta == last_meta[i] {
+                        continue;
+                    }
+
+                    if let Err(e) = db.reload() {
+                        tracing::error!(edition = %db.edition, error = ?e, "Failed to reload GeoIP database");
                     } else {
-                        tracing::info!("GeoIP database reloaded after file change");
-                        last_meta = meta;
+                        tracing::info!(edition = %db.edition, "GeoIP database reloaded after file change");
+                        last_meta[i] = meta;
                     }
                 }
             }
             _ = daily_interval.tick()  => {
-                if enable_updates && let Err(e) = geoip.check_for_updates().await {
+                if let Err(e) = geoip.check_for_updates().await {
                     tracing::error!(error = ?e, "Failed to check for GeoIP database updates");
                 }
             }
@@ -181,6 +420,23 @@ pub async fn keep_updated(geoip: Arc<LiwanGeoIP>) {
     }
 }
 
+/// Path of the file storing the time and result of the last update check of a database
+fn last_check_path(db_path: &Path) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push(".last-check");
+    PathBuf::from(path)
+}
+
+fn read_last_check(db_path: &Path) -> Option<(DateTime<Utc>, GeoIpCheckResult)> {
+    let file = std::fs::read_to_string(last_check_path(db_path)).ok()?;
+    serde_json::from_str(&file).ok()
+}
+
+fn write_last_check(db_path: &Path, last_check: &(DateTime<Utc>, GeoIpCheckResult)) -> Result<()> {
+    std::fs::write(last_check_path(db_path), serde_json::to_string(last_check)?)?;
+    Ok(())
+}
+
 fn get_file_meta(path: &PathBuf) -> Option<(u64, u64, u64, i64)> {
     let md = std::fs::metadata(path).ok()?;
 
@@ -200,71 +456,69 @@ fn get_file_meta(path: &PathBuf) -> Option<(u64, u64, u64, i64)> {
     Some((0, 0, md.len(), md.modified().ok()?.elapsed().ok()?.as_secs() as i64))
 }
 
-async fn get_latest_md5(edition: &str, account_id: &str, license_key: &str) -> Result<String> {
-    let url = format!("{BASE_URL}{METADATA_ENDPOINT}{edition}");
-    let client = reqwest::Client::new();
-    let response = client
-        .get(&url)
-        .basic_auth(account_id, Some(license_key))
-        .send()
-        .await?
-        .json::<ahash::HashMap<String, Vec<ahash::HashMap<String, String>>>>()
-        .await?;
-
-    Ok(response
-        .get("databases")
-        .ok_or_else(|| anyhow!("No databases found"))?
-        .first()
-        .ok_or_else(|| anyhow!("MD5 hash not found"))?
-        .get("md5")
-        .ok_or_else(|| anyhow!("MD5 hash not found"))?
-        .clone())
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-fn file_md5(path: &Path) -> Result<String> {
-    let file = std::fs::File::open(path)?;
-    let mut reader = std::io::BufReader::new(file);
-    let mut hasher = md5::Md5::new();
+    #[test]
+    fn url_sources_require_a_checksum_url() {
+        let mut config = crate::config::Config::default();
+        config.geoip.db_url = Some("https://example.com/dbip-city-lite.mmdb.gz".to_string());
 
-    let mut buffer = [0u8; 8192];
-    loop {
-        let n = reader.read(&mut buffer)?;
-        if n == 0 {
-            break;
-        }
-        hasher.update(&buffer[..n]);
+        let err = LiwanGeoIP::try_new(config).err().expect("unverified download should be rejected");
+        assert!(err.to_string().contains("has no checksum URL"));
     }
 
-    Ok(hex::encode(hasher.finalize()))
-}
+    #[test]
+    fn broken_databases_are_rejected_or_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let broken = dir.path().join("broken.mmdb");
+        std::fs::write(&broken, b"not a database").unwrap();
 
-async fn download_maxmind_db(edition: &str, account_id: &str, license_key: &str) -> Result<PathBuf> {
-    let url = format!("{BASE_URL}{DOWNLOAD_ENDPOINT}{edition}/download?suffix=tar.gz");
+        let mut config = crate::config::Config::default();
+        config.data_dir = dir.path().to_string_lossy().to_string();
+        config.geoip.maxmind_asn_db_path = Some(broken.to_string_lossy().to_string());
+        let geoip = LiwanGeoIP::try_new(config.clone()).expect("broken ASN database should be skipped");
+        assert!(geoip.asn.is_none());
 
-    let client = reqwest::Client::new();
-    let response = client.get(url).basic_auth(account_id, Some(license_key)).send().await?.error_for_status()?;
-    let stream = response.bytes_stream().map(|b| b.map_err(io::Error::other));
-    let stream = StreamReader::new(stream);
-    let stream = async_compression::tokio::bufread::GzipDecoder::new(stream);
-    let mut archive = Archive::new(stream);
-    let mut entries = archive.entries()?;
+        config.geoip.maxmind_db_path = Some(broken.to_string_lossy().to_string());
+        assert!(LiwanGeoIP::try_new(config).is_err());
+    }
 
-    let folder = std::env::temp_dir().join("liwan-geoip");
-    std::fs::create_dir_all(&folder).context("Failed to create temp directory")?;
+    #[test]
+    fn databases_are_reloaded_independently() {
+        let dir = tempfile::tempdir().unwrap();
+        let city = dir.path().join("city.mmdb");
+
+        let mut config = crate::config::Config::default();
+        config.data_dir = dir.path().to_string_lossy().to_string();
+        config.geoip.maxmind_db_path = Some(city.to_string_lossy().to_string());
+        config.geoip.maxmind_asn_db_path = Some(dir.path().join("asn.mmdb").to_string_lossy().to_string());
+        let geoip = LiwanGeoIP::try_new(config).expect("failed to create GeoIP");
+
+        // the missing ASN database is skipped, the broken city database reports its error
+        std::fs::write(&city, b"not a database").unwrap();
+        let status = geoip.reload();
+        assert_eq!(status.len(), 2);
+        assert!(status[0].reload_error.as_deref().is_some_and(|error| error.contains("Invalid GeoIP database")));
+        assert!(!status[1].loaded && status[1].reload_error.is_none());
+    }
 
-    loop {
-        let mut entry = entries.next().await.context("No entries found")?.context("Failed to read entry")?;
-
-        let entry_path = entry.path()?;
-        if entry_path.extension().is_some_and(|ext| ext == "mmdb") {
-            entry.set_allow_external_symlinks(false);
-            entry.set_preserve_permissions(false);
-
-            return entry
-                .unpack_in(folder)
-                .await
-                .context("Failed to unpack entry")?
-                .ok_or_else(|| anyhow!("Failed to unpack entry"));
-        }
+    #[tokio::test]
+    async fn update_checks_are_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = crate::config::Config::default();
+        config.data_dir = dir.path().to_string_lossy().to_string();
+        config.geoip.db_dir = Some(dir.path().join("drop-in").to_string_lossy().to_string());
+        config.geoip.maxmind_edition = "GeoLite2-City".to_string();
+
+        let geoip = LiwanGeoIP::try_new(config.clone()).expect("failed to create GeoIP");
+        assert!(geoip.status()[0].last_check.is_none());
+        geoip.check_for_updates().await.expect("failed to check for updates");
+
+        // a new instance, like the one of the `geoip` command, sees the check of the previous one
+        let status = LiwanGeoIP::try_new(config).expect("failed to create GeoIP").status();
+        assert_eq!(status[0].last_check, geoip.status()[0].last_check);
+        assert!(matches!(status[0].last_check_result, Some(GeoIpCheckResult::UpToDate)));
     }
 }
@@ -0,0 +1,388 @@
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow, bail};
+use futures_lite::StreamExt;
+use md5::Digest;
+use tokio::io::{AsyncBufRead, BufReader};
+use tokio_tar::Archive;
+use tokio_util::io::StreamReader;
+
+pub const DEFAULT_MAXMIND_BASE_URL: &str = "https://updates.maxmind.com";
+const METADATA_ENDPOINT: &str = "/geoip/updates/metadata?edition_id=";
+const DOWNLOAD_ENDPOINT: &str = "/geoip/databases/";
+
+/// Where updated database files are fetched from
+#[derive(Debug, Clone)]
+pub enum GeoIpSource {
+    /// MaxMind's update API, or a mirror with the same layout
+    MaxMind { base_url: String, credentials: Option<(String, String)> },
+    /// A plain `.mmdb`, `.mmdb.gz` or `.tar.gz` download, e.g. DB-IP Lite or IPinfo
+    ///
+    /// `checksum_url` is only `None` if unverified downloads were explicitly allowed.
+    Url { url: String, checksum_url: Option<String> },
+    /// A local directory that `{edition}.mmdb` or `{edition}.mmdb.gz` files are dropped into
+    ///
+    /// Files need a `.sha256` or `.md5` checksum file next to them, unless unverified files were explicitly allowed.
+    Directory { dir: PathBuf, allow_unverified: bool },
+}
+
+/// A fetched and verified database file, ready to be installed
+pub struct Update {
+    pub file: PathBuf,
+    /// Checksum of the source file, stored next to the database to skip unchanged updates
+    pub checksum: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileFormat {
+    Mmdb,
+    Gzip,
+    TarGzip,
+}
+
+impl FileFormat {
+    fn from_name(name: &str) -> Self {
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Self::TarGzip
+        } else if name.ends_with(".gz") {
+            Self::Gzip
+        } else {
+            Self::Mmdb
+        }
+    }
+}
+
+impl GeoIpSource {
+    /// Fetch the latest version of a database, unless it matches the one installed at `db_path`
+    pub async fn fetch(&self, edition: &str, db_path: &Path) -> Result<Option<Update>> {
+        match self {
+            Self::MaxMind { base_url, credentials } => {
+                fetch_maxmind(base_url, credentials.as_ref(), edition, db_path).await
+            }
+            Self::Url { url, checksum_url } => fetch_url(url, checksum_url.as_deref(), edition, db_path).await,
+            Self::Directory { dir, allow_unverified } => {
+                fetch_directory(dir, *allow_unverified, edition, db_path).await
+            }
+        }
+    }
+}
+
+/// Path of the file storing the checksum of the installed database's source file
+pub fn checksum_path(db_path: &Path) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push(".checksum");
+    PathBuf::from(path)
+}
+
+async fn fetch_maxmind(
+    base_url: &str,
+    credentials: Option<&(String, String)>,
+    edition: &str,
+    db_path: &Path,
+) -> Result<Option<Update>> {
+    let client = reqwest::Client::new();
+    let with_auth = |request: reqwest::RequestBuilder| match credentials {
+        Some((account_id, license_key)) => request.basic_auth(account_id, Some(license_key)),
+        None => request,
+    };
+    let base_url = base_url.trim_end_matches('/');
+
+    // the metadata is always fetched, so first downloads are verified too
+    let url = format!("{base_url}{METADATA_ENDPOINT}{edition}");
+    let response = with_auth(client.get(&url))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<ahash::HashMap<String, Vec<ahash::HashMap<String, String>>>>()
+        .await?;
+
+    let latest_md5 = response
+        .get("databases")
+        .ok_or_else(|| anyhow!("No databases found"))?
+        .first()
+        .and_then(|database| database.get("md5"))
+        .ok_or_else(|| anyhow!("MD5 hash not found"))?
+        .to_ascii_lowercase();
+
+    if db_path.exists() {
+        if latest_md5 == file_digest(db_path, Checksum::Md5)? {
+            return Ok(None);
+        }
+        tracing::info!(edition, "GeoIP database outdated, downloading...");
+    } else {
+        tracing::info!(edition, "GeoIP database doesn't exist, attempting to download...");
+    }
+
+    let url = format!("{base_url}{DOWNLOAD_ENDPOINT}{edition}/download?suffix=tar.gz");
+    let response = with_auth(client.get(url)).send().await?.error_for_status()?;
+    let stream = StreamReader::new(response.bytes_stream().map(|b| b.map_err(io::Error::other)));
+    let file = unpack(stream, FileFormat::TarGzip, edition).await?;
+
+    // the metadata hash is the hash of the database file itself, not of the archive
+    let checksum = file_digest(&file, Checksum::Md5)?;
+    if latest_md5 != checksum {
+        std::fs::remove_file(&file)?;
+        bail!("Checksum mismatch for downloaded GeoIP database {edition}");
+    }
+
+    Ok(Some(Update { file, checksum }))
+}
+
+async fn fetch_url(url: &str, checksum_url: Option<&str>, edition: &str, db_path: &Path) -> Result<Option<Update>> {
+    let client = reqwest::Client::new();
+    let installed = installed_checksum(db_path);
+
+    let expected = match checksum_url {
+        Some(checksum_url) => {
+            let response = client.get(checksum_url).send().await?.error_for_status()?.text().await?;
+            Some(parse_checksum(&response).context("Invalid GeoIP database checksum file")?)
+        }
+        None => {
+            tracing::warn!(edition, "Unverified GeoIP database downloads are allowed, skipping checksum verification");
+            None
+        }
+    };
+
+    if expected.is_some() && expected == installed {
+        return Ok(None);
+    }
+
+    // download the source file as is first, since the checksum covers the compressed file
+    let response = client.get(url).send().await?.error_for_status()?;
+    let stream = StreamReader::new(response.bytes_stream().map(|b| b.map_err(io::Error::other)));
+    let download = temp_dir()?.join(format!("{edition}.download"));
+    let mut file = tokio::fs::File::create(&download).await?;
+    tokio::io::copy(&mut BufReader::new(stream), &mut file).await?;
+    drop(file);
+
+    let checksum = match verify_checksum(&download, expected.as_deref()) {
+        Ok(checksum) => checksum,
+        Err(e) => {
+            std::fs::remove_file(&download)?;
+            return Err(e.context(format!("Failed to verify GeoIP database {edition}")));
+        }
+    };
+
+    if installed.as_deref() == Some(checksum.as_str()) {
+        std::fs::remove_file(&download)?;
+        return Ok(None);
+    }
+
+    let name = url::Url::parse(url).map(|url| url.path().to_string()).unwrap_or_default();
+    let source = tokio::fs::File::open(&download).await?;
+    let file = unpack(BufReader::new(source), FileFormat::from_name(&name), edition).await;
+    std::fs::remove_file(&download)?;
+    Ok(Some(Update { file: file?, checksum }))
+}
+
+/// Database file in a drop-in directory, either `{edition}.mmdb` or `{edition}.mmdb.gz`
+pub fn directory_file(dir: &Path, edition: &str) -> Option<PathBuf> {
+    [format!("{edition}.mmdb"), format!("{edition}.mmdb.gz")]
+        .into_iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.exists())
+}
+
+async fn fetch_directory(dir: &Path, allow_unverified: bool, edition: &str, db_path: &Path) -> Result<Option<Update>> {
+    let Some(source) = directory_file(dir, edition) else {
+        return Ok(None);
+    };
+
+    let expected = ["sha256", "md5"].into_iter().find_map(|extension| {
+        let mut path = source.as_os_str().to_owned();
+        path.push(format!(".{extension}"));
+        std::fs::read_to_string(path).ok()
+    });
+    let expected = match expected {
+        Some(checksum) => Some(parse_checksum(&checksum).context("Invalid GeoIP database checksum file")?),
+        None if allow_unverified => {
+            tracing::warn!(edition, "Unverified GeoIP database files are allowed, skipping checksum verification");
+            None
+        }
+        None => bail!(
+            "GeoIP database {} has no .sha256 or .md5 checksum file, add one or enable `allow_unverified_downloads`",
+            source.display()
+        ),
+    };
+
+    let checksum = verify_checksum(&source, expected.as_deref())
+        .with_context(|| format!("Failed to verify GeoIP database {}", source.display()))?;
+    if installed_checksum(db_path).as_deref() == Some(checksum.as_str()) {
+        return Ok(None);
+    }
+
+    let name = source.to_string_lossy().to_string();
+    let file = tokio::fs::File::open(&source).await?;
+    let file = unpack(BufReader::new(file), FileFormat::from_name(&name), edition).await?;
+    Ok(Some(Update { file, checksum }))
+}
+
+/// Unpack a database file into the temp directory
+async fn unpack(reader: impl AsyncBufRead + Unpin, format: FileFormat, edition: &str) -> Result<PathBuf> {
+    let folder = temp_dir()?;
+    let target = folder.join(format!("{edition}.mmdb"));
+
+    match format {
+        FileFormat::Mmdb => {
+            let mut reader = reader;
+            let mut file = tokio::fs::File::create(&target).await?;
+            tokio::io::copy(&mut reader, &mut file).await?;
+        }
+        FileFormat::Gzip => {
+            let mut reader = async_compression::tokio::bufread::GzipDecoder::new(reader);
+            let mut file = tokio::fs::File::create(&target).await?;
+            tokio::io::copy(&mut reader, &mut file).await?;
+        }
+        FileFormat::TarGzip => {
+            let stream = async_compression::tokio::bufread::GzipDecoder::new(reader);
+            let mut archive = Archive::new(stream);
+            let mut entries = archive.entries()?;
+
+            loop {
+                let mut entry = entries.next().await.context("No entries found")?.context("Failed to read entry")?;
+
+                let entry_path = entry.path()?;
+                if entry_path.extension().is_some_and(|ext| ext == "mmdb") {
+                    entry.set_allow_external_symlinks(false);
+                    entry.set_preserve_permissions(false);
+
+                    let file = entry
+                        .unpack_in(&folder)
+                        .await
+                        .context("Failed to unpack entry")?
+                        .ok_or_else(|| anyhow!("Failed to unpack entry"))?;
+                    std::fs::rename(file, &target)?;
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(target)
+}
+
+fn temp_dir() -> Result<PathBuf> {
+    let folder = std::env::temp_dir().join("liwan-geoip");
+    std::fs::create_dir_all(&folder).context("Failed to create temp directory")?;
+    Ok(folder)
+}
+
+fn installed_checksum(db_path: &Path) -> Option<String> {
+    std::fs::read_to_string(checksum_path(db_path)).ok().map(|checksum| checksum.trim().to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Checksum {
+    Md5,
+    Sha256,
+}
+
+/// Parse a checksum file, either just the hex digest or in `sha256sum`/`md5sum` format
+fn parse_checksum(content: &str) -> Result<String> {
+    let checksum = content.split_whitespace().next().unwrap_or_default().to_ascii_lowercase();
+    if !checksum.bytes().all(|b| b.is_ascii_hexdigit()) || checksum_kind(&checksum).is_none() {
+        bail!("expected an MD5 or SHA-256 hex digest");
+    }
+    Ok(checksum)
+}
+
+fn checksum_kind(checksum: &str) -> Option<Checksum> {
+    match checksum.len() {
+        32 => Some(Checksum::Md5),
+        64 => Some(Checksum::Sha256),
+        _ => None,
+    }
+}
+
+/// Verify a file against an expected checksum, returning the file's checksum
+///
+/// Without an expected checksum, the SHA-256 digest of the file is returned.
+fn verify_checksum(path: &Path, expected: Option<&str>) -> Result<String> {
+    let kind = expected.and_then(checksum_kind).unwrap_or(Checksum::Sha256);
+    let checksum = file_digest(path, kind)?;
+    if let Some(expected) = expected
+        && expected != checksum
+    {
+        bail!("checksum mismatch, expected {expected} but got {checksum}");
+    }
+    Ok(checksum)
+}
+
+fn file_digest(path: &Path, kind: Checksum) -> Result<String> {
+    match kind {
+        Checksum::Md5 => hash_file::<md5::Md5>(path),
+        Checksum::Sha256 => hash_file::<sha2::Sha256>(path),
+    }
+}
+
+fn hash_file<D: Digest>(path: &Path) -> Result<String> {
+    let file = std::fs::File::open(path)?;
+    let mut reader = std::io::BufReader::new(file);
+    let mut hasher = D::new();
+
+    let mut buffer = [0u8; 8192];
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_checksum() {
+        let sha256 = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        assert_eq!(parse_checksum(sha256).unwrap(), sha256);
+        assert_eq!(parse_checksum(&format!("{}  dbip-city-lite.mmdb.gz\n", sha256.to_uppercase())).unwrap(), sha256);
+        assert_eq!(parse_checksum("d41d8cd98f00b204e9800998ecf8427e").unwrap(), "d41d8cd98f00b204e9800998ecf8427e");
+        assert!(parse_checksum("not a checksum").is_err());
+        assert!(parse_checksum("").is_err());
+    }
+
+    #[test]
+    fn test_verify_checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("empty.mmdb");
+        std::fs::write(&path, b"").unwrap();
+
+        let sha256 = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        assert_eq!(verify_checksum(&path, None).unwrap(), sha256);
+        assert_eq!(verify_checksum(&path, Some(sha256)).unwrap(), sha256);
+        assert_eq!(
+            verify_checksum(&path, Some("d41d8cd98f00b204e9800998ecf8427e")).unwrap(),
+            "d41d8cd98f00b204e9800998ecf8427e"
+        );
+        assert!(verify_checksum(&path, Some("00000000000000000000000000000000")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_directory_requires_checksum_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("Test-Directory-City.mmdb"), b"").unwrap();
+        let db_path = dir.path().join("installed.mmdb");
+
+        let result = fetch_directory(dir.path(), false, "Test-Directory-City", &db_path).await;
+        assert!(result.is_err(), "files without a checksum file should be rejected");
+
+        let sha256 = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        std::fs::write(dir.path().join("Test-Directory-City.mmdb.sha256"), sha256).unwrap();
+        let update = fetch_directory(dir.path(), false, "Test-Directory-City", &db_path).await.unwrap().unwrap();
+        assert_eq!(update.checksum, sha256);
+        std::fs::remove_file(update.file).unwrap();
+    }
+
+    #[test]
+    fn test_file_format() {
+        assert_eq!(FileFormat::from_name("/GeoLite2-City.tar.gz"), FileFormat::TarGzip);
+        assert_eq!(FileFormat::from_name("/dbip-city-lite-2024-05.mmdb.gz"), FileFormat::Gzip);
+        assert_eq!(FileFormat::from_name("/country_asn.mmdb"), FileFormat::Mmdb);
+    }
+}
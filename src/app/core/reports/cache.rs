@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use chrono::Utc;
+use quick_cache::sync::Cache;
+
+use super::{
+    DateRange, Dimension, DimensionFilter, DimensionTableOptions, GraphInterval, JourneyOptions, Metric, MoversOptions,
+    ReportBreakdownGraph, ReportGraph, ReportHeatmap, ReportJourney, ReportMovers, ReportPivot, ReportQuery,
+    ReportStats, ReportTable, SessionTimeout, WeekStart,
+};
+use crate::config::ReportCacheConfig;
+
+/// Ranges that ended this recently can still receive queued events, so they are treated like ranges touching now
+const LIVE_RANGE_MARGIN: chrono::Duration = chrono::Duration::minutes(5);
+
+/// Report type and the options that only apply to it
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub enum ReportKind {
+    Stats,
+    Graph {
+        metric: Metric,
+        interval: GraphInterval,
+        timezone: Option<String>,
+        week_start: WeekStart,
+        /// Range the buckets were shifted from, set for comparison graphs
+        aligned_to: Option<DateRange>,
+    },
+    Dimension {
+        metric: Metric,
+        dimension: Dimension,
+        options: DimensionTableOptions,
+    },
+    Breakdown {
+        metric: Metric,
+        dimension: Dimension,
+        limit: usize,
+        interval: GraphInterval,
+        timezone: Option<String>,
+        week_start: WeekStart,
+    },
+    Pivot {
+        metric: Metric,
+        rows: Dimension,
+        columns: Dimension,
+        limit: usize,
+    },
+    Heatmap {
+        metric: Metric,
+        timezone: String,
+    },
+    Movers {
+        metric: Metric,
+        dimension: Dimension,
+        comparison_range: DateRange,
+        options: MoversOptions,
+    },
+    Journey(JourneyOptions),
+}
+
+/// Everything a cached report result depends on
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct ReportCacheKey {
+    project_id: String,
+    entities: Vec<String>,
+    event: String,
+    range: DateRange,
+    filters: Vec<DimensionFilter>,
+    session_timeout: SessionTimeout,
+    approximate: bool,
+    shared_visitor_groups: bool,
+    kind: ReportKind,
+}
+
+impl ReportCacheKey {
+    pub fn new(project_id: &str, report: &ReportQuery, kind: ReportKind) -> Self {
+        Self {
+            project_id: project_id.to_string(),
+            entities: report.entities.to_vec(),
+            event: report.event.to_string(),
+            range: report.range.clone(),
+            filters: report.filters.to_vec(),
+            session_timeout: report.session_timeout,
+            approximate: report.approximate,
+            shared_visitor_groups: report.shared_visitor_groups,
+            kind,
+        }
+    }
+
+    /// The report query the key was created from
+    pub fn query(&self) -> ReportQuery<'_> {
+        ReportQuery {
+            entities: &self.entities,
+            event: &self.event,
+            range: &self.range,
+            filters: &self.filters,
+            session_timeout: self.session_timeout,
+            approximate: self.approximate,
+            shared_visitor_groups: self.shared_visitor_groups,
+        }
+    }
+}
+
+/// A cached report result
+#[derive(Debug, Clone)]
+pub enum CachedReport {
+    Stats(ReportStats),
+    Graph(ReportGraph),
+    Table(ReportTable),
+    Breakdown(ReportBreakdownGraph),
+    Pivot(ReportPivot),
+    Heatmap(ReportHeatmap),
+    Movers(ReportMovers),
+    Journey(ReportJourney),
+}
+
+/// Report results that can be stored in the [`ReportCache`]
+pub trait CacheableReport: Clone {
+    fn into_cached(self) -> CachedReport;
+    fn from_cached(cached: &CachedReport) -> Option<Self>;
+}
+
+macro_rules! impl_cacheable_report {
+    ($($report:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl CacheableReport for $report {
+                fn into_cached(self) -> CachedReport {
+                    CachedReport::$variant(self)
+                }
+
+                fn from_cached(cached: &CachedReport) -> Option<Self> {
+                    match cached {
+                        CachedReport::$variant(report) => Some(report.clone()),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_cacheable_report! {
+    ReportStats => Stats,
+    ReportGraph => Graph,
+    ReportTable => Table,
+    ReportBreakdownGraph => Breakdown,
+    ReportPivot => Pivot,
+    ReportHeatmap => Heatmap,
+    ReportMovers => Movers,
+    ReportJourney => Journey,
+}
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    report: CachedReport,
+    inserted_at: Instant,
+    /// Entity generation the report was computed at, only set for ranges touching now
+    generation: Option<u64>,
+}
+
+/// Cache for report results
+///
+/// Reports of ranges entirely in the past stay cached until they are evicted. Reports of ranges touching now
+/// expire after the configured TTL, or as soon as new events of one of their entities are written.
+pub struct ReportCache {
+    cache: Option<Cache<ReportCacheKey, CacheEntry>>,
+    ttl: Duration,
+    /// Incremented whenever events of an entity are written
+    generations: RwLock<HashMap<String, u64>>,
+}
+
+impl ReportCache {
+    pub fn new(config: &ReportCacheConfig) -> Self {
+        Self {
+            cache: (config.capacity > 0).then(|| Cache::new(config.capacity)),
+            ttl: Duration::from_secs(config.ttl_secs),
+            generations: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn generation(&self, entities: &[String]) -> u64 {
+        let generations = self.generations.read().expect("report cache generations poisoned");
+        entities.iter().filter_map(|entity_id| generations.get(entity_id)).sum()
+    }
+
+    /// Invalidate cached reports of ranges touching now for the given entities
+    pub fn invalidate_entities<'a>(&self, entities: impl IntoIterator<Item = &'a str>) {
+        if self.cache.is_none() {
+            return;
+        }
+
+        let mut generations = self.generations.write().expect("report cache generations poisoned");
+        for entity_id in entities {
+            match generations.get_mut(entity_id) {
+                Some(generation) => *generation += 1,
+                None => {
+                    generations.insert(entity_id.to_string(), 1);
+                }
+            }
+        }
+    }
+
+    /// Remove all cached reports, e.g. after events were pruned
+    pub fn clear(&self) {
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+    }
+
+    fn get_fresh<T: CacheableReport>(
+        &self,
+        cache: &Cache<ReportCacheKey, CacheEntry>,
+        key: &ReportCacheKey,
+        generation: u64,
+    ) -> Option<T> {
+        let entry = cache.get(key)?;
+        let fresh =
+            entry.generation.is_none_or(|cached| cached == generation && entry.inserted_at.elapsed() < self.ttl);
+        T::from_cached(&entry.report).filter(|_| fresh)
+    }
+
+    /// Return the cached report for `key`, if it is still fresh
+    pub fn get<T: CacheableReport>(&self, key: &ReportCacheKey) -> Option<T> {
+        let cache = self.cache.as_ref()?;
+        self.get_fresh(cache, key, self.generation(&key.entities))
+    }
+
+    /// Return the cached report for `key`, or compute and cache it
+    pub fn get_or_compute<T: CacheableReport>(
+        &self,
+        key: ReportCacheKey,
+        compute: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        let Some(cache) = &self.cache else {
+            return compute();
+        };
+
+        // read the generation before computing, so events written in the meantime invalidate the result
+        let generation = self.generation(&key.entities);
+        if let Some(report) = self.get_fresh(cache, &key, generation) {
+            return Ok(report);
+        }
+
+        let live = key.range.end > Utc::now() - LIVE_RANGE_MARGIN;
+        let report = compute()?;
+        let entry = CacheEntry {
+            report: report.clone().into_cached(),
+            inserted_at: Instant::now(),
+            generation: live.then_some(generation),
+        };
+        cache.insert(key, entry);
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::reports::test_query;
+    use chrono::TimeZone;
+
+    fn stats(total_views: u64) -> ReportStats {
+        ReportStats { total_views, ..Default::default() }
+    }
+
+    #[test]
+    fn live_ranges_are_invalidated_by_new_events() {
+        let cache = ReportCache::new(&ReportCacheConfig::default());
+        let entities = ["entity-1".to_string()];
+        let past = DateRange {
+            start: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            end: Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap(),
+        };
+        let live = DateRange { start: Utc::now() - chrono::Duration::days(1), end: Utc::now() };
+        let query = |range| test_query(&entities, range);
+        let past_key = ReportCacheKey::new("project-1", &query(&past), ReportKind::Stats);
+        let live_key = ReportCacheKey::new("project-1", &query(&live), ReportKind::Stats);
+
+        let cached = |key: &ReportCacheKey, views| {
+            cache.get_or_compute(key.clone(), || Ok(stats(views))).expect("failed to compute stats").total_views
+        };
+
+        assert_eq!(cached(&past_key, 1), 1);
+        assert_eq!(cached(&live_key, 1), 1);
+        assert_eq!(cached(&past_key, 2), 1);
+        assert_eq!(cached(&live_key, 2), 1);
+
+        cache.invalidate_entities(["entity-2"]);
+        assert_eq!(cached(&live_key, 3), 1);
+
+        cache.invalidate_entities(["entity-1"]);
+        assert_eq!(cached(&past_key, 3), 1);
+        assert_eq!(cached(&live_key, 3), 3);
+    }
+}
@@ -1,69 +1,48 @@
-use std::collections::BTreeMap;
-
 use crate::app::DuckDBConn;
 use crate::utils::duckdb::{ParamVec, repeat_vars};
 use anyhow::Result;
-use duckdb::params_from_iter;
 
-use super::shared::{SESSION_DURATION_SQL, build_filter_clause, metric_aggregate_sql};
-use super::{DateRange, Dimension, DimensionFilter, Metric, ReportTable};
+use super::shared::{
+    build_filter_clause, dimension_column_sql, dimension_page_sql, metric_aggregate_sql, push_page_params,
+    query_dimension_page, scope_filters_sql, session_time_column_sql,
+};
+use super::{Dimension, DimensionTableOptions, Metric, ReportQuery, ReportTable};
 
 /// Build a dimension table report for a metric
 pub fn dimension_report(
     conn: &DuckDBConn,
-    entities: &[String],
-    event: &str,
-    range: &DateRange,
+    report: &ReportQuery,
     dimension: &Dimension,
-    filters: &[DimensionFilter],
     metric: &Metric,
+    options: &DimensionTableOptions,
 ) -> Result<ReportTable> {
-    if entities.is_empty() {
-        return Ok(BTreeMap::new());
+    if report.entities.is_empty() {
+        return Ok(ReportTable::default());
+    }
+
+    if let Some(report_table) = super::rollups::dimension_report(conn, report, *dimension, *metric, options)? {
+        return Ok(report_table);
     }
 
     let mut params = ParamVec::new();
-    let entity_vars = repeat_vars(entities.len());
-    let (filters_sql, filters_params) = build_filter_clause(filters)?;
-
-    let metric_column = metric_aggregate_sql(*metric, "sd");
-    let (dimension_column, dimension_scope_sql) = match dimension {
-        Dimension::Url => ("concat(fqdn, path)", None),
-        Dimension::UrlEntry => (
-            "concat(fqdn, path)",
-            Some(format!("time_from_last_event is null or time_from_last_event > {SESSION_DURATION_SQL}")),
-        ),
-        Dimension::UrlExit => (
-            "concat(fqdn, path)",
-            Some(format!("time_to_next_event is null or time_to_next_event > {SESSION_DURATION_SQL}")),
-        ),
-        Dimension::Path => ("path", None),
-        Dimension::Fqdn => ("fqdn", None),
-        Dimension::Referrer => ("referrer", None),
-        Dimension::Platform => ("platform", None),
-        Dimension::Browser => ("browser", None),
-        Dimension::Mobile => ("mobile::text", None),
-        Dimension::Country => ("country", None),
-        Dimension::City => ("concat(country, city)", None),
-        Dimension::UtmSource => ("utm_source", None),
-        Dimension::UtmMedium => ("utm_medium", None),
-        Dimension::UtmCampaign => ("utm_campaign", None),
-        Dimension::UtmContent => ("utm_content", None),
-        Dimension::UtmTerm => ("utm_term", None),
-        Dimension::ScreenWidth => ("screen_width", None),
-        Dimension::Orientation => ("orientation", None),
-    };
-    let filters_sql = match (filters_sql.is_empty(), dimension_scope_sql) {
-        (true, Some(scope)) => format!("and ({scope})"),
-        (false, Some(scope)) => format!("{filters_sql} and ({scope})"),
-        (_, None) => filters_sql,
-    };
+    let entity_vars = repeat_vars(report.entities.len());
+    let (filters_sql, filters_params) = build_filter_clause(report, "sd")?;
+    let filters_sql = scope_filters_sql(filters_sql, *dimension, report.session_timeout);
+
+    let metric_column = metric_aggregate_sql(*metric, "sd", report);
+    let session_time_column = session_time_column_sql(&[*metric], "sd", report.session_timeout);
+    let dimension_column = dimension_column_sql(*dimension);
+    let other_sql = format!(
+        "select {metric_column} from session_data sd where sd.dimension_value in (select dimension_value from remainder)"
+    );
+    let page_sql = dimension_page_sql(options, &other_sql);
 
-    params.push(event);
-    params.push(range.start);
-    params.push(range.end);
-    params.extend(entities);
+    params.push(report.event);
+    params.push(report.range.start);
+    params.push(report.range.end);
+    params.extend(report.entities);
     params.extend_from_params(filters_params);
+    push_page_params(&mut params, options);
 
     let query = format!(
         "--sql
@@ -75,40 +54,143 @@ pub fn dimension_report(
 					created_at,
 					time_from_last_event,
 					time_to_next_event
+					{session_time_column}
 				from events sd
 				where
 					sd.event = ?::text and
 					sd.created_at >= ?::timestamp and sd.created_at < ?::timestamp and
 					sd.entity_id in ({entity_vars})
 					{filters_sql}
-			)
-		select
-			dimension_value,
-			{metric_column} as metric_value
-		from session_data sd
-		group by dimension_value
-		order by metric_value desc;
-	"
+			),
+			dimension_values as (
+				select
+					dimension_value,
+					{metric_column} as metric_value
+				from session_data sd
+				group by dimension_value
+			),
+			{page_sql}"
     );
 
-    let mut stmt = conn.prepare_cached(&query)?;
-
-    match metric {
-        Metric::Views | Metric::UniqueVisitors => {
-            let rows = stmt.query_map(params_from_iter(params), |row| {
-                let dimension_value: String = row.get(0)?;
-                Ok((dimension_value, row.get(1)?))
-            })?;
-            let report_table = rows.collect::<Result<BTreeMap<String, f64>, duckdb::Error>>()?;
-            Ok(report_table)
-        }
-        Metric::AvgTimeOnSite | Metric::BounceRate => {
-            let rows = stmt.query_map(params_from_iter(params), |row| {
-                let dimension_value: String = row.get(0)?;
-                Ok((dimension_value, row.get(1)?))
-            })?;
-            let report_table = rows.collect::<Result<BTreeMap<String, f64>, duckdb::Error>>()?;
-            Ok(report_table)
-        }
+    query_dimension_page(conn, &query, params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::reports::{
+        DateRange, DimensionFilter, FilterType, ReportTableRow, SortOrder, test_pageview, test_query,
+    };
+    use crate::app::{Liwan, models::Event};
+    use crate::config::Config;
+    use chrono::{TimeZone, Utc};
+
+    fn test_event(visitor: &str, path: &str, hour: u32) -> Event {
+        let created_at = Utc.with_ymd_and_hms(2024, 1, 1, hour, 0, 0).unwrap();
+        Event { path: Some(path.to_string()), ..test_pageview(visitor, created_at) }
+    }
+
+    #[test]
+    fn dimension_report_pages_searches_and_rolls_up_other() {
+        let app = Liwan::new_memory(Config::default()).expect("failed to create app");
+        let events = vec![
+            test_event("a", "/", 1),
+            test_event("b", "/", 2),
+            test_event("c", "/", 3),
+            test_event("a", "/blog/one", 4),
+            test_event("a", "/blog/one", 5),
+            test_event("b", "/blog/two", 6),
+            test_event("a", "/pricing", 7),
+        ];
+        app.events.append(events.into_iter()).expect("failed to append events");
+
+        let conn = app.events_conn().expect("failed to get events conn");
+        let entities = ["entity-1".to_string()];
+        let range = DateRange {
+            start: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            end: Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap(),
+        };
+        let query = test_query(&entities, &range);
+        let row = |path: &str, value| ReportTableRow { dimension_value: path.to_string(), value };
+        let report = |metric, options| {
+            dimension_report(&conn, &query, &Dimension::Path, &metric, &options).expect("failed to build report")
+        };
+
+        let table = report(Metric::Views, DimensionTableOptions::default());
+        assert_eq!(table.rows, vec![row("/", 3.0), row("/blog/one", 2.0), row("/blog/two", 1.0), row("/pricing", 1.0)]);
+        assert_eq!(table.total_rows, 4);
+        assert_eq!(table.other, None);
+
+        let options = DimensionTableOptions { limit: Some(1), offset: 1, include_other: true, ..Default::default() };
+        let table = report(Metric::Views, options);
+        assert_eq!(table.rows, vec![row("/blog/one", 2.0)]);
+        assert_eq!(table.total_rows, 4);
+        assert_eq!(table.other, Some(2.0));
+
+        // visitors are counted once across all remaining values
+        let options = DimensionTableOptions { limit: Some(1), include_other: true, ..Default::default() };
+        let table = report(Metric::UniqueVisitors, options);
+        assert_eq!(table.rows, vec![row("/", 3.0)]);
+        assert_eq!(table.other, Some(2.0));
+
+        let options = DimensionTableOptions {
+            search: Some("BLOG".to_string()),
+            order: SortOrder::Asc,
+            include_other: true,
+            ..Default::default()
+        };
+        let table = report(Metric::Views, options);
+        assert_eq!(table.rows, vec![row("/blog/two", 1.0), row("/blog/one", 2.0)]);
+        assert_eq!(table.total_rows, 2);
+        assert_eq!(table.other, None);
+
+        let values = Some(vec!["/pricing".to_string(), "/".to_string(), "/missing".to_string()]);
+        let table = report(Metric::Views, DimensionTableOptions { values, ..Default::default() });
+        assert_eq!(table.rows, vec![row("/", 3.0), row("/pricing", 1.0)]);
+
+        let table = report(Metric::Views, DimensionTableOptions { values: Some(Vec::new()), ..Default::default() });
+        assert!(table.rows.is_empty());
+    }
+
+    #[test]
+    fn dimension_report_groups_and_filters_by_entity() {
+        let app = Liwan::new_memory(Config::default()).expect("failed to create app");
+        let event = |entity_id: &str, path: &str, hour| Event {
+            entity_id: entity_id.to_string(),
+            ..test_event("a", path, hour)
+        };
+        let events = vec![
+            event("production", "/", 1),
+            event("production", "/pricing", 2),
+            event("staging", "/", 3),
+            event("other", "/", 4),
+        ];
+        app.events.append(events.into_iter()).expect("failed to append events");
+
+        let conn = app.events_conn().expect("failed to get events conn");
+        let entities = ["production".to_string(), "staging".to_string()];
+        let range = DateRange {
+            start: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            end: Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap(),
+        };
+        let filters = [DimensionFilter {
+            dimension: Dimension::Entity,
+            filter_type: FilterType::Equal,
+            inversed: None,
+            strict: None,
+            value: Some("staging".to_string()),
+            scope: None,
+        }];
+        let query = |filters| ReportQuery { filters, ..test_query(&entities, &range) };
+        let row = |entity: &str, value| ReportTableRow { dimension_value: entity.to_string(), value };
+        let options = DimensionTableOptions::default();
+
+        let table = dimension_report(&conn, &query(&[]), &Dimension::Entity, &Metric::Views, &options)
+            .expect("failed to build report");
+        assert_eq!(table.rows, vec![row("production", 2.0), row("staging", 1.0)]);
+
+        let table = dimension_report(&conn, &query(&filters), &Dimension::Path, &Metric::Views, &options)
+            .expect("failed to build report");
+        assert_eq!(table.rows, vec![row("/", 1.0)]);
     }
 }
@@ -1,11 +1,17 @@
 use crate::app::DuckDBConn;
 use crate::utils::duckdb::{ParamVec, repeat_vars};
 use anyhow::{Context, Result};
-use chrono::{DateTime, Days, Duration, LocalResult, NaiveDate, TimeZone, Timelike, Utc};
+use chrono::{DateTime, Datelike, Days, Duration, LocalResult, Months, NaiveDate, TimeZone, Timelike, Utc};
 use chrono_tz::Tz;
 
-use super::shared::{build_filter_clause, metric_aggregate_sql};
-use super::{DateRange, DimensionFilter, GraphInterval, Metric, ReportGraph, ReportGraphPoint};
+use super::shared::{
+    build_filter_clause, dimension_column_sql, metric_aggregate_sql, rank_aggregate_sql, scope_filters_sql,
+    session_time_column_sql,
+};
+use super::{
+    DateRange, Dimension, GraphInterval, Metric, ReportBreakdownGraph, ReportGraph, ReportGraphPoint,
+    ReportGraphSeries, ReportQuery, WeekStart,
+};
 
 fn zero_report_graph(buckets: &[DateRange]) -> ReportGraph {
     buckets.iter().map(|bucket| ReportGraphPoint { bin_start: bucket.start, value: 0.0 }).collect()
@@ -47,41 +53,82 @@ fn resolve_local_day_start(timezone: Tz, date: NaiveDate) -> Result<DateTime<Utc
     Ok(resolved.with_timezone(&Utc))
 }
 
+/// Parse an IANA timezone name, defaulting to UTC
+pub fn parse_timezone(timezone: Option<&str>) -> Result<Tz> {
+    let timezone_name = timezone.unwrap_or("UTC");
+    timezone_name.parse().with_context(|| format!("Invalid timezone: {timezone_name}"))
+}
+
+/// Return the first local date of the bucket containing `date`
+fn bucket_start_date(interval: GraphInterval, week_start: WeekStart, date: NaiveDate) -> Option<NaiveDate> {
+    match interval {
+        GraphInterval::Minute | GraphInterval::Hour | GraphInterval::Day => Some(date),
+        GraphInterval::Week => date.checked_sub_days(Days::new(date.weekday().days_since(week_start.weekday()).into())),
+        GraphInterval::Month => date.with_day(1),
+        GraphInterval::Quarter => NaiveDate::from_ymd_opt(date.year(), date.month0() / 3 * 3 + 1, 1),
+    }
+}
+
+/// Return the first local date of the bucket following the one starting at `date`
+fn next_bucket_date(interval: GraphInterval, date: NaiveDate) -> Option<NaiveDate> {
+    match interval {
+        GraphInterval::Minute | GraphInterval::Hour | GraphInterval::Day => date.checked_add_days(Days::new(1)),
+        GraphInterval::Week => date.checked_add_days(Days::new(7)),
+        GraphInterval::Month => date.checked_add_months(Months::new(1)),
+        GraphInterval::Quarter => date.checked_add_months(Months::new(3)),
+    }
+}
+
 /// Split a date range into graph buckets aligned to the selected timezone
+///
+/// `week_start` is only used for weekly buckets. Returns `None` as soon as the range is split into more than
+/// `max_buckets` buckets, so huge ranges are rejected without building all of them.
 pub fn build_graph_buckets(
     range: &DateRange,
     interval: GraphInterval,
     timezone: Option<&str>,
-) -> Result<Vec<DateRange>> {
+    week_start: WeekStart,
+    max_buckets: usize,
+) -> Result<Option<Vec<DateRange>>> {
     if range.start >= range.end {
-        return Ok(Vec::new());
+        return Ok(Some(Vec::new()));
     }
 
-    let timezone_name = timezone.unwrap_or("UTC");
-    let timezone: Tz = timezone_name.parse().with_context(|| format!("Invalid timezone: {timezone_name}"))?;
+    let timezone = parse_timezone(timezone)?;
 
+    let start_local = range.start.with_timezone(&timezone);
     let aligned_start = match interval {
+        GraphInterval::Minute => {
+            range.start
+                - Duration::seconds(i64::from(start_local.second()))
+                - Duration::nanoseconds(i64::from(start_local.nanosecond()))
+        }
         GraphInterval::Hour => {
-            let start_local = range.start.with_timezone(&timezone);
             range.start
                 - Duration::minutes(i64::from(start_local.minute()))
                 - Duration::seconds(i64::from(start_local.second()))
                 - Duration::nanoseconds(i64::from(start_local.nanosecond()))
         }
-        GraphInterval::Day => resolve_local_day_start(timezone, range.start.with_timezone(&timezone).date_naive())?,
+        GraphInterval::Day | GraphInterval::Week | GraphInterval::Month | GraphInterval::Quarter => {
+            let date = bucket_start_date(interval, week_start, start_local.date_naive())
+                .context("Failed to align bucket date")?;
+            resolve_local_day_start(timezone, date)?
+        }
     };
 
     let mut buckets = Vec::new();
     let mut bucket_start = aligned_start;
 
     while bucket_start < range.end {
+        if buckets.len() >= max_buckets {
+            return Ok(None);
+        }
+
         let next_bucket_start = match interval {
+            GraphInterval::Minute => bucket_start + Duration::minutes(1),
             GraphInterval::Hour => bucket_start + Duration::hours(1),
-            GraphInterval::Day => {
-                let next_date = bucket_start
-                    .with_timezone(&timezone)
-                    .date_naive()
-                    .checked_add_days(Days::new(1))
+            GraphInterval::Day | GraphInterval::Week | GraphInterval::Month | GraphInterval::Quarter => {
+                let next_date = next_bucket_date(interval, bucket_start.with_timezone(&timezone).date_naive())
                     .context("Failed to advance bucket date")?;
                 resolve_local_day_start(timezone, next_date)?
             }
@@ -94,43 +141,45 @@ pub fn build_graph_buckets(
         bucket_start = next_bucket_start;
     }
 
-    Ok(buckets)
+    Ok(Some(buckets))
 }
 
 /// Build a graph report for a metric across precomputed time buckets
 pub fn overall_report(
     conn: &DuckDBConn,
-    entities: &[String],
-    event: &str,
-    range: &DateRange,
+    report: &ReportQuery,
     buckets: &[DateRange],
-    filters: &[DimensionFilter],
     metric: &Metric,
 ) -> Result<ReportGraph> {
     if buckets.is_empty() {
         return Ok(Vec::new());
     }
 
-    if entities.is_empty() {
+    if report.entities.is_empty() {
         return Ok(zero_report_graph(buckets));
     }
 
+    if let Some(report_graph) = super::rollups::overall_report(conn, report, buckets, *metric)? {
+        return Ok(report_graph);
+    }
+
     let mut params = ParamVec::new();
 
-    let (filters_sql, filters_params) = build_filter_clause(filters)?;
-    let metric_sql = metric_aggregate_sql(*metric, "sd");
+    let (filters_sql, filters_params) = build_filter_clause(report, "e")?;
+    let metric_sql = metric_aggregate_sql(*metric, "sd", report);
+    let session_time_column = session_time_column_sql(&[*metric], "e", report.session_timeout);
     let time_bins_sql = build_time_bins_values_sql(buckets.len());
 
-    let entity_vars = repeat_vars(entities.len());
+    let entity_vars = repeat_vars(report.entities.len());
 
     for bucket in buckets {
         params.push(bucket.start);
         params.push(bucket.end);
     }
-    params.push(event);
-    params.push(range.start);
-    params.push(range.end);
-    params.extend(entities);
+    params.push(report.event);
+    params.push(report.range.start);
+    params.push(report.range.end);
+    params.extend(report.entities);
     params.extend_from_params(filters_params);
 
     let query = format!(
@@ -145,6 +194,7 @@ pub fn overall_report(
 					e.created_at,
 					e.time_from_last_event,
 					e.time_to_next_event
+					{session_time_column}
 				from events e
 				where
 					e.event = ?::text and
@@ -155,10 +205,7 @@ pub fn overall_report(
 			bucketed_events as (
 				select
 					tb.bucket_idx,
-					sd.visitor_group_id,
-					sd.created_at,
-					sd.time_from_last_event,
-					sd.time_to_next_event
+					sd.*
 				from (select * from session_data order by created_at) sd
 				asof join (select * from time_bins order by bin_start) tb
 					on sd.created_at >= tb.bin_start
@@ -183,14 +230,18 @@ pub fn overall_report(
     let mut stmt = conn.prepare_cached(&query)?;
 
     match metric {
-        Metric::Views | Metric::UniqueVisitors => {
+        Metric::Views | Metric::UniqueVisitors | Metric::Sessions => {
             let rows = stmt.query_map(duckdb::params_from_iter(params), |row| {
                 Ok(ReportGraphPoint { bin_start: row.get(0)?, value: row.get(1)? })
             })?;
             let report_graph = rows.collect::<Result<Vec<ReportGraphPoint>, duckdb::Error>>()?;
             Ok(report_graph)
         }
-        Metric::AvgTimeOnSite | Metric::BounceRate => {
+        Metric::ViewsPerSession
+        | Metric::AvgTimeOnSite
+        | Metric::BounceRate
+        | Metric::MedianSessionDuration
+        | Metric::ExitRate => {
             let rows = stmt.query_map(duckdb::params_from_iter(params), |row| {
                 Ok(ReportGraphPoint { bin_start: row.get(0)?, value: row.get::<_, Option<f64>>(1)?.unwrap_or(0.0) })
             })?;
@@ -200,9 +251,133 @@ pub fn overall_report(
     }
 }
 
+/// Build a graph report for a metric with one series per top value of a dimension
+///
+/// Values are ranked by the metric over the whole range, or by views for metrics that aren't counts. All values
+/// outside of the top `limit` are combined into a final series without a dimension value.
+pub fn breakdown_report(
+    conn: &DuckDBConn,
+    report: &ReportQuery,
+    buckets: &[DateRange],
+    dimension: &Dimension,
+    metric: &Metric,
+    limit: usize,
+) -> Result<ReportBreakdownGraph> {
+    if buckets.is_empty() || report.entities.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut params = ParamVec::new();
+
+    let (filters_sql, filters_params) = build_filter_clause(report, "e")?;
+    let filters_sql = scope_filters_sql(filters_sql, *dimension, report.session_timeout);
+    let metric_sql = metric_aggregate_sql(*metric, "sd", report);
+    let rank_sql = rank_aggregate_sql(*metric, "sd", report);
+    let session_time_column = session_time_column_sql(&[*metric], "e", report.session_timeout);
+    let time_bins_sql = build_time_bins_values_sql(buckets.len());
+    let dimension_column = dimension_column_sql(*dimension);
+
+    let entity_vars = repeat_vars(report.entities.len());
+
+    for bucket in buckets {
+        params.push(bucket.start);
+        params.push(bucket.end);
+    }
+    params.push(report.event);
+    params.push(report.range.start);
+    params.push(report.range.end);
+    params.extend(report.entities);
+    params.extend_from_params(filters_params);
+
+    let query = format!(
+        "--sql
+		with
+			time_bins(bucket_idx, bin_start, bin_end) as (
+				values {time_bins_sql}
+			),
+			session_data as (
+				select
+					coalesce({dimension_column}, 'Unknown') as dimension_value,
+					e.visitor_group_id,
+					e.created_at,
+					e.time_from_last_event,
+					e.time_to_next_event
+					{session_time_column}
+				from events e
+				where
+					e.event = ?::text and
+					e.created_at >= ?::timestamp and e.created_at < ?::timestamp and
+					e.entity_id in ({entity_vars})
+					{filters_sql}
+			),
+			top_values as (
+				select
+					sd.dimension_value,
+					row_number() over (order by {rank_sql} desc, sd.dimension_value) as position
+				from session_data sd
+				group by sd.dimension_value
+				order by position
+				limit {limit}
+			),
+			bucketed_events as (
+				select
+					tb.bucket_idx,
+					sd.*
+				from (select * from session_data order by created_at) sd
+				asof join (select * from time_bins order by bin_start) tb
+					on sd.created_at >= tb.bin_start
+				where sd.created_at < tb.bin_end
+			)
+		select
+			tv.position,
+			tv.dimension_value,
+			sd.bucket_idx,
+			{metric_sql} as metric_value
+		from bucketed_events sd
+		left join top_values tv on sd.dimension_value = tv.dimension_value
+		group by tv.position, tv.dimension_value, sd.bucket_idx
+		order by tv.position nulls last, sd.bucket_idx;
+	"
+    );
+
+    let mut stmt = conn.prepare_cached(&query)?;
+    let rows = stmt.query_map(duckdb::params_from_iter(params), |row| {
+        Ok((
+            row.get::<_, Option<i64>>(0)?,
+            row.get::<_, Option<String>>(1)?,
+            row.get::<_, i64>(2)?,
+            row.get::<_, Option<f64>>(3)?,
+        ))
+    })?;
+
+    let mut report_graph = ReportBreakdownGraph::new();
+    let mut other = None;
+    for row in rows {
+        let (position, dimension_value, bucket_idx, value) = row?;
+        let series = match position {
+            Some(_) => {
+                if report_graph.last().is_none_or(|series| series.dimension_value != dimension_value) {
+                    report_graph.push(ReportGraphSeries { dimension_value, data: zero_report_graph(buckets) });
+                }
+                report_graph.last_mut().context("Missing breakdown series")?
+            }
+            None => other
+                .get_or_insert_with(|| ReportGraphSeries { dimension_value: None, data: zero_report_graph(buckets) }),
+        };
+
+        if let Some(point) = usize::try_from(bucket_idx).ok().and_then(|idx| series.data.get_mut(idx)) {
+            point.value = value.unwrap_or(0.0);
+        }
+    }
+    report_graph.extend(other);
+
+    Ok(report_graph)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::app::reports::{Comparison, test_pageview, test_query};
     use crate::app::{Liwan, models::Event};
     use crate::config::Config;
 
@@ -215,28 +390,7 @@ mod tests {
     }
 
     fn test_event(created_at: DateTime<Utc>) -> Event {
-        Event {
-            entity_id: "entity-1".to_string(),
-            visitor_group_id: format!("visitor-{created_at}"),
-            event: "pageview".to_string(),
-            created_at,
-            fqdn: Some("example.com".to_string()),
-            path: Some("/".to_string()),
-            referrer: None,
-            platform: None,
-            browser: None,
-            mobile: None,
-            country: None,
-            city: None,
-            utm_source: None,
-            utm_medium: None,
-            utm_campaign: None,
-            utm_content: None,
-            utm_term: None,
-            screen_width: None,
-            orientation: None,
-            track_sessions: true,
-        }
+        test_pageview(&format!("visitor-{created_at}"), created_at)
     }
 
     #[test]
@@ -247,8 +401,9 @@ mod tests {
             end: local_datetime(Tz::Asia__Kolkata, 2024, 1, 2, 1, 23),
         };
 
-        let buckets =
-            build_graph_buckets(&range, GraphInterval::Hour, Some(timezone)).expect("failed to build buckets");
+        let buckets = build_graph_buckets(&range, GraphInterval::Hour, Some(timezone), WeekStart::Monday, usize::MAX)
+            .expect("failed to build buckets")
+            .expect("too many buckets");
         let local_starts = buckets
             .iter()
             .map(|bucket| bucket.start.with_timezone(&Tz::Asia__Kolkata).format("%Y-%m-%d %H:%M").to_string())
@@ -266,7 +421,9 @@ mod tests {
             end: local_datetime(Tz::America__New_York, 2024, 1, 3, 12, 0),
         };
 
-        let buckets = build_graph_buckets(&range, GraphInterval::Day, Some(timezone)).expect("failed to build buckets");
+        let buckets = build_graph_buckets(&range, GraphInterval::Day, Some(timezone), WeekStart::Monday, usize::MAX)
+            .expect("failed to build buckets")
+            .expect("too many buckets");
         let local_starts = buckets
             .iter()
             .map(|bucket| bucket.start.with_timezone(&Tz::America__New_York).format("%Y-%m-%d %H:%M").to_string())
@@ -285,6 +442,123 @@ mod tests {
         );
     }
 
+    #[test]
+    fn build_graph_buckets_aligns_weeks_to_week_start() {
+        let timezone = "Europe/Berlin";
+        // 2024-01-03 is a Wednesday
+        let range = DateRange {
+            start: local_datetime(Tz::Europe__Berlin, 2024, 1, 3, 10, 0),
+            end: local_datetime(Tz::Europe__Berlin, 2024, 1, 17, 0, 0),
+        };
+        let local_starts = |week_start| {
+            build_graph_buckets(&range, GraphInterval::Week, Some(timezone), week_start, usize::MAX)
+                .expect("failed to build buckets")
+                .expect("too many buckets")
+                .iter()
+                .map(|bucket| bucket.start.with_timezone(&Tz::Europe__Berlin).format("%Y-%m-%d %H:%M").to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(local_starts(WeekStart::Monday), vec!["2024-01-01 00:00", "2024-01-08 00:00", "2024-01-15 00:00"]);
+        assert_eq!(local_starts(WeekStart::Sunday), vec!["2023-12-31 00:00", "2024-01-07 00:00", "2024-01-14 00:00"]);
+    }
+
+    #[test]
+    fn build_graph_buckets_aligns_months_and_quarters_across_dst() {
+        let timezone = "America/New_York";
+        let range = DateRange {
+            start: local_datetime(Tz::America__New_York, 2024, 2, 15, 8, 0),
+            end: local_datetime(Tz::America__New_York, 2024, 11, 20, 0, 0),
+        };
+        let local_starts = |interval| {
+            build_graph_buckets(&range, interval, Some(timezone), WeekStart::Monday, usize::MAX)
+                .expect("failed to build buckets")
+                .expect("too many buckets")
+                .iter()
+                .map(|bucket| bucket.start.with_timezone(&Tz::America__New_York).format("%Y-%m-%d %H:%M").to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let months = local_starts(GraphInterval::Month);
+        assert_eq!(months.len(), 10);
+        assert_eq!(months[0], "2024-02-01 00:00");
+        assert_eq!(months[2], "2024-04-01 00:00");
+        assert_eq!(months[9], "2024-11-01 00:00");
+
+        assert_eq!(
+            local_starts(GraphInterval::Quarter),
+            vec!["2024-01-01 00:00", "2024-04-01 00:00", "2024-07-01 00:00", "2024-10-01 00:00"]
+        );
+    }
+
+    #[test]
+    fn build_graph_buckets_aligns_minutes() {
+        let range = DateRange {
+            start: Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 30).unwrap(),
+            end: Utc.with_ymd_and_hms(2024, 1, 1, 12, 3, 0).unwrap(),
+        };
+
+        let buckets = build_graph_buckets(&range, GraphInterval::Minute, None, WeekStart::Monday, usize::MAX)
+            .expect("failed to build buckets")
+            .expect("too many buckets");
+        let starts = buckets.iter().map(|bucket| bucket.start.format("%H:%M:%S").to_string()).collect::<Vec<_>>();
+
+        assert_eq!(starts, vec!["12:00:00", "12:01:00", "12:02:00"]);
+    }
+
+    #[test]
+    fn build_graph_buckets_stops_at_max_buckets() {
+        let range = DateRange {
+            start: Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap(),
+            end: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        };
+        let buckets = |interval, max_buckets| {
+            build_graph_buckets(&range, interval, None, WeekStart::Monday, max_buckets)
+                .expect("failed to build buckets")
+        };
+
+        assert!(buckets(GraphInterval::Minute, 2000).is_none());
+        assert!(buckets(GraphInterval::Quarter, 15).is_none());
+        assert_eq!(buckets(GraphInterval::Quarter, 16).map(|buckets| buckets.len()), Some(16));
+    }
+
+    #[test]
+    fn comparison_buckets_align_by_offset() {
+        let range = DateRange {
+            start: Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap(),
+            end: Utc.with_ymd_and_hms(2024, 3, 4, 0, 0, 0).unwrap(),
+        };
+        let buckets = build_graph_buckets(&range, GraphInterval::Day, None, WeekStart::Monday, usize::MAX)
+            .expect("failed to build buckets")
+            .expect("too many buckets");
+        let starts = |comparison: Comparison| {
+            comparison
+                .buckets(&range, &buckets)
+                .expect("failed to shift buckets")
+                .iter()
+                .map(|bucket| bucket.start.format("%Y-%m-%d").to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(starts(Comparison::PreviousPeriod), vec!["2024-02-27", "2024-02-28", "2024-02-29"]);
+        assert_eq!(starts(Comparison::PreviousYear), vec!["2023-03-01", "2023-03-02", "2023-03-03"]);
+
+        let custom = DateRange {
+            start: Utc.with_ymd_and_hms(2024, 1, 10, 0, 0, 0).unwrap(),
+            end: Utc.with_ymd_and_hms(2024, 1, 13, 0, 0, 0).unwrap(),
+        };
+        assert_eq!(Comparison::Custom(custom.clone()).range(&range), Some(custom.clone()));
+        assert_eq!(starts(Comparison::Custom(custom)), vec!["2024-01-10", "2024-01-11", "2024-01-12"]);
+
+        // custom ranges with a different duration would leave buckets outside of the range
+        let shorter = DateRange {
+            start: Utc.with_ymd_and_hms(2024, 1, 10, 0, 0, 0).unwrap(),
+            end: Utc.with_ymd_and_hms(2024, 1, 12, 0, 0, 0).unwrap(),
+        };
+        assert_eq!(Comparison::Custom(shorter.clone()).range(&range), None);
+        assert_eq!(Comparison::Custom(shorter).buckets(&range, &buckets), None);
+    }
+
     #[test]
     fn overall_report_includes_start_and_excludes_end() {
         let app = Liwan::new_memory(Config::default()).expect("failed to create app");
@@ -299,13 +573,61 @@ mod tests {
             .expect("failed to append events");
 
         let range = DateRange { start, end };
-        let buckets = build_graph_buckets(&range, GraphInterval::Day, Some("UTC")).expect("failed to build buckets");
+        let buckets = build_graph_buckets(&range, GraphInterval::Day, Some("UTC"), WeekStart::Monday, usize::MAX)
+            .expect("failed to build buckets")
+            .expect("too many buckets");
         let conn = app.events_conn().expect("failed to get events conn");
-        let report =
-            overall_report(&conn, &["entity-1".to_string()], "pageview", &range, &buckets, &[], &Metric::Views)
-                .expect("failed to build report");
+        let entities = ["entity-1".to_string()];
+        let query = test_query(&entities, &range);
+        let report = overall_report(&conn, &query, &buckets, &Metric::Views).expect("failed to build report");
 
         let values = report.iter().map(|point| point.value).collect::<Vec<_>>();
         assert_eq!(values, vec![1.0, 1.0]);
     }
+
+    #[test]
+    fn breakdown_report_splits_top_values_and_other() {
+        let app = Liwan::new_memory(Config::default()).expect("failed to create app");
+        app.seed_database(0).expect("failed to seed app");
+
+        let start = local_datetime(Tz::UTC, 2024, 1, 1, 0, 0);
+        let country_event = |country: &str, day: u32, hour: u32| Event {
+            country: Some(country.to_string()),
+            ..test_event(local_datetime(Tz::UTC, 2024, 1, day, hour, 0))
+        };
+        let events = vec![
+            country_event("DE", 1, 1),
+            country_event("DE", 1, 2),
+            country_event("DE", 2, 1),
+            country_event("FR", 1, 3),
+            country_event("FR", 2, 2),
+            country_event("US", 2, 3),
+            test_event(local_datetime(Tz::UTC, 2024, 1, 2, 4, 0)),
+        ];
+        app.events.append(events.into_iter()).expect("failed to append events");
+
+        let range = DateRange { start, end: local_datetime(Tz::UTC, 2024, 1, 3, 0, 0) };
+        let buckets = build_graph_buckets(&range, GraphInterval::Day, Some("UTC"), WeekStart::Monday, usize::MAX)
+            .expect("failed to build buckets")
+            .expect("too many buckets");
+        let conn = app.events_conn().expect("failed to get events conn");
+        let entities = ["entity-1".to_string()];
+        let query = test_query(&entities, &range);
+
+        let report = breakdown_report(&conn, &query, &buckets, &Dimension::Country, &Metric::Views, 2)
+            .expect("failed to build report");
+        let series = report
+            .iter()
+            .map(|series| (series.dimension_value.clone(), series.data.iter().map(|point| point.value).collect()))
+            .collect::<Vec<(Option<String>, Vec<f64>)>>();
+
+        assert_eq!(
+            series,
+            vec![
+                (Some("DE".to_string()), vec![2.0, 1.0]),
+                (Some("FR".to_string()), vec![1.0, 1.0]),
+                (None, vec![0.0, 2.0]),
+            ]
+        );
+    }
 }
@@ -0,0 +1,199 @@
+use crate::app::DuckDBConn;
+use crate::utils::duckdb::{ParamVec, repeat_vars};
+use anyhow::Result;
+use chrono::{DateTime, Duration, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use super::shared::{build_filter_clause, metric_aggregate_sql, session_time_column_sql};
+use super::{DateRange, Metric, ReportHeatmap, ReportQuery};
+
+fn utc_offset_seconds(timezone: Tz, time: DateTime<Utc>) -> i32 {
+    timezone.offset_from_utc_datetime(&time.naive_utc()).fix().local_minus_utc()
+}
+
+/// Split a date range into periods with a constant UTC offset in the selected timezone
+fn utc_offset_periods(range: &DateRange, timezone: Tz) -> Vec<(DateRange, i32)> {
+    let mut periods = Vec::new();
+    let mut period_start = range.start;
+    let mut offset = utc_offset_seconds(timezone, period_start);
+    let mut probe = period_start;
+
+    while probe < range.end {
+        let next_probe = (probe + Duration::days(1)).min(range.end);
+        if utc_offset_seconds(timezone, next_probe) == offset {
+            probe = next_probe;
+            continue;
+        }
+
+        // narrow down the first instant with the new offset
+        let (mut before, mut after) = (probe, next_probe);
+        while after - before > Duration::nanoseconds(1) {
+            let middle = before + (after - before) / 2;
+            if utc_offset_seconds(timezone, middle) == offset {
+                before = middle;
+            } else {
+                after = middle;
+            }
+        }
+
+        periods.push((DateRange { start: period_start, end: after }, offset));
+        period_start = after;
+        offset = utc_offset_seconds(timezone, after);
+        probe = after;
+    }
+
+    if period_start < range.end {
+        periods.push((DateRange { start: period_start, end: range.end }, offset));
+    }
+    periods
+}
+
+fn build_offset_values_sql(period_count: usize) -> String {
+    (0..period_count).map(|_| "(?::timestamp, ?::timestamp, ?::bigint)").collect::<Vec<_>>().join(", ")
+}
+
+/// Build a heatmap of a metric by local day of week and hour of day
+///
+/// Events are assigned to the local time of `timezone` at the moment they happened, so daylight saving time
+/// changes within the range are taken into account.
+pub fn heatmap_report(conn: &DuckDBConn, report: &ReportQuery, metric: &Metric, timezone: Tz) -> Result<ReportHeatmap> {
+    let mut heatmap = vec![vec![0.0; 24]; 7];
+    if report.entities.is_empty() || report.range.start >= report.range.end {
+        return Ok(heatmap);
+    }
+
+    let mut params = ParamVec::new();
+
+    let (filters_sql, filters_params) = build_filter_clause(report, "e")?;
+    let metric_sql = metric_aggregate_sql(*metric, "sd", report);
+    let session_time_column = session_time_column_sql(&[*metric], "e", report.session_timeout);
+    let entity_vars = repeat_vars(report.entities.len());
+
+    let periods = utc_offset_periods(report.range, timezone);
+    let offsets_sql = build_offset_values_sql(periods.len());
+    for (period, offset) in &periods {
+        params.push(period.start);
+        params.push(period.end);
+        params.push(i64::from(*offset));
+    }
+    params.push(report.event);
+    params.push(report.range.start);
+    params.push(report.range.end);
+    params.extend(report.entities);
+    params.extend_from_params(filters_params);
+
+    let query = format!(
+        "--sql
+		with
+			utc_offsets(period_start, period_end, offset_seconds) as (
+				values {offsets_sql}
+			),
+			session_data as (
+				select
+					e.visitor_group_id,
+					e.created_at,
+					e.time_from_last_event,
+					e.time_to_next_event
+					{session_time_column}
+				from events e
+				where
+					e.event = ?::text and
+					e.created_at >= ?::timestamp and e.created_at < ?::timestamp and
+					e.entity_id in ({entity_vars})
+					{filters_sql}
+			),
+			local_events as (
+				select
+					sd.*,
+					sd.created_at + to_seconds(uo.offset_seconds) as local_time
+				from session_data sd
+				join utc_offsets uo on sd.created_at >= uo.period_start and sd.created_at < uo.period_end
+			)
+		select
+			isodow(sd.local_time) - 1 as local_weekday,
+			hour(sd.local_time) as local_hour,
+			({metric_sql})::double as metric_value
+		from local_events sd
+		group by local_weekday, local_hour;
+	"
+    );
+
+    let mut stmt = conn.prepare_cached(&query)?;
+    let rows = stmt.query_map(duckdb::params_from_iter(params), |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, Option<f64>>(2)?))
+    })?;
+
+    for row in rows {
+        let (weekday, hour, value) = row?;
+        let cell = usize::try_from(weekday).ok().zip(usize::try_from(hour).ok());
+        if let Some(cell) = cell.and_then(|(weekday, hour)| heatmap.get_mut(weekday)?.get_mut(hour)) {
+            *cell = value.unwrap_or(0.0);
+        }
+    }
+
+    Ok(heatmap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::Liwan;
+    use crate::app::reports::{test_pageview, test_query};
+    use crate::config::Config;
+
+    #[test]
+    fn utc_offset_periods_split_at_dst_changes() {
+        let range = DateRange {
+            start: Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap(),
+            end: Utc.with_ymd_and_hms(2024, 11, 1, 0, 0, 0).unwrap(),
+        };
+
+        let periods = utc_offset_periods(&range, Tz::Europe__Berlin);
+        let dst_start = Utc.with_ymd_and_hms(2024, 3, 31, 1, 0, 0).unwrap();
+        let dst_end = Utc.with_ymd_and_hms(2024, 10, 27, 1, 0, 0).unwrap();
+        assert_eq!(
+            periods,
+            vec![
+                (DateRange { start: range.start, end: dst_start }, 3600),
+                (DateRange { start: dst_start, end: dst_end }, 7200),
+                (DateRange { start: dst_end, end: range.end }, 3600),
+            ]
+        );
+
+        assert_eq!(utc_offset_periods(&range, Tz::UTC), vec![(range.clone(), 0)]);
+    }
+
+    #[test]
+    fn heatmap_report_uses_local_weekday_and_hour() {
+        let app = Liwan::new_memory(Config::default()).expect("failed to create app");
+        let events = vec![
+            // Monday 23:30 UTC is Tuesday 00:30 in Berlin before DST
+            test_pageview("a", Utc.with_ymd_and_hms(2024, 3, 25, 23, 30, 0).unwrap()),
+            // Monday 22:30 UTC is Tuesday 00:30 in Berlin after DST
+            test_pageview("b", Utc.with_ymd_and_hms(2024, 4, 1, 22, 30, 0).unwrap()),
+            test_pageview("b", Utc.with_ymd_and_hms(2024, 4, 1, 22, 45, 0).unwrap()),
+            // Sunday 12:00 UTC is Sunday 14:00 in Berlin
+            test_pageview("c", Utc.with_ymd_and_hms(2024, 4, 7, 12, 0, 0).unwrap()),
+        ];
+        app.events.append(events.into_iter()).expect("failed to append events");
+
+        let conn = app.events_conn().expect("failed to get events conn");
+        let entities = ["entity-1".to_string()];
+        let range = DateRange {
+            start: Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap(),
+            end: Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap(),
+        };
+        let query = test_query(&entities, &range);
+
+        let views = heatmap_report(&conn, &query, &Metric::Views, Tz::Europe__Berlin).expect("failed to build report");
+        assert_eq!(views.len(), 7);
+        assert!(views.iter().all(|hours| hours.len() == 24));
+        assert_eq!(views[1][0], 3.0);
+        assert_eq!(views[6][14], 1.0);
+        assert_eq!(views.iter().flatten().sum::<f64>(), 4.0);
+
+        let visitors =
+            heatmap_report(&conn, &query, &Metric::UniqueVisitors, Tz::Europe__Berlin).expect("failed to build report");
+        assert_eq!(visitors[1][0], 2.0);
+    }
+}
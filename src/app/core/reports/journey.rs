@@ -0,0 +1,233 @@
+use crate::app::DuckDBConn;
+use crate::utils::duckdb::{ParamVec, repeat_vars};
+use anyhow::Result;
+
+use super::shared::build_filter_clause;
+use super::{JourneyDirection, JourneyOptions, ReportJourney, ReportJourneyStep, ReportQuery, ReportTableRow};
+
+/// Build a report of the pages viewed before or after a page within the same session
+///
+/// Pages are identified by their URL, like [`super::Dimension::Url`]. Sessions are anchored at their first view of
+/// `options.url`. Each step counts the sessions by the page viewed that many pageviews after (or before) the anchor,
+/// with all pages outside of the top `options.limit` combined.
+///
+/// Sessions are reconstructed from all of their pageviews first and the filters then select whole sessions, those with
+/// at least one matching pageview, so filtering never removes pages from the middle of a journey.
+pub fn journey_report(conn: &DuckDBConn, report: &ReportQuery, options: &JourneyOptions) -> Result<ReportJourney> {
+    let mut journey = ReportJourney {
+        sessions: 0,
+        steps: (1..=options.steps).map(|step| ReportJourneyStep { step, ..Default::default() }).collect(),
+    };
+    if report.entities.is_empty() || options.steps == 0 {
+        return Ok(journey);
+    }
+
+    let mut params = ParamVec::new();
+    let entity_vars = repeat_vars(report.entities.len());
+    let (filters_sql, filters_params) = build_filter_clause(report, "sd")?;
+    let timeout_sql = report.session_timeout.sql();
+    let (steps, limit) = (options.steps, options.limit);
+    let entry_sql = if options.entry_only { "and position = 1" } else { "" };
+    let step_sql = match options.direction {
+        JourneyDirection::Forward => "a.anchor_position + s.step",
+        JourneyDirection::Backward => "a.anchor_position - s.step",
+    };
+
+    params.extend_from_params(filters_params);
+    params.push(report.event);
+    params.push(report.range.start);
+    params.push(report.range.end);
+    params.extend(report.entities);
+    params.push(options.url.as_str());
+
+    let query = format!(
+        "--sql
+		with
+			session_events as (
+				select
+					concat(sd.fqdn, sd.path) as page,
+					sd.visitor_group_id,
+					sd.created_at,
+					(true {filters_sql}) as matches_filters,
+					sum(case when sd.time_from_last_event is null or sd.time_from_last_event > {timeout_sql} then 1 else 0 end)
+						over (partition by sd.visitor_group_id order by sd.created_at rows between unbounded preceding and current row) as session_idx
+				from events sd
+				where
+					sd.event = ?::text and
+					sd.created_at >= ?::timestamp and sd.created_at < ?::timestamp and
+					sd.entity_id in ({entity_vars})
+			),
+			positioned as (
+				select
+					page,
+					visitor_group_id,
+					session_idx,
+					row_number() over (partition by visitor_group_id, session_idx order by created_at) as position,
+					bool_or(matches_filters) over (partition by visitor_group_id, session_idx) as session_matches
+				from session_events
+			),
+			anchors as (
+				select
+					visitor_group_id,
+					session_idx,
+					min(position) as anchor_position
+				from positioned
+				where session_matches and page = ?::text {entry_sql}
+				group by visitor_group_id, session_idx
+			),
+			step_pages as (
+				select
+					s.step,
+					p.page,
+					count(*) as sessions
+				from anchors a
+				cross join range(1, {steps} + 1) s(step)
+				left join positioned p on
+					p.visitor_group_id = a.visitor_group_id and
+					p.session_idx = a.session_idx and
+					p.position = {step_sql}
+				group by s.step, p.page
+			),
+			ranked as (
+				select
+					step,
+					page,
+					sessions,
+					case
+						when page is null then 2
+						when row_number() over (partition by step order by page is null, sessions desc, page) <= {limit} then 0
+						else 1
+					end as kind
+				from step_pages
+			)
+		select
+			step,
+			kind,
+			case when kind = 0 then page end as step_page,
+			sum(sessions)::bigint as session_count
+		from ranked
+		group by step, kind, step_page
+		order by step, kind, session_count desc, step_page;
+	"
+    );
+
+    let mut stmt = conn.prepare_cached(&query)?;
+    let rows = stmt.query_map(duckdb::params_from_iter(params), |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, i32>(1)?, row.get::<_, Option<String>>(2)?, row.get::<_, i64>(3)?))
+    })?;
+
+    for row in rows {
+        let (step, kind, page, sessions) = row?;
+        let sessions = u64::try_from(sessions).unwrap_or(0);
+        let index = usize::try_from(step).ok().and_then(|step| step.checked_sub(1));
+        let Some(journey_step) = index.and_then(|index| journey.steps.get_mut(index)) else {
+            continue;
+        };
+
+        match (kind, page) {
+            (0, Some(dimension_value)) => {
+                journey_step.pages.push(ReportTableRow { dimension_value, value: sessions as f64 });
+            }
+            (1, _) => journey_step.other = sessions,
+            (2, _) => journey_step.ended = sessions,
+            _ => {}
+        }
+    }
+
+    // every anchored session is counted exactly once in each step
+    if let Some(first_step) = journey.steps.first() {
+        journey.sessions =
+            first_step.pages.iter().map(|page| page.value as u64).sum::<u64>() + first_step.other + first_step.ended;
+    }
+
+    Ok(journey)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::reports::{DateRange, Dimension, DimensionFilter, FilterType, test_pageview, test_query};
+    use crate::app::{Liwan, models::Event};
+    use crate::config::Config;
+    use chrono::{TimeZone, Utc};
+
+    fn test_event(visitor: &str, path: &str, hour: u32, minute: u32) -> Event {
+        let created_at = Utc.with_ymd_and_hms(2024, 1, 1, hour, minute, 0).unwrap();
+        Event { path: Some(path.to_string()), ..test_pageview(visitor, created_at) }
+    }
+
+    #[test]
+    fn journey_report_follows_sessions_in_both_directions() {
+        let app = Liwan::new_memory(Config::default()).expect("failed to create app");
+        let events = vec![
+            test_event("a", "/", 1, 0),
+            test_event("a", "/docs", 1, 1),
+            test_event("a", "/docs/install", 1, 2),
+            // a new session of the same visitor
+            test_event("a", "/docs", 5, 0),
+            test_event("b", "/blog", 2, 0),
+            test_event("b", "/docs", 2, 1),
+            test_event("b", "/docs/install", 2, 2),
+            test_event("b", "/docs", 2, 3),
+            test_event("c", "/docs", 3, 0),
+            test_event("c", "/pricing", 3, 1),
+        ];
+        app.events.append(events.into_iter()).expect("failed to append events");
+
+        let conn = app.events_conn().expect("failed to get events conn");
+        let entities = ["entity-1".to_string()];
+        let range = DateRange {
+            start: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            end: Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap(),
+        };
+        let query = test_query(&entities, &range);
+        let row = |path: &str, value| ReportTableRow { dimension_value: format!("example.com{path}"), value };
+        let options = |direction, limit, entry_only| JourneyOptions {
+            url: "example.com/docs".to_string(),
+            direction,
+            steps: 2,
+            limit,
+            entry_only,
+        };
+        let journey = |direction, limit, entry_only| {
+            journey_report(&conn, &query, &options(direction, limit, entry_only)).expect("failed to build report")
+        };
+
+        let forward = journey(JourneyDirection::Forward, 1, false);
+        assert_eq!(forward.sessions, 4);
+        assert_eq!(forward.steps[0].pages, vec![row("/docs/install", 2.0)]);
+        assert_eq!((forward.steps[0].other, forward.steps[0].ended), (1, 1));
+        assert_eq!(forward.steps[1].pages, vec![row("/docs", 1.0)]);
+        assert_eq!((forward.steps[1].other, forward.steps[1].ended), (0, 3));
+
+        let backward = journey(JourneyDirection::Backward, 10, false);
+        assert_eq!(backward.sessions, 4);
+        assert_eq!(backward.steps[0].pages, vec![row("/", 1.0), row("/blog", 1.0)]);
+        assert_eq!(backward.steps[0].ended, 2);
+        assert!(backward.steps[1].pages.is_empty());
+
+        let entries = journey(JourneyDirection::Forward, 10, true);
+        assert_eq!(entries.sessions, 2);
+        assert_eq!(entries.steps[0].pages, vec![row("/pricing", 1.0)]);
+        assert_eq!(entries.steps.len(), 2);
+
+        // filters select whole sessions, the pages before and after the matching pageview are still followed
+        let filters = [DimensionFilter {
+            dimension: Dimension::Path,
+            filter_type: FilterType::Equal,
+            inversed: None,
+            strict: None,
+            value: Some("/blog".to_string()),
+            scope: None,
+        }];
+        let filtered = journey_report(
+            &conn,
+            &ReportQuery { filters: &filters, ..query },
+            &options(JourneyDirection::Forward, 10, false),
+        )
+        .expect("failed to build report");
+        assert_eq!(filtered.sessions, 1);
+        assert_eq!(filtered.steps[0].pages, vec![row("/docs/install", 1.0)]);
+        assert_eq!(filtered.steps[1].pages, vec![row("/docs", 1.0)]);
+    }
+}
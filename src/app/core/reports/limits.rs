@@ -0,0 +1,188 @@
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use duckdb::InterruptHandle;
+use tokio::sync::Semaphore;
+
+use crate::app::{DuckDBConn, DuckDBPool};
+use crate::config::ReportLimitsConfig;
+
+/// Reasons a report query was not answered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportLimitError {
+    /// All report query slots stayed in use for longer than the queue timeout
+    Busy,
+    /// The query ran for longer than the query timeout and was interrupted
+    TimedOut,
+}
+
+impl Display for ReportLimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Busy => write!(f, "too many report queries are running"),
+            Self::TimedOut => write!(f, "report query timed out"),
+        }
+    }
+}
+
+impl std::error::Error for ReportLimitError {}
+
+#[derive(Default)]
+enum QueryState {
+    #[default]
+    Pending,
+    Running(Arc<InterruptHandle>),
+    Finished,
+    Cancelled,
+}
+
+/// Tracks the connection a report query runs on, so it can be interrupted from another thread
+///
+/// The interrupt handle is only kept while the query runs. Once the query has finished, cancelling does nothing,
+/// so a connection that is already back in the pool is never interrupted.
+#[derive(Clone, Default)]
+struct RunningQuery(Arc<Mutex<QueryState>>);
+
+impl RunningQuery {
+    /// Mark the query as running on `conn`, unless it was cancelled before it started
+    fn start(&self, conn: &DuckDBConn) -> Result<()> {
+        let mut state = self.0.lock().expect("query state poisoned");
+        if matches!(*state, QueryState::Cancelled) {
+            return Err(ReportLimitError::TimedOut.into());
+        }
+        *state = QueryState::Running(conn.interrupt_handle());
+        Ok(())
+    }
+
+    /// Mark the query as finished, this has to happen before the connection is released
+    fn finish(&self) {
+        *self.0.lock().expect("query state poisoned") = QueryState::Finished;
+    }
+
+    /// Interrupt the query if it is running, or keep it from starting
+    fn cancel(&self) {
+        let mut state = self.0.lock().expect("query state poisoned");
+        match &*state {
+            QueryState::Running(handle) => handle.interrupt(),
+            QueryState::Pending => *state = QueryState::Cancelled,
+            QueryState::Finished | QueryState::Cancelled => {}
+        }
+    }
+}
+
+/// Cancels the report query when dropped, e.g. because the timeout elapsed or the request was aborted
+struct CancelOnDrop(RunningQuery);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+/// Runs report queries with a timeout and a limit on how many run at the same time
+pub struct ReportLimiter {
+    pool: DuckDBPool,
+    permits: Arc<Semaphore>,
+    query_timeout: Duration,
+    queue_timeout: Duration,
+}
+
+impl ReportLimiter {
+    pub fn new(pool: DuckDBPool, config: &ReportLimitsConfig) -> Self {
+        Self {
+            pool,
+            permits: Arc::new(Semaphore::new(config.max_concurrent_queries.max(1))),
+            query_timeout: Duration::from_secs(config.query_timeout_secs),
+            queue_timeout: Duration::from_secs(config.queue_timeout_secs),
+        }
+    }
+
+    /// Run a report query on a blocking thread
+    ///
+    /// The query is interrupted once the timeout elapses or the returned future is dropped, e.g. because the
+    /// client disconnected. The query slot is only released after DuckDB has actually stopped.
+    pub async fn run<T: Send + 'static>(
+        &self,
+        query: impl FnOnce(&DuckDBConn) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let permit = match tokio::time::timeout(self.queue_timeout, self.permits.clone().acquire_owned()).await {
+            Ok(permit) => permit?,
+            Err(_) => return Err(ReportLimitError::Busy.into()),
+        };
+
+        let running = RunningQuery::default();
+        let _cancel = CancelOnDrop(running.clone());
+        let pool = self.pool.clone();
+        let task = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            // getting a connection can block, so it happens on the blocking thread as well
+            let conn = pool.get()?;
+            running.start(&conn)?;
+            let result = query(&conn);
+            running.finish();
+            result
+        });
+
+        match tokio::time::timeout(self.query_timeout, task).await {
+            Ok(result) => result?,
+            Err(_) => Err(ReportLimitError::TimedOut.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::Liwan;
+    use crate::config::Config;
+
+    const SLOW_QUERY: &str = "select count(*) from range(1000000000000) a, range(1000) b";
+
+    fn limiter(query_timeout_secs: u64, max_concurrent_queries: usize) -> ReportLimiter {
+        let app = Liwan::new_memory(Config::default()).expect("failed to create app");
+        let config = ReportLimitsConfig { query_timeout_secs, max_concurrent_queries, queue_timeout_secs: 0 };
+        ReportLimiter::new(app.events_pool.clone(), &config)
+    }
+
+    fn slow_query(conn: &DuckDBConn) -> Result<i64> {
+        Ok(conn.query_row(SLOW_QUERY, [], |row| row.get(0))?)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn slow_queries_time_out() {
+        let limiter = limiter(0, 1);
+        let err = limiter.run(slow_query).await.expect_err("query should time out");
+        assert_eq!(err.downcast_ref::<ReportLimitError>(), Some(&ReportLimitError::TimedOut));
+
+        // the interrupted query releases its slot
+        let permit = tokio::time::timeout(Duration::from_secs(10), limiter.permits.acquire()).await;
+        assert!(permit.is_ok(), "query slot was not released");
+    }
+
+    #[test]
+    fn finished_queries_are_not_interrupted() {
+        let app = Liwan::new_memory(Config::default()).expect("failed to create app");
+        let conn = app.events_conn().expect("failed to get connection");
+
+        let running = RunningQuery::default();
+        running.start(&conn).expect("query should start");
+        running.finish();
+        running.cancel();
+        assert_eq!(conn.query_row("select 1", [], |row| row.get::<_, i64>(0)).expect("query failed"), 1);
+
+        let cancelled = RunningQuery::default();
+        cancelled.cancel();
+        let err = cancelled.start(&conn).expect_err("cancelled query should not start");
+        assert_eq!(err.downcast_ref::<ReportLimitError>(), Some(&ReportLimitError::TimedOut));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn busy_limiter_rejects_queries() {
+        let limiter = limiter(30, 1);
+        let _permit = limiter.permits.acquire().await.expect("failed to acquire permit");
+        let err = limiter.run(|_| Ok(())).await.expect_err("query should be rejected");
+        assert_eq!(err.downcast_ref::<ReportLimitError>(), Some(&ReportLimitError::Busy));
+    }
+}
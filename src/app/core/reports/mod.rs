@@ -1,16 +1,30 @@
+mod cache;
 mod dimension;
 mod graph;
+mod heatmap;
+mod journey;
+mod limits;
+mod movers;
+mod pivot;
+mod rollups;
 mod shared;
 mod stats;
 
+pub use cache::{CacheableReport, CachedReport, ReportCache, ReportCacheKey, ReportKind};
 pub use dimension::dimension_report;
-pub use graph::{build_graph_buckets, overall_report};
+pub use graph::{breakdown_report, build_graph_buckets, overall_report, parse_timezone};
+pub use heatmap::heatmap_report;
+pub use journey::journey_report;
+pub use limits::{ReportLimitError, ReportLimiter};
+pub use movers::movers_report;
+pub use pivot::pivot_report;
+pub(crate) use rollups::{invalidate_rollups, rebuild_entity_rollups};
+pub use rollups::{keep_rollups_updated, update_rollups};
 pub use stats::{earliest_timestamp, online_users, overall_stats};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Months, Utc, Weekday};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
 use std::fmt::{Debug, Display};
 
 pub use crate::app::models::FilterType;
@@ -41,6 +55,52 @@ impl DateRange {
     }
 }
 
+/// Range a report is compared against
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Hash, PartialEq, Eq, Default)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum Comparison {
+    /// The immediately preceding range with the same duration
+    #[default]
+    PreviousPeriod,
+    /// The same range one year earlier
+    PreviousYear,
+    /// A custom range with the same duration, compared by the offset from its start
+    Custom(DateRange),
+}
+
+impl Comparison {
+    /// Map a time of the report `range` to the corresponding time of the comparison range
+    pub fn shift(&self, range: &DateRange, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Self::PreviousPeriod => time.checked_sub_signed(range.duration()),
+            Self::PreviousYear => time.checked_sub_months(Months::new(12)),
+            Self::Custom(custom) => time.checked_sub_signed(range.start - custom.start),
+        }
+    }
+
+    /// Return the range the report `range` is compared against
+    ///
+    /// Custom ranges have to be as long as the report range, otherwise some of the shifted buckets would fall outside
+    /// of them.
+    pub fn range(&self, range: &DateRange) -> Option<DateRange> {
+        match self {
+            Self::Custom(custom) => (custom.duration() == range.duration()).then(|| custom.clone()),
+            _ => Some(DateRange { start: self.shift(range, range.start)?, end: self.shift(range, range.end)? }),
+        }
+    }
+
+    /// Return the graph buckets of the comparison range, aligned by index to the `buckets` of the report `range`
+    pub fn buckets(&self, range: &DateRange, buckets: &[DateRange]) -> Option<Vec<DateRange>> {
+        self.range(range)?;
+        buckets
+            .iter()
+            .map(|bucket| {
+                Some(DateRange { start: self.shift(range, bucket.start)?, end: self.shift(range, bucket.end)? })
+            })
+            .collect()
+    }
+}
+
 impl Display for DateRange {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{} - {}", self.start, self.end)
@@ -54,10 +114,18 @@ pub enum Metric {
     Views,
     /// Distinct visitor groups
     UniqueVisitors,
+    /// Total sessions
+    Sessions,
+    /// Average pageviews per session
+    ViewsPerSession,
     /// Percentage of sessions with one pageview
     BounceRate,
     /// Average time between pageviews in a session
     AvgTimeOnSite,
+    /// Median time between the first and last pageview of a session
+    MedianSessionDuration,
+    /// Percentage of pageviews that were the last in their session
+    ExitRate,
 }
 
 impl Display for Metric {
@@ -65,8 +133,12 @@ impl Display for Metric {
         f.write_str(match self {
             Self::Views => "views",
             Self::UniqueVisitors => "unique_visitors",
+            Self::Sessions => "sessions",
+            Self::ViewsPerSession => "views_per_session",
             Self::BounceRate => "bounce_rate",
             Self::AvgTimeOnSite => "avg_time_on_site",
+            Self::MedianSessionDuration => "median_session_duration",
+            Self::ExitRate => "exit_rate",
         })
     }
 }
@@ -74,18 +146,111 @@ impl Display for Metric {
 impl Metric {
     /// Return all report metrics in dashboard order
     pub const fn all() -> &'static [Self] {
-        &[Self::Views, Self::UniqueVisitors, Self::BounceRate, Self::AvgTimeOnSite]
+        &[
+            Self::Views,
+            Self::UniqueVisitors,
+            Self::Sessions,
+            Self::ViewsPerSession,
+            Self::BounceRate,
+            Self::AvgTimeOnSite,
+            Self::MedianSessionDuration,
+            Self::ExitRate,
+        ]
+    }
+
+    /// Return whether the metric is derived from session intervals
+    pub const fn is_session_metric(self) -> bool {
+        !matches!(self, Self::Views | Self::UniqueVisitors)
+    }
+}
+
+/// Inactivity timeout after which the next pageview of a visitor starts a new session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SessionTimeout(u32);
+
+impl SessionTimeout {
+    /// Default session timeout of 30 minutes
+    pub const DEFAULT: Self = Self(30);
+    /// Longest supported session timeout, limited by how far back session intervals are recalculated
+    pub const MAX: Self = Self(24 * 60);
+
+    /// Create a session timeout from minutes, clamped to `1..=MAX`
+    pub fn from_minutes(minutes: u32) -> Self {
+        Self(minutes.clamp(1, Self::MAX.0))
+    }
+
+    /// Return the timeout in minutes
+    pub const fn minutes(self) -> u32 {
+        self.0
+    }
+
+    /// Return the timeout as a DuckDB interval literal
+    pub(super) fn sql(self) -> String {
+        format!("interval '{} minutes'", self.0)
     }
 }
 
+impl Default for SessionTimeout {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Common inputs of a report query
+#[derive(Debug, Clone, Copy)]
+pub struct ReportQuery<'a> {
+    /// Entities to include events from
+    pub entities: &'a [String],
+    /// Event name, e.g. `pageview`
+    pub event: &'a str,
+    /// Report range
+    pub range: &'a DateRange,
+    /// Filters applied to all events
+    pub filters: &'a [DimensionFilter],
+    /// Inactivity timeout separating sessions
+    pub session_timeout: SessionTimeout,
+    /// Estimate unique visitors instead of counting them exactly, which is faster on large ranges
+    pub approximate: bool,
+    /// Whether the entities share visitor groups, so a visitor can be counted in several of them
+    pub shared_visitor_groups: bool,
+}
+
 /// Time bucket size for graph reports
 #[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
 pub enum GraphInterval {
+    /// Buckets of one minute
+    Minute,
     /// Hourly buckets
     Hour,
     /// Daily buckets
     Day,
+    /// Weekly buckets, starting on the requested [`WeekStart`]
+    Week,
+    /// Calendar month buckets
+    Month,
+    /// Calendar quarter buckets
+    Quarter,
+}
+
+/// First day of weekly graph buckets
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum WeekStart {
+    /// ISO 8601 weeks starting on Monday
+    #[default]
+    Monday,
+    /// Weeks starting on Sunday
+    Sunday,
+}
+
+impl WeekStart {
+    pub const fn weekday(self) -> Weekday {
+        match self {
+            Self::Monday => Weekday::Mon,
+            Self::Sunday => Weekday::Sun,
+        }
+    }
 }
 
 /// Dimension selected for table reports and filters
@@ -100,20 +265,40 @@ pub enum Dimension {
     UrlExit,
     /// Tracked hostname
     Fqdn,
+    /// Entity of a project the event was tracked for
+    Entity,
     /// Tracked path
     Path,
     /// Referrer domain
     Referrer,
+    /// Traffic channel (direct, organic search, paid search, social, email, referral or campaign)
+    Channel,
     /// Operating system family
     Platform,
     /// Browser family
     Browser,
+    /// Browser family and major version
+    BrowserVersion,
+    /// Operating system family and version
+    PlatformVersion,
+    /// Device class (desktop, mobile, tablet, tv or console)
+    DeviceClass,
     /// Device type
     Mobile,
     /// GeoIP country
     Country,
     /// GeoIP city
     City,
+    /// GeoIP region, e.g. a state or province
+    Region,
+    /// GeoIP continent
+    Continent,
+    /// GeoIP time zone
+    TimeZone,
+    /// Autonomous system organization of the visitor's network
+    AsnOrg,
+    /// Preferred language from the Accept-Language header
+    Language,
     /// UTM source
     UtmSource,
     /// UTM medium
@@ -137,13 +322,23 @@ impl Display for Dimension {
             Self::UrlEntry => "url_entry",
             Self::UrlExit => "url_exit",
             Self::Fqdn => "fqdn",
+            Self::Entity => "entity",
             Self::Path => "path",
             Self::Referrer => "referrer",
+            Self::Channel => "channel",
             Self::Platform => "platform",
             Self::Browser => "browser",
+            Self::BrowserVersion => "browser_version",
+            Self::PlatformVersion => "platform_version",
+            Self::DeviceClass => "device_class",
             Self::Mobile => "mobile",
             Self::Country => "country",
             Self::City => "city",
+            Self::Region => "region",
+            Self::Continent => "continent",
+            Self::TimeZone => "time_zone",
+            Self::AsnOrg => "asn_org",
+            Self::Language => "language",
             Self::UtmSource => "utm_source",
             Self::UtmMedium => "utm_medium",
             Self::UtmCampaign => "utm_campaign",
@@ -160,16 +355,26 @@ impl Dimension {
     pub const fn all() -> &'static [Self] {
         &[
             Self::Platform,
+            Self::PlatformVersion,
             Self::Browser,
+            Self::BrowserVersion,
             Self::Url,
             Self::UrlEntry,
             Self::UrlExit,
             Self::Path,
             Self::Mobile,
+            Self::DeviceClass,
             Self::Referrer,
+            Self::Channel,
             Self::City,
+            Self::Region,
             Self::Country,
+            Self::Continent,
+            Self::TimeZone,
+            Self::AsnOrg,
+            Self::Language,
             Self::Fqdn,
+            Self::Entity,
             Self::UtmCampaign,
             Self::UtmContent,
             Self::UtmMedium,
@@ -194,8 +399,176 @@ pub struct ReportGraphPoint {
 /// Graph report points ordered by bucket start
 pub type ReportGraph = Vec<ReportGraphPoint>;
 
-/// Dimension table values mapped to their metric value
-pub type ReportTable = BTreeMap<String, f64>;
+/// One series of a breakdown graph report
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportGraphSeries {
+    /// Dimension value of the series, or `None` for the series combining all other values
+    pub dimension_value: Option<String>,
+    /// Graph points of the series ordered by bucket start
+    pub data: ReportGraph,
+}
+
+/// Breakdown graph series of the top dimension values, followed by the series of all other values
+pub type ReportBreakdownGraph = Vec<ReportGraphSeries>;
+
+/// Sort direction of dimension table rows by metric value
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    /// Highest values first
+    #[default]
+    Desc,
+    /// Lowest values first
+    Asc,
+}
+
+/// Rows of a dimension table report to return
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct DimensionTableOptions {
+    /// Maximum number of rows, all rows if `None`
+    pub limit: Option<usize>,
+    /// Number of rows to skip
+    pub offset: usize,
+    pub order: SortOrder,
+    /// Only include values containing this text, ignoring case
+    pub search: Option<String>,
+    /// Combine all rows after the returned ones into [`ReportTable::other`]
+    pub include_other: bool,
+    /// Only include these values, e.g. the rows of another report
+    pub values: Option<Vec<String>>,
+}
+
+/// One row of a dimension table report
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportTableRow {
+    pub dimension_value: String,
+    pub value: f64,
+}
+
+/// Page of a dimension table report, ordered by metric value
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportTable {
+    pub rows: Vec<ReportTableRow>,
+    /// Number of rows matching the search across all pages
+    pub total_rows: u64,
+    /// Metric value of all rows after the returned ones, if requested and there are any
+    pub other: Option<f64>,
+}
+
+/// One non-empty cell of a pivot report
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportPivotCell {
+    pub row: String,
+    pub column: String,
+    pub value: f64,
+}
+
+/// Sparse matrix of a metric grouped by two dimensions
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportPivot {
+    /// Cells of the top row and column values, ordered by row and then column
+    pub cells: Vec<ReportPivotCell>,
+    /// Metric of each top row value across all columns, in rank order
+    pub row_totals: Vec<ReportTableRow>,
+    /// Metric of each top column value across all rows, in rank order
+    pub column_totals: Vec<ReportTableRow>,
+    /// Metric across all rows and columns
+    pub total: f64,
+}
+
+/// Ranking of a movers report
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MoversOrder {
+    /// Largest absolute change first
+    #[default]
+    Absolute,
+    /// New values first, followed by the largest relative change
+    Relative,
+}
+
+/// Rows of a movers report to return
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct MoversOptions {
+    pub limit: usize,
+    /// Minimum views in either range, or the metric itself for counts
+    pub min_volume: u64,
+    pub order: MoversOrder,
+}
+
+/// A dimension value whose metric changed between the report and the comparison range
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportMover {
+    pub dimension_value: String,
+    pub value: f64,
+    pub comparison: f64,
+    /// `value - comparison`
+    pub change: f64,
+    /// Change relative to `comparison`, `None` if the comparison value is zero
+    pub relative_change: Option<f64>,
+    /// Whether the value has no events in the comparison range
+    pub is_new: bool,
+}
+
+/// Dimension values with the largest changes, in the requested order
+pub type ReportMovers = Vec<ReportMover>;
+
+/// Direction of a journey report from its page
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum JourneyDirection {
+    /// Pages viewed after the page
+    #[default]
+    Forward,
+    /// Pages that led to the page
+    Backward,
+}
+
+/// Page and steps of a journey report
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct JourneyOptions {
+    /// URL (`fqdn` and path) of the page
+    pub url: String,
+    pub direction: JourneyDirection,
+    /// Number of pageviews to follow from the page
+    pub steps: usize,
+    /// Number of top pages per step, all other pages are combined
+    pub limit: usize,
+    /// Only follow sessions that start on the page
+    pub entry_only: bool,
+}
+
+/// Pages viewed a number of steps away from the page of a journey report
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportJourneyStep {
+    /// Distance from the page, starting at 1
+    pub step: usize,
+    /// Sessions by the top pages viewed at this step
+    pub pages: Vec<ReportTableRow>,
+    /// Sessions viewing any other page at this step
+    pub other: u64,
+    /// Sessions that ended (or started, for backward journeys) before this step
+    pub ended: u64,
+}
+
+/// Navigation before or after a page within sessions
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportJourney {
+    /// Sessions viewing the page
+    pub sessions: u64,
+    pub steps: Vec<ReportJourneyStep>,
+}
+
+/// Metric values by local day of week and hour of day, as 7 rows starting on Monday with 24 hourly values each
+pub type ReportHeatmap = Vec<Vec<f64>>;
 
 /// Overall metric summary for a report range
 #[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default)]
@@ -209,6 +582,43 @@ pub struct ReportStats {
     pub bounce_rate: Option<f64>,
     /// Average time on site, when session metrics are available
     pub avg_time_on_site: Option<f64>,
+    /// Total sessions, when session metrics are available
+    pub sessions: Option<u64>,
+    /// Average pageviews per session, when session metrics are available
+    pub views_per_session: Option<f64>,
+    /// Median session duration in seconds, when session metrics are available
+    pub median_session_duration: Option<f64>,
+    /// Exit rate, when session metrics are available
+    pub exit_rate: Option<f64>,
+}
+
+impl ReportStats {
+    /// Clear the value of a hidden session metric
+    pub fn clear_metric(&mut self, metric: Metric) {
+        match metric {
+            Metric::Views | Metric::UniqueVisitors => {}
+            Metric::Sessions => self.sessions = None,
+            Metric::ViewsPerSession => self.views_per_session = None,
+            Metric::BounceRate => self.bounce_rate = None,
+            Metric::AvgTimeOnSite => self.avg_time_on_site = None,
+            Metric::MedianSessionDuration => self.median_session_duration = None,
+            Metric::ExitRate => self.exit_rate = None,
+        }
+    }
+}
+
+/// What a dimension filter is matched against
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, Hash, Eq, PartialEq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterScope {
+    /// Match each event individually
+    #[default]
+    Event,
+    /// Match events whose session matches, e.g. sessions that entered on a URL
+    ///
+    /// URL dimensions match the entry page (`url_exit` the exit page), attribution dimensions the first event of
+    /// the session. Sessions are split by the session timeout of the report, like the session metrics.
+    Session,
 }
 
 /// Filter applied to a dashboard report query
@@ -220,4 +630,67 @@ pub struct DimensionFilter {
     pub(super) inversed: Option<bool>,
     pub(super) strict: Option<bool>,
     pub(super) value: Option<String>,
+    pub(super) scope: Option<FilterScope>,
+}
+
+impl DimensionFilter {
+    /// Return whether the filter is matched against sessions
+    pub fn is_session_scoped(&self) -> bool {
+        self.scope.unwrap_or_default() == FilterScope::Session
+    }
+}
+
+/// A pageview of `visitor` on `example.com/`, the base event of the report tests
+///
+/// Tests override the fields they care about with struct update syntax.
+#[cfg(test)]
+pub(crate) fn test_pageview(visitor: &str, created_at: DateTime<Utc>) -> crate::app::models::Event {
+    crate::app::models::Event {
+        entity_id: "entity-1".to_string(),
+        visitor_group_id: visitor.to_string(),
+        event: "pageview".to_string(),
+        created_at,
+        fqdn: Some("example.com".to_string()),
+        path: Some("/".to_string()),
+        referrer: None,
+        platform: None,
+        browser: None,
+        mobile: None,
+        country: None,
+        city: None,
+        utm_source: None,
+        utm_medium: None,
+        utm_campaign: None,
+        utm_content: None,
+        utm_term: None,
+        screen_width: None,
+        orientation: None,
+        browser_version: None,
+        platform_version: None,
+        device_class: None,
+        language: None,
+        channel: None,
+        region: None,
+        continent: None,
+        time_zone: None,
+        asn: None,
+        asn_org: None,
+        track_sessions: true,
+    }
+}
+
+/// An unfiltered pageview report of `entities` over `range` with the default session timeout
+///
+/// Like [`test_pageview`], tests override the fields they care about with struct update syntax.
+#[cfg(test)]
+pub(crate) fn test_query<'a>(entities: &'a [String], range: &'a DateRange) -> ReportQuery<'a> {
+    ReportQuery {
+        entities,
+        event: "pageview",
+        range,
+        filters: &[],
+        session_timeout: SessionTimeout::DEFAULT,
+        approximate: false,
+        shared_visitor_groups: false,
+    }
 }
@@ -0,0 +1,190 @@
+use crate::app::DuckDBConn;
+use crate::utils::duckdb::{ParamVec, repeat_vars};
+use anyhow::Result;
+
+use super::shared::{
+    build_filter_clause, dimension_column_sql, metric_aggregate_sql, rank_aggregate_sql, scope_filters_sql,
+    session_time_column_sql,
+};
+use super::{DateRange, Dimension, Metric, MoversOptions, MoversOrder, ReportMover, ReportMovers, ReportQuery};
+
+/// Build a report of the dimension values whose metric changed the most between `report.range` and
+/// `comparison_range`
+///
+/// Values that only occur in one of the ranges are included with a metric of zero in the other one. Values with
+/// less than `options.min_volume` views (or events of count metrics) in both ranges are left out.
+pub fn movers_report(
+    conn: &DuckDBConn,
+    report: &ReportQuery,
+    comparison_range: &DateRange,
+    dimension: &Dimension,
+    metric: &Metric,
+    options: &MoversOptions,
+) -> Result<ReportMovers> {
+    if report.entities.is_empty() || options.limit == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut params = ParamVec::new();
+    let entity_vars = repeat_vars(report.entities.len());
+    let metric_column = metric_aggregate_sql(*metric, "sd", report);
+    let volume_column = rank_aggregate_sql(*metric, "sd", report);
+    let session_time_column = session_time_column_sql(&[*metric], "sd", report.session_timeout);
+    let dimension_column = dimension_column_sql(*dimension);
+
+    let (filters_sql, filters_params) = build_filter_clause(report, "sd")?;
+    let comparison_report = ReportQuery { range: comparison_range, ..*report };
+    let (_, comparison_filters_params) = build_filter_clause(&comparison_report, "sd")?;
+    let filters_sql = scope_filters_sql(filters_sql, *dimension, report.session_timeout);
+    let (min_volume, limit) = (options.min_volume, options.limit);
+
+    for (range, filters_params) in [(report.range, filters_params), (comparison_range, comparison_filters_params)] {
+        params.push(report.event);
+        params.push(range.start);
+        params.push(range.end);
+        params.extend(report.entities);
+        params.extend_from_params(filters_params);
+    }
+
+    // session times are measured within the queried events, so each range is aggregated on its own
+    let values_sql = format!(
+        "--sql
+				with session_data as (
+					select
+						coalesce({dimension_column}, 'Unknown') as dimension_value,
+						visitor_group_id,
+						created_at,
+						time_from_last_event,
+						time_to_next_event
+						{session_time_column}
+					from events sd
+					where
+						sd.event = ?::text and
+						sd.created_at >= ?::timestamp and sd.created_at < ?::timestamp and
+						sd.entity_id in ({entity_vars})
+						{filters_sql}
+				)
+				select
+					dimension_value,
+					{metric_column} as metric_value,
+					{volume_column} as volume
+				from session_data sd
+				group by dimension_value"
+    );
+
+    let order_sql = match options.order {
+        MoversOrder::Absolute => "abs(change) desc",
+        MoversOrder::Relative => "is_new desc, abs(relative_change) desc nulls last, abs(change) desc",
+    };
+
+    let query = format!(
+        "--sql
+		with
+			current_values as (
+				{values_sql}
+			),
+			comparison_values as (
+				{values_sql}
+			),
+			movers as (
+				select
+					coalesce(cv.dimension_value, pv.dimension_value) as dimension_value,
+					coalesce(cv.metric_value, 0)::double as value,
+					coalesce(pv.metric_value, 0)::double as comparison,
+					coalesce(cv.metric_value, 0)::double - coalesce(pv.metric_value, 0)::double as change,
+					(coalesce(cv.metric_value, 0)::double - pv.metric_value::double) / nullif(pv.metric_value::double, 0) as relative_change,
+					coalesce(pv.volume, 0) = 0 as is_new
+				from current_values cv
+				full outer join comparison_values pv on cv.dimension_value = pv.dimension_value
+				where greatest(coalesce(cv.volume, 0), coalesce(pv.volume, 0)) >= {min_volume}
+			)
+		select
+			dimension_value,
+			value,
+			comparison,
+			change,
+			relative_change,
+			is_new
+		from movers
+		where change != 0
+		order by {order_sql}, dimension_value
+		limit {limit};
+	"
+    );
+
+    let mut stmt = conn.prepare_cached(&query)?;
+    let rows = stmt.query_map(duckdb::params_from_iter(params), |row| {
+        Ok(ReportMover {
+            dimension_value: row.get(0)?,
+            value: row.get(1)?,
+            comparison: row.get(2)?,
+            change: row.get(3)?,
+            relative_change: row.get(4)?,
+            is_new: row.get(5)?,
+        })
+    })?;
+
+    Ok(rows.collect::<Result<Vec<_>, duckdb::Error>>()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::reports::{test_pageview, test_query};
+    use crate::app::{Liwan, models::Event};
+    use crate::config::Config;
+    use chrono::{TimeZone, Utc};
+
+    fn test_event(visitor: &str, path: &str, day: u32) -> Event {
+        let created_at = Utc.with_ymd_and_hms(2024, 1, day, 12, 0, 0).unwrap();
+        Event { path: Some(path.to_string()), ..test_pageview(visitor, created_at) }
+    }
+
+    #[test]
+    fn movers_report_ranks_changes_and_new_values() {
+        let app = Liwan::new_memory(Config::default()).expect("failed to create app");
+        let mut events = Vec::new();
+        // comparison range: the 1st, report range: the 2nd
+        for (path, before, after) in
+            [("/", 10, 12), ("/docs", 2, 6), ("/launch", 0, 3), ("/old", 4, 0), ("/rare", 0, 1)]
+        {
+            events.extend((0..before).map(|idx| test_event(&format!("{path}-{idx}"), path, 1)));
+            events.extend((0..after).map(|idx| test_event(&format!("{path}-{idx}"), path, 2)));
+        }
+        app.events.append(events.into_iter()).expect("failed to append events");
+
+        let conn = app.events_conn().expect("failed to get events conn");
+        let entities = ["entity-1".to_string()];
+        let range = DateRange {
+            start: Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap(),
+            end: Utc.with_ymd_and_hms(2024, 1, 3, 0, 0, 0).unwrap(),
+        };
+        let comparison_range = DateRange {
+            start: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            end: Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap(),
+        };
+        let query = test_query(&entities, &range);
+        let movers = |order| {
+            let options = MoversOptions { limit: 10, min_volume: 2, order };
+            movers_report(&conn, &query, &comparison_range, &Dimension::Path, &Metric::Views, &options)
+                .expect("failed to build report")
+                .into_iter()
+                .map(|mover| (mover.dimension_value, mover.change, mover.relative_change, mover.is_new))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            movers(MoversOrder::Absolute),
+            vec![
+                ("/docs".to_string(), 4.0, Some(2.0), false),
+                ("/old".to_string(), -4.0, Some(-1.0), false),
+                ("/launch".to_string(), 3.0, None, true),
+                ("/".to_string(), 2.0, Some(0.2), false),
+            ]
+        );
+
+        let relative = movers(MoversOrder::Relative);
+        let order = relative.iter().map(|(value, ..)| value.as_str()).collect::<Vec<_>>();
+        assert_eq!(order, vec!["/launch", "/docs", "/old", "/"]);
+    }
+}
@@ -0,0 +1,181 @@
+use crate::app::DuckDBConn;
+use crate::utils::duckdb::{ParamVec, repeat_vars};
+use anyhow::{Result, bail};
+
+use super::shared::{
+    build_filter_clause, dimension_column_sql, metric_aggregate_sql, rank_aggregate_sql, scope_filters_sql,
+    session_time_column_sql,
+};
+use super::{Dimension, Metric, ReportPivot, ReportPivotCell, ReportQuery, ReportTableRow};
+
+/// Build a pivot report of a metric grouped by two dimensions
+///
+/// Only the top `limit` values of each dimension are included, ranked like breakdown graphs. Row, column and
+/// overall totals are aggregated over the events themselves, so they are correct for metrics that can't be summed.
+pub fn pivot_report(
+    conn: &DuckDBConn,
+    report: &ReportQuery,
+    row_dimension: &Dimension,
+    column_dimension: &Dimension,
+    metric: &Metric,
+    limit: usize,
+) -> Result<ReportPivot> {
+    if row_dimension == column_dimension {
+        bail!("Pivot reports need two different dimensions");
+    }
+
+    if report.entities.is_empty() {
+        return Ok(ReportPivot::default());
+    }
+
+    let mut params = ParamVec::new();
+    let entity_vars = repeat_vars(report.entities.len());
+    let (filters_sql, filters_params) = build_filter_clause(report, "sd")?;
+    let filters_sql = scope_filters_sql(filters_sql, *row_dimension, report.session_timeout);
+    let filters_sql = scope_filters_sql(filters_sql, *column_dimension, report.session_timeout);
+
+    let metric_column = metric_aggregate_sql(*metric, "sd", report);
+    let rank_column = rank_aggregate_sql(*metric, "sd", report);
+    let session_time_column = session_time_column_sql(&[*metric], "sd", report.session_timeout);
+    let row_column = dimension_column_sql(*row_dimension);
+    let column_column = dimension_column_sql(*column_dimension);
+
+    params.push(report.event);
+    params.push(report.range.start);
+    params.push(report.range.end);
+    params.extend(report.entities);
+    params.extend_from_params(filters_params);
+
+    let query = format!(
+        "--sql
+		with
+			session_data as (
+				select
+					coalesce({row_column}, 'Unknown') as row_value,
+					coalesce({column_column}, 'Unknown') as column_value,
+					visitor_group_id,
+					created_at,
+					time_from_last_event,
+					time_to_next_event
+					{session_time_column}
+				from events sd
+				where
+					sd.event = ?::text and
+					sd.created_at >= ?::timestamp and sd.created_at < ?::timestamp and
+					sd.entity_id in ({entity_vars})
+					{filters_sql}
+			),
+			pivot_events as (
+				select
+					row_value,
+					column_value,
+					grouping(row_value) as row_grouped,
+					grouping(column_value) as column_grouped,
+					{metric_column} as metric_value,
+					{rank_column} as rank_value
+				from session_data sd
+				group by grouping sets ((row_value, column_value), (row_value), (column_value), ())
+			),
+			top_rows as (
+				select
+					row_value,
+					row_number() over (order by rank_value desc, row_value) as row_position
+				from pivot_events
+				where row_grouped = 0 and column_grouped = 1
+				order by row_position
+				limit {limit}
+			),
+			top_columns as (
+				select
+					column_value,
+					row_number() over (order by rank_value desc, column_value) as column_position
+				from pivot_events
+				where row_grouped = 1 and column_grouped = 0
+				order by column_position
+				limit {limit}
+			)
+		select
+			p.row_value,
+			p.column_value,
+			p.metric_value::double
+		from pivot_events p
+		left join top_rows tr on p.row_value = tr.row_value
+		left join top_columns tc on p.column_value = tc.column_value
+		where
+			(p.row_grouped = 1 or tr.row_position is not null) and
+			(p.column_grouped = 1 or tc.column_position is not null)
+		order by tr.row_position nulls first, tc.column_position nulls first;
+	"
+    );
+
+    let mut stmt = conn.prepare_cached(&query)?;
+    let rows = stmt.query_map(duckdb::params_from_iter(params), |row| {
+        Ok((row.get::<_, Option<String>>(0)?, row.get::<_, Option<String>>(1)?, row.get::<_, Option<f64>>(2)?))
+    })?;
+
+    let mut pivot = ReportPivot::default();
+    for row in rows {
+        let (row_value, column_value, value) = row?;
+        let value = value.unwrap_or(0.0);
+        match (row_value, column_value) {
+            (Some(row), Some(column)) => pivot.cells.push(ReportPivotCell { row, column, value }),
+            (Some(dimension_value), None) => pivot.row_totals.push(ReportTableRow { dimension_value, value }),
+            (None, Some(dimension_value)) => pivot.column_totals.push(ReportTableRow { dimension_value, value }),
+            (None, None) => pivot.total = value,
+        }
+    }
+
+    Ok(pivot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::reports::{DateRange, test_pageview, test_query};
+    use crate::app::{Liwan, models::Event};
+    use crate::config::Config;
+    use chrono::{TimeZone, Utc};
+
+    fn test_event(visitor: &str, country: &str, path: &str, hour: u32) -> Event {
+        let created_at = Utc.with_ymd_and_hms(2024, 1, 1, hour, 0, 0).unwrap();
+        Event { path: Some(path.to_string()), country: Some(country.to_string()), ..test_pageview(visitor, created_at) }
+    }
+
+    #[test]
+    fn pivot_report_includes_cells_and_totals_of_top_values() {
+        let app = Liwan::new_memory(Config::default()).expect("failed to create app");
+        let events = vec![
+            test_event("a", "DE", "/", 1),
+            test_event("a", "DE", "/pricing", 2),
+            test_event("b", "DE", "/", 3),
+            test_event("c", "FR", "/", 4),
+            test_event("c", "FR", "/pricing", 6),
+            test_event("d", "US", "/blog", 5),
+        ];
+        app.events.append(events.into_iter()).expect("failed to append events");
+
+        let conn = app.events_conn().expect("failed to get events conn");
+        let entities = ["entity-1".to_string()];
+        let range = DateRange {
+            start: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            end: Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap(),
+        };
+        let query = test_query(&entities, &range);
+
+        let pivot = pivot_report(&conn, &query, &Dimension::Country, &Dimension::Path, &Metric::UniqueVisitors, 2)
+            .expect("failed to build pivot report");
+
+        let cell = |row: &str, column: &str, value| ReportPivotCell { row: row.into(), column: column.into(), value };
+        let total = |dimension_value: &str, value| ReportTableRow { dimension_value: dimension_value.into(), value };
+        assert_eq!(
+            pivot.cells,
+            vec![cell("DE", "/", 2.0), cell("DE", "/pricing", 1.0), cell("FR", "/", 1.0), cell("FR", "/pricing", 1.0)]
+        );
+        // visitor a viewed both paths but is only counted once per row
+        assert_eq!(pivot.row_totals, vec![total("DE", 2.0), total("FR", 1.0)]);
+        assert_eq!(pivot.column_totals, vec![total("/", 3.0), total("/pricing", 2.0)]);
+        assert_eq!(pivot.total, 4.0);
+
+        assert!(pivot_report(&conn, &query, &Dimension::Path, &Dimension::Path, &Metric::Views, 2).is_err());
+    }
+}
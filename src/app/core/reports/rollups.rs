@@ -0,0 +1,721 @@
+use crate::app::DuckDBPool;
+use crate::utils::duckdb::{ParamVec, repeat_vars};
+use anyhow::Result;
+use chrono::{DateTime, DurationRound, Timelike, Utc};
+use duckdb::{Connection, params};
+
+use super::shared::{dimension_column_sql, dimension_page_sql, push_page_params, query_dimension_page};
+use super::{
+    DateRange, Dimension, DimensionTableOptions, Metric, ReportGraph, ReportGraphPoint, ReportQuery, ReportTable,
+};
+
+/// Size of the time buckets events are pre-aggregated into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RollupGranularity {
+    Hour,
+    Day,
+}
+
+impl RollupGranularity {
+    /// Coarsest granularity first, so reports read as few rows as possible
+    const ALL: [Self; 2] = [Self::Day, Self::Hour];
+
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Hour => "hour",
+            Self::Day => "day",
+        }
+    }
+
+    fn duration(self) -> chrono::Duration {
+        match self {
+            Self::Hour => chrono::Duration::hours(1),
+            Self::Day => chrono::Duration::days(1),
+        }
+    }
+
+    fn truncate(self, time: DateTime<Utc>) -> DateTime<Utc> {
+        time.duration_trunc(self.duration()).unwrap_or(time)
+    }
+
+    /// Dimensions stored at this granularity besides the entity totals
+    fn dimensions(self, sketches: bool) -> Vec<Dimension> {
+        match (self, sketches) {
+            (Self::Day, false) => rollup_dimensions().collect(),
+            _ => COMPACT_DIMENSIONS.to_vec(),
+        }
+    }
+
+    fn is_aligned(self, time: DateTime<Utc>) -> bool {
+        let hour_aligned = time.minute() == 0 && time.second() == 0 && time.nanosecond() == 0;
+        match self {
+            Self::Hour => hour_aligned,
+            Self::Day => hour_aligned && time.hour() == 0,
+        }
+    }
+}
+
+/// Whether a dimension is stored in rollups
+///
+/// Entry and exit pages depend on the session timeout, so they aren't rolled up. Entities are already a column of
+/// every rollup row, but entity reports still read the events table.
+const fn is_rolled_up(dimension: Dimension) -> bool {
+    !matches!(dimension, Dimension::UrlEntry | Dimension::UrlExit | Dimension::Entity)
+}
+
+/// Dimensions stored in rollups
+fn rollup_dimensions() -> impl Iterator<Item = Dimension> {
+    Dimension::all().iter().copied().filter(|dimension| is_rolled_up(*dimension))
+}
+
+/// Dimensions stored in hourly rollups and visitor sketches, besides the entity totals
+///
+/// Hourly buckets and sketches (up to one row per register for every bucket and dimension value) multiply the rows
+/// per dimension value, so they are limited to the most commonly viewed dimensions to keep them smaller than the
+/// events they summarize. Sketches are also only stored for daily buckets.
+const COMPACT_DIMENSIONS: [Dimension; 6] =
+    [Dimension::Url, Dimension::Path, Dimension::Referrer, Dimension::Channel, Dimension::Country, Dimension::Browser];
+
+/// HyperLogLog precision, sketches use `2^SKETCH_PRECISION` registers (~3% standard error)
+const SKETCH_PRECISION: u32 = 10;
+const SKETCH_REGISTERS: u32 = 1 << SKETCH_PRECISION;
+
+/// HyperLogLog register and rank columns of a visitor group
+fn sketch_columns_sql(alias: &str) -> String {
+    let mask = SKETCH_REGISTERS - 1;
+    let bits = 64 - SKETCH_PRECISION;
+    let hash = format!("hash({alias}.visitor_group_id)");
+    format!(
+        "({hash} & {mask})::usmallint as register, coalesce(({bits} - floor(log2(nullif({hash} >> {SKETCH_PRECISION}, 0))))::utinyint, {}) as rank",
+        bits + 1
+    )
+}
+
+/// HyperLogLog estimate over one `(register, rank)` row per register, `alias` is the registers table
+fn sketch_estimate_sql(alias: &str) -> String {
+    let registers = f64::from(SKETCH_REGISTERS);
+    let alpha = 0.7213 / (1.0 + 1.079 / registers);
+    // double literals, decimal ones overflow when multiplied
+    let m = format!("{registers}::double");
+    let raw = format!("{alpha}::double * {m} * {m} / (sum(pow(2, -{alias}.rank::integer)) + {m} - count(*))");
+
+    // linear counting for small cardinalities
+    format!(
+        "round(case when count(*) < {m} and {raw} <= 2.5 * {m} then {m} * ln({m} / ({m} - count(*))) else {raw} end)"
+    )
+}
+
+fn insert_rollups_sql(granularity: RollupGranularity, single_entity: bool, sketches: bool) -> String {
+    let dimensions = granularity.dimensions(sketches);
+    let granularity = granularity.as_str();
+    let dimension_columns = dimensions
+        .iter()
+        .map(|dimension| format!("coalesce({}, 'Unknown') as \"{dimension}\"", dimension_column_sql(*dimension)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let dimension_names = dimensions.iter().map(|dimension| format!("\"{dimension}\"")).collect::<Vec<_>>().join(", ");
+    let entity_sql = if single_entity { "and e.entity_id = ?" } else { "" };
+    let sketch_columns = sketch_columns_sql("e");
+    let (table, aggregates, group_by) = if sketches {
+        ("rollup_sketches", "register, max(rank)", ", register")
+    } else {
+        ("rollups", "count(*), count(distinct visitor_group_id)", "")
+    };
+
+    format!(
+        "--sql
+		insert into {table}
+		with
+			base as (
+				select
+					date_trunc('{granularity}', e.created_at) as bucket,
+					e.entity_id,
+					e.event,
+					e.visitor_group_id,
+					{sketch_columns},
+					{dimension_columns}
+				from events e
+				where e.created_at >= ?::timestamp and e.created_at < ?::timestamp {entity_sql}
+			)
+		select '{granularity}', bucket, entity_id, event, '', null, {aggregates}
+		from base
+		group by bucket, entity_id, event{group_by}
+		union all
+		select '{granularity}', bucket, entity_id, event, dimension, dimension_value, {aggregates}
+		from (unpivot base on {dimension_names} into name dimension value dimension_value)
+		group by bucket, entity_id, event, dimension, dimension_value{group_by};
+	"
+    )
+}
+
+/// Tables kept in sync with the rollup watermark, and whether they store visitor sketches
+const ROLLUP_TABLES: [(&str, bool); 2] = [("rollups", false), ("rollup_sketches", true)];
+
+/// Rollup tables stored at a granularity, sketches are only stored for daily buckets
+fn rollup_tables(granularity: RollupGranularity) -> impl Iterator<Item = (&'static str, bool)> {
+    ROLLUP_TABLES.into_iter().filter(move |(_, sketches)| !sketches || granularity == RollupGranularity::Day)
+}
+
+fn rollup_watermark(conn: &Connection, granularity: RollupGranularity) -> duckdb::Result<Option<DateTime<Utc>>> {
+    conn.query_row("select max(watermark) from rollup_state where granularity = ?", [granularity.as_str()], |row| {
+        row.get(0)
+    })
+}
+
+/// Number of events in a range, to detect events committed while rollups were built
+const COUNT_EVENTS_SQL: &str =
+    "(select count(*) from events where created_at >= ?::timestamp and created_at < ?::timestamp)";
+
+/// Aggregate events that aren't covered by rollups yet, up to the start of the current hour or day
+pub fn update_rollups(conn: &mut Connection) -> Result<()> {
+    for granularity in RollupGranularity::ALL {
+        let upto = granularity.truncate(Utc::now());
+        let watermark = rollup_watermark(conn, granularity)?;
+        let from = match watermark {
+            Some(watermark) => watermark,
+            None => conn
+                .query_row("select min(created_at) from events", [], |row| row.get::<_, Option<DateTime<Utc>>>(0))?
+                .map_or(upto, |earliest| granularity.truncate(earliest)),
+        };
+
+        // number of events the rollups were built from
+        let mut aggregated = 0;
+        if from < upto {
+            let tx = conn.transaction()?;
+            for (table, sketches) in rollup_tables(granularity) {
+                tx.execute(
+                    &format!("delete from {table} where granularity = ? and bucket >= ?::timestamp"),
+                    params![granularity.as_str(), from],
+                )?;
+                tx.execute(&insert_rollups_sql(granularity, false, sketches), params![from, upto])?;
+            }
+            aggregated =
+                tx.query_row(&format!("select {COUNT_EVENTS_SQL}"), params![from, upto], |row| row.get::<_, u64>(0))?;
+            tx.commit()?;
+        }
+
+        // only advance the watermark if it wasn't lowered by backfilled events in the meantime, and no events in the
+        // range were committed after the rollups were built. Those wouldn't lower the watermark since it's still
+        // before them.
+        let updated = match watermark {
+            Some(watermark) => conn.execute(
+                &format!(
+                    "update rollup_state set watermark = ?::timestamp where granularity = ? and watermark = ?::timestamp and {COUNT_EVENTS_SQL} = ?"
+                ),
+                params![upto, granularity.as_str(), watermark, from, upto, aggregated],
+            )?,
+            None => conn.execute(
+                &format!(
+                    "insert into rollup_state (granularity, watermark) select ?, ?::timestamp where {COUNT_EVENTS_SQL} = ? on conflict do nothing"
+                ),
+                params![granularity.as_str(), upto, from, upto, aggregated],
+            )?,
+        };
+
+        if updated == 0 {
+            tracing::debug!(
+                granularity = granularity.as_str(),
+                "Rollup watermark changed during update, retrying later"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Make sure events at or after `from` are aggregated again on the next rollup update
+pub(crate) fn invalidate_rollups(conn: &Connection, from: DateTime<Utc>) -> duckdb::Result<()> {
+    conn.execute(
+        "update rollup_state set watermark = ?::timestamp where watermark > ?::timestamp",
+        params![RollupGranularity::Day.truncate(from), from],
+    )?;
+    Ok(())
+}
+
+/// Rebuild all rollups of an entity, e.g. after its events were pruned
+pub(crate) fn rebuild_entity_rollups(conn: &mut Connection, entity_id: &str) -> Result<()> {
+    let earliest: Option<DateTime<Utc>> =
+        conn.query_row("select min(created_at) from events where entity_id = ?", [entity_id], |row| row.get(0))?;
+
+    for granularity in RollupGranularity::ALL {
+        let Some(watermark) = rollup_watermark(conn, granularity)? else {
+            continue;
+        };
+
+        let tx = conn.transaction()?;
+        for (table, sketches) in rollup_tables(granularity) {
+            tx.execute(
+                &format!("delete from {table} where granularity = ? and entity_id = ?"),
+                params![granularity.as_str(), entity_id],
+            )?;
+            if let Some(earliest) = earliest {
+                tx.execute(
+                    &insert_rollups_sql(granularity, true, sketches),
+                    params![granularity.truncate(earliest), watermark, entity_id],
+                )?;
+            }
+        }
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+/// Keep rollups up to date, runs every five minutes
+pub async fn keep_rollups_updated(pool: DuckDBPool) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(5 * 60));
+
+    loop {
+        interval.tick().await;
+        let pool = pool.clone();
+        let res = tokio::task::spawn_blocking(move || update_rollups(&mut *pool.get()?)).await;
+        match res {
+            Ok(Err(err)) => tracing::error!(error = ?err, "Failed to update rollups"),
+            Err(err) => tracing::error!(error = ?err, "Rollup update task panicked"),
+            Ok(Ok(())) => {}
+        }
+    }
+}
+
+/// Find a rollup granularity storing `dimension` whose buckets fit the boundaries of all `bins` before its watermark
+fn select_granularity(
+    conn: &Connection,
+    report: &ReportQuery,
+    dimension: Option<Dimension>,
+    metric: Metric,
+    bins: &[DateRange],
+) -> Result<Option<(RollupGranularity, DateTime<Utc>)>> {
+    if !report.filters.is_empty() || !matches!(metric, Metric::Views | Metric::UniqueVisitors) {
+        return Ok(None);
+    }
+
+    // exact visitor counts are summed over the rollups of each entity, which counts a visitor seen by several
+    // entities more than once if they share visitor groups. Sketches are merged per register, so they don't.
+    if metric == Metric::UniqueVisitors
+        && !report.approximate
+        && report.shared_visitor_groups
+        && report.entities.len() > 1
+    {
+        return Ok(None);
+    }
+
+    let boundaries = bins
+        .iter()
+        .flat_map(|bin| [bin.start, bin.end])
+        .chain([report.range.start, report.range.end])
+        .collect::<Vec<_>>();
+
+    for granularity in RollupGranularity::ALL {
+        if let Some(dimension) = dimension
+            && !granularity.dimensions(false).contains(&dimension)
+        {
+            continue;
+        }
+
+        // visitor groups rotate at a local hour, not at UTC midnight, so a visitor can be in several daily buckets.
+        // Exact visitor counts are only read from bins of a single daily bucket, daily sketches can be merged.
+        if metric == Metric::UniqueVisitors
+            && (granularity != RollupGranularity::Day
+                || (!report.approximate && bins.iter().any(|bin| bin.end - bin.start > granularity.duration())))
+        {
+            continue;
+        }
+
+        let Some(watermark) = rollup_watermark(conn, granularity)? else {
+            continue;
+        };
+
+        // boundaries after the watermark are answered from raw events
+        if boundaries.iter().all(|time| *time >= watermark || granularity.is_aligned(*time)) {
+            return Ok(Some((granularity, watermark)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Rollup rows before the watermark and raw events after it, selecting `rollup_columns` and `event_columns`
+fn source_sql(
+    table: &str,
+    granularity: RollupGranularity,
+    entity_count: usize,
+    rollup_columns: &str,
+    event_columns: &str,
+) -> String {
+    let granularity = granularity.as_str();
+    let entity_vars = repeat_vars(entity_count);
+
+    format!(
+        "--sql
+				select {rollup_columns}
+				from {table} r
+				where
+					r.granularity = '{granularity}' and r.dimension = ? and
+					r.event = ?::text and
+					r.entity_id in ({entity_vars}) and
+					r.bucket >= ?::timestamp and r.bucket < ?::timestamp and r.bucket < ?::timestamp
+				union all
+				select {event_columns}
+				from events e
+				where
+					e.event = ?::text and
+					e.entity_id in ({entity_vars}) and
+					e.created_at >= greatest(?::timestamp, ?::timestamp) and e.created_at < ?::timestamp"
+    )
+}
+
+fn source_params<'a>(report: &ReportQuery<'a>, dimension: String, watermark: DateTime<Utc>) -> ParamVec<'a> {
+    let mut params = ParamVec::new();
+    params.push(dimension);
+    params.push(report.event);
+    params.extend(report.entities);
+    params.push(report.range.start);
+    params.push(report.range.end);
+    params.push(watermark);
+    params.push(report.event);
+    params.extend(report.entities);
+    params.push(report.range.start);
+    params.push(watermark);
+    params.push(report.range.end);
+    params
+}
+
+/// Aggregate `metric` over the `from` table, grouped by `group_column`
+fn aggregate_sql(metric: Metric, sketches: bool, group_column: &str, from: &str) -> String {
+    if sketches {
+        let estimate = sketch_estimate_sql("sk");
+        return format!(
+            "select sk.{group_column}, {estimate} as metric_value from (select sd.{group_column}, sd.register, max(sd.rank) as rank from {from} sd group by sd.{group_column}, sd.register) sk group by sk.{group_column}"
+        );
+    }
+
+    let metric_sql = match metric {
+        Metric::UniqueVisitors => "(sum(sd.visitors) + count(distinct sd.visitor_group_id))::double",
+        _ => "sum(sd.views)::double",
+    };
+    format!("select sd.{group_column}, {metric_sql} as metric_value from {from} sd group by sd.{group_column}")
+}
+
+/// Build a graph report from rollups, if the report allows it
+pub(super) fn overall_report(
+    conn: &Connection,
+    report: &ReportQuery,
+    buckets: &[DateRange],
+    metric: Metric,
+) -> Result<Option<ReportGraph>> {
+    let Some((granularity, watermark)) = select_granularity(conn, report, None, metric, buckets)? else {
+        return Ok(None);
+    };
+
+    let entity_count = report.entities.len();
+    let sketches = metric == Metric::UniqueVisitors && report.approximate;
+    let source_sql = if sketches {
+        let rollup_columns = "r.bucket as created_at, r.register, r.rank";
+        let event_columns = format!("e.created_at, {}", sketch_columns_sql("e"));
+        source_sql("rollup_sketches", granularity, entity_count, rollup_columns, &event_columns)
+    } else {
+        let rollup_columns = "r.bucket as created_at, r.views, r.visitors, null::text as visitor_group_id";
+        source_sql("rollups", granularity, entity_count, rollup_columns, "e.created_at, 1, 0, e.visitor_group_id")
+    };
+    let aggregate_sql = aggregate_sql(metric, sketches, "bucket_idx", "bucketed_events");
+    let time_bins_sql =
+        (0..buckets.len()).map(|idx| format!("({idx}, ?::timestamp, ?::timestamp)")).collect::<Vec<_>>().join(", ");
+
+    let mut params = ParamVec::new();
+    for bucket in buckets {
+        params.push(bucket.start);
+        params.push(bucket.end);
+    }
+    params.extend_from_params(source_params(report, String::new(), watermark));
+
+    let query = format!(
+        "--sql
+		with
+			time_bins(bucket_idx, bin_start, bin_end) as (
+				values {time_bins_sql}
+			),
+			source as (
+				{source_sql}
+			),
+			bucketed_events as (
+				select
+					tb.bucket_idx,
+					sd.*
+				from (select * from source order by created_at) sd
+				asof join (select * from time_bins order by bin_start) tb
+					on sd.created_at >= tb.bin_start
+				where sd.created_at < tb.bin_end
+			),
+			event_bins as (
+				{aggregate_sql}
+			)
+		select
+			tb.bin_start,
+			coalesce(eb.metric_value, 0)
+		from time_bins tb
+		left join event_bins eb on tb.bucket_idx = eb.bucket_idx
+		order by tb.bucket_idx;
+	"
+    );
+
+    let mut stmt = conn.prepare_cached(&query)?;
+    let rows = stmt.query_map(duckdb::params_from_iter(params), |row| {
+        Ok(ReportGraphPoint { bin_start: row.get(0)?, value: row.get(1)? })
+    })?;
+    Ok(Some(rows.collect::<Result<ReportGraph, duckdb::Error>>()?))
+}
+
+/// Build a dimension report from rollups, if the report allows it
+pub(super) fn dimension_report(
+    conn: &Connection,
+    report: &ReportQuery,
+    dimension: Dimension,
+    metric: Metric,
+    options: &DimensionTableOptions,
+) -> Result<Option<ReportTable>> {
+    // unique visitors of the remaining rows can't be derived from the per-value rollups
+    if !is_rolled_up(dimension) || (options.include_other && metric != Metric::Views) {
+        return Ok(None);
+    }
+
+    let sketches = metric == Metric::UniqueVisitors && report.approximate;
+    if sketches && !COMPACT_DIMENSIONS.contains(&dimension) {
+        return Ok(None);
+    }
+
+    let bins = std::slice::from_ref(report.range);
+    let Some((granularity, watermark)) = select_granularity(conn, report, Some(dimension), metric, bins)? else {
+        return Ok(None);
+    };
+
+    let entity_count = report.entities.len();
+    let dimension_column = format!("coalesce({}, 'Unknown')", dimension_column_sql(dimension));
+    let source_sql = if sketches {
+        let event_columns = format!("{dimension_column}, {}", sketch_columns_sql("e"));
+        source_sql(
+            "rollup_sketches",
+            granularity,
+            entity_count,
+            "r.dimension_value, r.register, r.rank",
+            &event_columns,
+        )
+    } else {
+        let rollup_columns = "r.dimension_value, r.views, r.visitors, null::text as visitor_group_id";
+        let event_columns = format!("{dimension_column}, 1, 0, e.visitor_group_id");
+        source_sql("rollups", granularity, entity_count, rollup_columns, &event_columns)
+    };
+    let aggregate_sql = aggregate_sql(metric, sketches, "dimension_value", "source");
+    let page_sql = dimension_page_sql(options, "select sum(metric_value) from remainder");
+    let mut params = source_params(report, dimension.to_string(), watermark);
+    push_page_params(&mut params, options);
+
+    let query = format!(
+        "--sql
+		with
+			source as (
+				{source_sql}
+			),
+			dimension_values as (
+				{aggregate_sql}
+			),
+			{page_sql}"
+    );
+
+    Ok(Some(query_dimension_page(conn, &query, params)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::reports::{
+        GraphInterval, ReportTableRow, WeekStart, build_graph_buckets, test_pageview, test_query,
+    };
+    use crate::app::{Liwan, models::Event};
+    use crate::config::Config;
+    use chrono::TimeZone;
+
+    fn test_event(visitor: &str, path: &str, created_at: DateTime<Utc>) -> Event {
+        Event { path: Some(path.to_string()), track_sessions: false, ..test_pageview(visitor, created_at) }
+    }
+
+    #[test]
+    fn rollup_reports_match_raw_events() {
+        let app = Liwan::new_memory(Config::default()).expect("failed to create app");
+        let mut conn = app.events_conn().expect("failed to get events conn");
+
+        let day = |day: u32, hour: u32| Utc.with_ymd_and_hms(2024, 1, day, hour, 0, 0).unwrap();
+        let events = vec![
+            test_event("a", "/", day(1, 1)),
+            test_event("a", "/pricing", day(1, 2)),
+            test_event("b", "/", day(2, 5)),
+            test_event("c", "/", day(3, 23)),
+        ];
+        app.events.append(events.into_iter()).expect("failed to append events");
+        update_rollups(&mut conn).expect("failed to update rollups");
+
+        // backfilled events are read from raw events until the next update
+        app.events.append(vec![test_event("d", "/pricing", day(2, 6))].into_iter()).expect("failed to append events");
+
+        let entities = ["entity-1".to_string()];
+        let range = DateRange { start: day(1, 0), end: day(4, 0) };
+        let report = test_query(&entities, &range);
+        let buckets = build_graph_buckets(&range, GraphInterval::Day, Some("UTC"), WeekStart::Monday, usize::MAX)
+            .expect("failed to build buckets")
+            .expect("too many buckets");
+        let options = DimensionTableOptions { limit: Some(1), include_other: true, ..Default::default() };
+
+        for _ in 0..2 {
+            let graph = overall_report(&conn, &report, &buckets, Metric::Views)
+                .expect("failed to build graph")
+                .expect("rollups should be used");
+            assert_eq!(graph.iter().map(|point| point.value).collect::<Vec<_>>(), vec![2.0, 2.0, 1.0]);
+
+            let visitors = overall_report(&conn, &report, &buckets, Metric::UniqueVisitors)
+                .expect("failed to build graph")
+                .expect("rollups should be used");
+            assert_eq!(visitors.iter().map(|point| point.value).collect::<Vec<_>>(), vec![1.0, 2.0, 1.0]);
+
+            let paths = dimension_report(&conn, &report, Dimension::Path, Metric::Views, &options)
+                .expect("failed to build dimension report")
+                .expect("rollups should be used");
+            let row = |path: &str, value| ReportTableRow { dimension_value: path.to_string(), value };
+            assert_eq!(paths.rows, vec![row("/", 3.0)]);
+            assert_eq!(paths.total_rows, 2);
+            assert_eq!(paths.other, Some(2.0));
+
+            update_rollups(&mut conn).expect("failed to update rollups");
+        }
+
+        // hourly rollups only store a few dimensions
+        let range = DateRange { start: day(1, 1), end: day(2, 6) };
+        let report = ReportQuery { range: &range, ..report };
+        assert!(dimension_report(&conn, &report, Dimension::Path, Metric::Views, &options).unwrap().is_some());
+        assert!(dimension_report(&conn, &report, Dimension::City, Metric::Views, &options).unwrap().is_none());
+    }
+
+    #[test]
+    fn approximate_unique_visitors_use_sketches() {
+        let app = Liwan::new_memory(Config::default()).expect("failed to create app");
+        let mut conn = app.events_conn().expect("failed to get events conn");
+
+        let day = |day: u32, hour: u32| Utc.with_ymd_and_hms(2024, 1, day, hour, 0, 0).unwrap();
+        let events = (0..200)
+            .map(|idx| {
+                test_event(&format!("visitor-{idx}"), if idx % 4 == 0 { "/pricing" } else { "/" }, day(1, idx % 24))
+            })
+            .chain((0..50).map(|idx| test_event(&format!("visitor-{idx}"), "/", day(2, 12))));
+        app.events.append(events).expect("failed to append events");
+        update_rollups(&mut conn).expect("failed to update rollups");
+
+        let entities = ["entity-1".to_string()];
+        let range = DateRange { start: day(1, 0), end: day(3, 0) };
+        let report = ReportQuery { approximate: true, ..test_query(&entities, &range) };
+        let assert_close = |value: f64, expected: f64| {
+            assert!((value - expected).abs() <= expected * 0.1, "expected ~{expected}, got {value}");
+        };
+
+        let buckets = build_graph_buckets(&range, GraphInterval::Day, Some("UTC"), WeekStart::Monday, usize::MAX)
+            .expect("failed to build buckets")
+            .expect("too many buckets");
+        let graph = overall_report(&conn, &report, &buckets, Metric::UniqueVisitors)
+            .expect("failed to build graph")
+            .expect("sketches should be used");
+        assert_close(graph[0].value, 200.0);
+        assert_close(graph[1].value, 50.0);
+
+        let paths = dimension_report(
+            &conn,
+            &report,
+            Dimension::Path,
+            Metric::UniqueVisitors,
+            &DimensionTableOptions::default(),
+        )
+        .expect("failed to build dimension report")
+        .expect("sketches should be used");
+        // visitors 0, 4, .., 48 only viewed /pricing on the first day
+        assert_eq!(paths.rows[0].dimension_value, "/");
+        assert_close(paths.rows[0].value, 163.0);
+        assert_eq!(paths.rows[1].dimension_value, "/pricing");
+        assert_close(paths.rows[1].value, 50.0);
+
+        // sketches are only stored for a few dimensions
+        let options = DimensionTableOptions::default();
+        let browsers = dimension_report(&conn, &report, Dimension::Browser, Metric::UniqueVisitors, &options);
+        assert!(browsers.unwrap().is_some());
+        let cities = dimension_report(&conn, &report, Dimension::City, Metric::UniqueVisitors, &options);
+        assert!(cities.unwrap().is_none());
+
+        // sketches are only stored for daily buckets
+        let range = DateRange { start: day(1, 6), end: day(2, 18) };
+        let report = ReportQuery { range: &range, ..report };
+        assert!(
+            overall_report(&conn, &report, std::slice::from_ref(&range), Metric::UniqueVisitors).unwrap().is_none()
+        );
+        let report = ReportQuery { approximate: false, ..report };
+        assert!(
+            overall_report(&conn, &report, std::slice::from_ref(&range), Metric::UniqueVisitors).unwrap().is_none()
+        );
+    }
+
+    #[test]
+    fn exact_unique_visitors_spanning_utc_midnight_match_raw_events() {
+        let app = Liwan::new_memory(Config::default()).expect("failed to create app");
+        let mut conn = app.events_conn().expect("failed to get events conn");
+
+        // the visitor group of "a" doesn't rotate at midnight UTC
+        let day = |day: u32, hour: u32| Utc.with_ymd_and_hms(2024, 1, day, hour, 0, 0).unwrap();
+        let events =
+            vec![test_event("a", "/", day(1, 23)), test_event("a", "/", day(2, 1)), test_event("b", "/", day(2, 2))];
+        app.events.append(events.into_iter()).expect("failed to append events");
+        update_rollups(&mut conn).expect("failed to update rollups");
+
+        let entities = ["entity-1".to_string()];
+        let range = DateRange { start: day(1, 0), end: day(3, 0) };
+        let report = test_query(&entities, &range);
+
+        // daily bins are read from rollups, bins spanning several days from raw events
+        let buckets = build_graph_buckets(&range, GraphInterval::Day, Some("UTC"), WeekStart::Monday, usize::MAX)
+            .expect("failed to build buckets")
+            .expect("too many buckets");
+        let daily = overall_report(&conn, &report, &buckets, Metric::UniqueVisitors)
+            .expect("failed to build graph")
+            .expect("rollups should be used");
+        assert_eq!(daily.iter().map(|point| point.value).collect::<Vec<_>>(), vec![1.0, 2.0]);
+        assert!(
+            overall_report(&conn, &report, std::slice::from_ref(&range), Metric::UniqueVisitors).unwrap().is_none()
+        );
+
+        let options = DimensionTableOptions::default();
+        assert!(dimension_report(&conn, &report, Dimension::Path, Metric::UniqueVisitors, &options).unwrap().is_none());
+        let paths =
+            crate::app::reports::dimension_report(&conn, &report, &Dimension::Path, &Metric::UniqueVisitors, &options)
+                .expect("failed to build dimension report");
+        assert_eq!(paths.rows, vec![ReportTableRow { dimension_value: "/".to_string(), value: 2.0 }]);
+    }
+
+    #[test]
+    fn shared_visitor_groups_are_not_summed_across_entities() {
+        let app = Liwan::new_memory(Config::default()).expect("failed to create app");
+        let mut conn = app.events_conn().expect("failed to get events conn");
+
+        // the same visitor group seen by both entities of a project with shared visitor groups
+        let day = |day: u32, hour: u32| Utc.with_ymd_and_hms(2024, 1, day, hour, 0, 0).unwrap();
+        let events = vec![
+            test_event("a", "/", day(1, 1)),
+            Event { entity_id: "entity-2".to_string(), ..test_event("a", "/", day(1, 2)) },
+        ];
+        app.events.append(events.into_iter()).expect("failed to append events");
+        update_rollups(&mut conn).expect("failed to update rollups");
+
+        let entities = ["entity-1".to_string(), "entity-2".to_string()];
+        let range = DateRange { start: day(1, 0), end: day(2, 0) };
+        let report = ReportQuery { shared_visitor_groups: true, ..test_query(&entities, &range) };
+        let bins = [range.clone()];
+        assert!(overall_report(&conn, &report, &bins, Metric::UniqueVisitors).unwrap().is_none());
+        assert!(overall_report(&conn, &report, &bins, Metric::Views).unwrap().is_some());
+
+        let visitors = crate::app::reports::overall_report(&conn, &report, &bins, &Metric::UniqueVisitors)
+            .expect("failed to build graph");
+        assert_eq!(visitors[0].value, 1.0);
+    }
+}
@@ -1,12 +1,219 @@
-use crate::utils::duckdb::ParamVec;
+use crate::utils::duckdb::{ParamVec, repeat_vars};
 use anyhow::{Result, bail};
+use duckdb::Connection;
 
-use super::{Dimension, DimensionFilter, FilterType, Metric};
+use super::{
+    Dimension, DimensionTableOptions, FilterType, Metric, ReportQuery, ReportTable, ReportTableRow, SessionTimeout,
+    SortOrder,
+};
 
-pub(super) const SESSION_DURATION_SQL: &str = "interval '30 minutes'";
+/// Events column or expression a dimension report groups by
+pub(super) const fn dimension_column_sql(dimension: Dimension) -> &'static str {
+    match dimension {
+        Dimension::Url | Dimension::UrlEntry | Dimension::UrlExit => "concat(fqdn, path)",
+        Dimension::Path => "path",
+        Dimension::Fqdn => "fqdn",
+        Dimension::Entity => "entity_id",
+        Dimension::Referrer => "referrer",
+        Dimension::Channel => "channel",
+        Dimension::Platform => "platform",
+        Dimension::Browser => "browser",
+        Dimension::BrowserVersion => "concat_ws(' ', browser, browser_version)",
+        Dimension::PlatformVersion => "concat_ws(' ', platform, platform_version)",
+        Dimension::DeviceClass => "device_class",
+        Dimension::Mobile => "mobile::text",
+        Dimension::Country => "country",
+        Dimension::City => "concat(country, city)",
+        Dimension::Region => "concat(country, region)",
+        Dimension::Continent => "continent",
+        Dimension::TimeZone => "time_zone",
+        Dimension::AsnOrg => "asn_org",
+        Dimension::Language => "language",
+        Dimension::UtmSource => "utm_source",
+        Dimension::UtmMedium => "utm_medium",
+        Dimension::UtmCampaign => "utm_campaign",
+        Dimension::UtmContent => "utm_content",
+        Dimension::UtmTerm => "utm_term",
+        Dimension::ScreenWidth => "screen_width",
+        Dimension::Orientation => "orientation",
+    }
+}
+
+/// Add the condition restricting a dimension report to the events the dimension applies to, e.g. session entries
+pub(super) fn scope_filters_sql(filters_sql: String, dimension: Dimension, session_timeout: SessionTimeout) -> String {
+    let timeout_sql = session_timeout.sql();
+    let scope_sql = match dimension {
+        Dimension::UrlEntry => format!("time_from_last_event is null or time_from_last_event > {timeout_sql}"),
+        Dimension::UrlExit => format!("time_to_next_event is null or time_to_next_event > {timeout_sql}"),
+        _ => return filters_sql,
+    };
+
+    if filters_sql.is_empty() { format!("and ({scope_sql})") } else { format!("{filters_sql} and ({scope_sql})") }
+}
+
+/// Select the requested page of the `dimension_values(dimension_value, metric_value)` table of a dimension report
+///
+/// Continues the `with` clause defining `dimension_values`. `other_sql` aggregates the metric of the rows after the
+/// page, which are available as `remainder(dimension_value, metric_value)`. The search text and values are bound as
+/// the last parameters, see [`push_page_params`].
+pub(super) fn dimension_page_sql(options: &DimensionTableOptions, other_sql: &str) -> String {
+    let order = match options.order {
+        SortOrder::Desc => "desc",
+        SortOrder::Asc => "asc",
+    };
+    let mut conditions = Vec::new();
+    if options.search.is_some() {
+        conditions.push("contains(lower(dv.dimension_value), lower(?::text))".to_string());
+    }
+    match options.values.as_deref() {
+        Some([]) => conditions.push("false".to_string()),
+        Some(values) => conditions.push(format!("dv.dimension_value in ({})", repeat_vars(values.len()))),
+        None => {}
+    }
+    let where_sql = if conditions.is_empty() { String::new() } else { format!("where {}", conditions.join(" and ")) };
+    let offset = options.offset;
+    let (page_end_sql, remainder_sql) = match options.limit {
+        Some(limit) => {
+            let end = offset.saturating_add(limit);
+            (format!("and r.position <= {end}"), format!("r.position > {end}"))
+        }
+        None => (String::new(), "false".to_string()),
+    };
+    let other_sql = if options.include_other {
+        format!(
+            "--sql
+		union all
+		select 1, null, null, ({other_sql})::double where exists (select 1 from remainder)"
+        )
+    } else {
+        String::new()
+    };
+
+    format!(
+        "--sql
+			ranked as (
+				select
+					dv.dimension_value,
+					dv.metric_value,
+					row_number() over (order by dv.metric_value {order} nulls last, dv.dimension_value) as position
+				from dimension_values dv
+				{where_sql}
+			),
+			remainder as (
+				select r.dimension_value, r.metric_value from ranked r where {remainder_sql}
+			)
+		select 0 as kind, r.position, r.dimension_value, r.metric_value::double
+		from ranked r
+		where r.position > {offset} {page_end_sql}
+		union all
+		select 2, count(*), null, null from ranked
+		{other_sql}
+		order by kind, position;
+	"
+    )
+}
+
+/// Bind the search text and values of a query built with [`dimension_page_sql`]
+pub(super) fn push_page_params<'a>(params: &mut ParamVec<'a>, options: &'a DimensionTableOptions) {
+    if let Some(search) = &options.search {
+        params.push(search.as_str());
+    }
+    if let Some(values) = &options.values {
+        params.extend(values.iter().map(String::as_str));
+    }
+}
+
+/// Run a query built with [`dimension_page_sql`]
+pub(super) fn query_dimension_page(conn: &Connection, query: &str, params: ParamVec) -> Result<ReportTable> {
+    let mut stmt = conn.prepare_cached(query)?;
+    let rows = stmt.query_map(duckdb::params_from_iter(params), |row| {
+        Ok((
+            row.get::<_, i32>(0)?,
+            row.get::<_, Option<i64>>(1)?,
+            row.get::<_, Option<String>>(2)?,
+            row.get::<_, Option<f64>>(3)?,
+        ))
+    })?;
+
+    let mut table = ReportTable::default();
+    for row in rows {
+        match row? {
+            (0, _, Some(dimension_value), value) => {
+                table.rows.push(ReportTableRow { dimension_value, value: value.unwrap_or(0.0) });
+            }
+            (1, _, _, value) => table.other = Some(value.unwrap_or(0.0)),
+            (2, total_rows, _, _) => {
+                table.total_rows = total_rows.and_then(|total| u64::try_from(total).ok()).unwrap_or(0);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(table)
+}
+
+/// How far before or after the report range session-scoped filters look for the start or end of a session
+const SESSION_FILTER_LOOKBACK: &str = "interval '24 hours'";
 
-pub(super) fn build_filter_clause(filters: &[DimensionFilter]) -> Result<(String, ParamVec<'_>)> {
+/// Value of `dimension` for the session of each event in `alias` that a session-scoped filter matches against
+///
+/// Exit pages are read from the last event of the session, everything else from its first event.
+/// Only events of the report entities within [`SESSION_FILTER_LOOKBACK`] of the report range are considered.
+fn session_filter_sql<'a>(
+    dimension: Dimension,
+    alias: &str,
+    report: &ReportQuery<'a>,
+    params: &mut ParamVec<'a>,
+) -> Result<String> {
+    let column = match dimension {
+        Dimension::Url | Dimension::UrlEntry | Dimension::UrlExit => "concat(s.fqdn, s.path)",
+        Dimension::Path => "s.path",
+        Dimension::Fqdn => "s.fqdn",
+        Dimension::Referrer => "s.referrer",
+        Dimension::Channel => "s.channel",
+        Dimension::UtmSource => "s.utm_source",
+        Dimension::UtmMedium => "s.utm_medium",
+        Dimension::UtmCampaign => "s.utm_campaign",
+        Dimension::UtmContent => "s.utm_content",
+        Dimension::UtmTerm => "s.utm_term",
+        _ => bail!("Dimension {dimension} can't be used in a session filter"),
+    };
+    let timeout_sql = report.session_timeout.sql();
+    let (session_event, order) = match dimension {
+        Dimension::UrlExit => (
+            format!(
+                "s.created_at >= {alias}.created_at and (s.time_to_next_event is null or s.time_to_next_event > {timeout_sql})"
+            ),
+            "asc",
+        ),
+        _ => (
+            format!(
+                "s.created_at <= {alias}.created_at and (s.time_from_last_event is null or s.time_from_last_event > {timeout_sql})"
+            ),
+            "desc",
+        ),
+    };
+
+    let entity_vars = repeat_vars(report.entities.len());
+    params.extend(report.entities);
+    params.push(report.range.start);
+    params.push(report.range.end);
+
+    Ok(format!(
+        "(select {column} from events s
+			where
+				s.entity_id in ({entity_vars}) and
+				s.created_at >= ?::timestamp - {SESSION_FILTER_LOOKBACK} and s.created_at < ?::timestamp + {SESSION_FILTER_LOOKBACK} and
+				s.visitor_group_id = {alias}.visitor_group_id and {session_event}
+			order by s.created_at {order} limit 1)"
+    ))
+}
+
+/// Build the `and (...)` clause for the report filters, `alias` is the alias of the filtered events table
+pub(super) fn build_filter_clause<'a>(report: &ReportQuery<'a>, alias: &str) -> Result<(String, ParamVec<'a>)> {
     let mut params = ParamVec::new();
+    let filters = report.filters;
+    let timeout_sql = report.session_timeout.sql();
 
     if filters.is_empty() {
         return Ok((String::new(), params));
@@ -15,6 +222,10 @@ pub(super) fn build_filter_clause(filters: &[DimensionFilter]) -> Result<(String
     let filter_clauses = filters
 		.iter()
 		.map(|filter| {
+			// the session subquery comes before the filter value in the clause, and so do its parameters
+			let session_column =
+				filter.is_session_scoped().then(|| session_filter_sql(filter.dimension, alias, report, &mut params)).transpose()?;
+
 			let filter_value = match (filter.value.clone(), filter.filter_type, filter.inversed.unwrap_or(false)) {
 				(Some(value), filter_type, inversed) => {
 					params.push(value);
@@ -60,22 +271,36 @@ pub(super) fn build_filter_clause(filters: &[DimensionFilter]) -> Result<(String
 				bail!("Invalid filter type for string dimension");
 			}
 
+			if let Some(column) = session_column {
+				return Ok(format!("{column} {filter_value}"));
+			}
+
 			Ok(match filter.dimension {
 				Dimension::Url => format!("concat(fqdn, path) {filter_value}"),
 				Dimension::UrlEntry => format!(
-					"(time_from_last_event is null or time_from_last_event > {SESSION_DURATION_SQL}) and concat(fqdn, path) {filter_value}"
+					"(time_from_last_event is null or time_from_last_event > {timeout_sql}) and concat(fqdn, path) {filter_value}"
 				),
 				Dimension::UrlExit => format!(
-					"(time_to_next_event is null or time_to_next_event > {SESSION_DURATION_SQL}) and concat(fqdn, path) {filter_value}"
+					"(time_to_next_event is null or time_to_next_event > {timeout_sql}) and concat(fqdn, path) {filter_value}"
 				),
 				Dimension::Path => format!("path {filter_value}"),
 				Dimension::Fqdn => format!("fqdn {filter_value}"),
+				Dimension::Entity => format!("entity_id {filter_value}"),
 				Dimension::Referrer => format!("referrer {filter_value}"),
+				Dimension::Channel => format!("channel {filter_value}"),
 				Dimension::Platform => format!("platform {filter_value}"),
 				Dimension::Browser => format!("browser {filter_value}"),
+				Dimension::BrowserVersion => format!("concat_ws(' ', browser, browser_version) {filter_value}"),
+				Dimension::PlatformVersion => format!("concat_ws(' ', platform, platform_version) {filter_value}"),
+				Dimension::DeviceClass => format!("device_class {filter_value}"),
 				Dimension::Mobile => format!("mobile {filter_value}"),
 				Dimension::Country => format!("country {filter_value}"),
 				Dimension::City => format!("city {filter_value}"),
+				Dimension::Region => format!("region {filter_value}"),
+				Dimension::Continent => format!("continent {filter_value}"),
+				Dimension::TimeZone => format!("time_zone {filter_value}"),
+				Dimension::AsnOrg => format!("asn_org {filter_value}"),
+				Dimension::Language => format!("language {filter_value}"),
 				Dimension::UtmSource => format!("utm_source {filter_value}"),
 				Dimension::UtmMedium => format!("utm_medium {filter_value}"),
 				Dimension::UtmCampaign => format!("utm_campaign {filter_value}"),
@@ -90,18 +315,61 @@ pub(super) fn build_filter_clause(filters: &[DimensionFilter]) -> Result<(String
     Ok((format!("and ({})", filter_clauses.join(" and ")), params))
 }
 
-pub(super) fn metric_aggregate_sql(metric: Metric, alias: &str) -> String {
+/// Extra `session_data` column with the time since the start of the current session, if the metric needs it
+///
+/// If the first pageview of a session is outside of the report range or filters, the time is measured
+/// from an earlier session start in the result set, or is null if there is none.
+pub(super) fn session_time_column_sql(metrics: &[Metric], alias: &str, session_timeout: SessionTimeout) -> String {
+    if !metrics.contains(&Metric::MedianSessionDuration) {
+        return String::new();
+    }
+
+    let timeout_sql = session_timeout.sql();
+
+    format!(
+        "--sql
+					, {alias}.created_at - max(case when {alias}.time_from_last_event is null or {alias}.time_from_last_event > {timeout_sql} then {alias}.created_at end)
+						over (partition by {alias}.visitor_group_id order by {alias}.created_at rows between unbounded preceding and current row) as session_time"
+    )
+}
+
+/// Aggregate expression used to rank dimension values, the metric itself for counts and views otherwise
+pub(super) fn rank_aggregate_sql(metric: Metric, alias: &str, report: &ReportQuery) -> String {
+    match metric {
+        Metric::Views | Metric::UniqueVisitors | Metric::Sessions => metric_aggregate_sql(metric, alias, report),
+        _ => metric_aggregate_sql(Metric::Views, alias, report),
+    }
+}
+
+/// Aggregate expression of `metric` over the events table `alias`
+pub(super) fn metric_aggregate_sql(metric: Metric, alias: &str, report: &ReportQuery) -> String {
+    let timeout_sql = report.session_timeout.sql();
+    let session_start =
+        format!("({alias}.time_from_last_event is null or {alias}.time_from_last_event > {timeout_sql})");
+    let session_end = format!("({alias}.time_to_next_event is null or {alias}.time_to_next_event > {timeout_sql})");
+
     match metric {
         Metric::Views => format!("count({alias}.created_at)"),
+        Metric::UniqueVisitors if report.approximate => format!("approx_count_distinct({alias}.visitor_group_id)"),
         Metric::UniqueVisitors => format!("count(distinct {alias}.visitor_group_id)"),
+        Metric::Sessions => format!("count(*) filter (where {session_start})"),
+        Metric::ViewsPerSession => {
+            format!("coalesce(count(*)::double / nullif(count(*) filter (where {session_start}), 0), 0)")
+        }
+        Metric::MedianSessionDuration => {
+            format!(
+                "coalesce(median(extract(epoch from {alias}.session_time)) filter (where {session_end} and {alias}.session_time is not null), 0)"
+            )
+        }
+        Metric::ExitRate => format!("coalesce(count(*) filter (where {session_end})::double / nullif(count(*), 0), 0)"),
         Metric::BounceRate => {
             format!(
 				"--sql
 			coalesce(
 				count(*)
-					filter (where ({alias}.time_from_last_event is null or {alias}.time_from_last_event > {SESSION_DURATION_SQL}) and
-								 ({alias}.time_to_next_event is null or {alias}.time_to_next_event > {SESSION_DURATION_SQL}))::double /
-				nullif(count(*) filter (where {alias}.time_from_last_event is null or {alias}.time_from_last_event > {SESSION_DURATION_SQL}), 0),
+					filter (where ({alias}.time_from_last_event is null or {alias}.time_from_last_event > {timeout_sql}) and
+								 ({alias}.time_to_next_event is null or {alias}.time_to_next_event > {timeout_sql}))::double /
+				nullif(count(*) filter (where {alias}.time_from_last_event is null or {alias}.time_from_last_event > {timeout_sql}), 0),
 				1
 			)
 			"
@@ -110,7 +378,7 @@ pub(super) fn metric_aggregate_sql(metric: Metric, alias: &str) -> String {
         Metric::AvgTimeOnSite => {
             format!(
 				"--sql
-			coalesce(avg(extract(epoch from {alias}.time_to_next_event)) filter (where {alias}.time_to_next_event is not null and {alias}.time_to_next_event <= {SESSION_DURATION_SQL}), 0)"
+			coalesce(avg(extract(epoch from {alias}.time_to_next_event)) filter (where {alias}.time_to_next_event is not null and {alias}.time_to_next_event <= {timeout_sql}), 0)"
 			)
         }
     }
@@ -4,8 +4,8 @@ use anyhow::Result;
 use chrono::{DateTime, Utc};
 use duckdb::params_from_iter;
 
-use super::shared::{build_filter_clause, metric_aggregate_sql};
-use super::{DateRange, DimensionFilter, Metric, ReportStats};
+use super::shared::{build_filter_clause, metric_aggregate_sql, session_time_column_sql};
+use super::{Metric, ReportQuery, ReportStats};
 
 /// Return the earliest event timestamp for the selected entities
 pub fn earliest_timestamp(conn: &DuckDBConn, entities: &[String]) -> Result<Option<DateTime<Utc>>> {
@@ -50,30 +50,30 @@ pub fn online_users(conn: &DuckDBConn, entities: &[String]) -> Result<u64> {
 }
 
 /// Build overall stats for a report range
-pub fn overall_stats(
-    conn: &DuckDBConn,
-    entities: &[String],
-    event: &str,
-    range: &DateRange,
-    filters: &[DimensionFilter],
-) -> Result<ReportStats> {
-    if entities.is_empty() {
+pub fn overall_stats(conn: &DuckDBConn, report: &ReportQuery) -> Result<ReportStats> {
+    if report.entities.is_empty() {
         return Ok(ReportStats::default());
     }
 
-    let entity_vars = repeat_vars(entities.len());
-    let (filters_sql, filters_params) = build_filter_clause(filters)?;
+    let entity_vars = repeat_vars(report.entities.len());
+    let (filters_sql, filters_params) = build_filter_clause(report, "e")?;
 
-    let metric_total = metric_aggregate_sql(Metric::Views, "sd");
-    let metric_unique_visitors = metric_aggregate_sql(Metric::UniqueVisitors, "sd");
-    let metric_bounce_rate = metric_aggregate_sql(Metric::BounceRate, "sd");
-    let metric_avg_time_on_site = metric_aggregate_sql(Metric::AvgTimeOnSite, "sd");
+    let timeout = report.session_timeout;
+    let metric_total = metric_aggregate_sql(Metric::Views, "sd", report);
+    let metric_unique_visitors = metric_aggregate_sql(Metric::UniqueVisitors, "sd", report);
+    let metric_bounce_rate = metric_aggregate_sql(Metric::BounceRate, "sd", report);
+    let metric_avg_time_on_site = metric_aggregate_sql(Metric::AvgTimeOnSite, "sd", report);
+    let metric_sessions = metric_aggregate_sql(Metric::Sessions, "sd", report);
+    let metric_views_per_session = metric_aggregate_sql(Metric::ViewsPerSession, "sd", report);
+    let metric_median_session_duration = metric_aggregate_sql(Metric::MedianSessionDuration, "sd", report);
+    let metric_exit_rate = metric_aggregate_sql(Metric::ExitRate, "sd", report);
+    let session_time_column = session_time_column_sql(Metric::all(), "e", timeout);
 
     let mut params = ParamVec::new();
-    params.push(event);
-    params.push(range.start);
-    params.push(range.end);
-    params.extend(entities);
+    params.push(report.event);
+    params.push(report.range.start);
+    params.push(report.range.end);
+    params.extend(report.entities);
     params.extend_from_params(filters_params);
 
     let query = format!(
@@ -85,6 +85,7 @@ pub fn overall_stats(
 					e.created_at,
 					e.time_from_last_event,
 					e.time_to_next_event
+					{session_time_column}
 				from events e
 				where
 					e.event = ?::text and
@@ -96,7 +97,11 @@ pub fn overall_stats(
 			{metric_total} as total_views,
 			{metric_unique_visitors} as unique_visitors,
 			{metric_bounce_rate} as bounce_rate,
-			{metric_avg_time_on_site} as avg_time_on_site
+			{metric_avg_time_on_site} as avg_time_on_site,
+			{metric_sessions} as sessions,
+			{metric_views_per_session} as views_per_session,
+			{metric_median_session_duration} as median_session_duration,
+			{metric_exit_rate} as exit_rate
 		from session_data sd;
 	"
     );
@@ -108,8 +113,122 @@ pub fn overall_stats(
             unique_visitors: row.get(1)?,
             bounce_rate: row.get(2)?,
             avg_time_on_site: row.get(3)?,
+            sessions: row.get(4)?,
+            views_per_session: row.get(5)?,
+            median_session_duration: row.get(6)?,
+            exit_rate: row.get(7)?,
         })
     })?;
 
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::Liwan;
+    use crate::app::reports::{
+        DateRange, Dimension, DimensionFilter, FilterScope, FilterType, SessionTimeout, test_pageview, test_query,
+    };
+    use crate::config::Config;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    fn overall_stats_session_metrics() {
+        let app = Liwan::new_memory(Config::default()).expect("failed to create app");
+        app.seed_database(0).expect("failed to seed app");
+
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let minutes = |minutes: i64| start + Duration::minutes(minutes);
+
+        // visitor a: one session with three pageviews over 4 minutes, then a bounce two hours later
+        // visitor b: one session with two pageviews over 10 minutes
+        let events = vec![
+            test_pageview("a", minutes(0)),
+            test_pageview("a", minutes(1)),
+            test_pageview("a", minutes(4)),
+            test_pageview("a", minutes(120)),
+            test_pageview("b", minutes(5)),
+            test_pageview("b", minutes(15)),
+        ];
+        app.events.append(events.into_iter()).expect("failed to append events");
+
+        let range = DateRange { start, end: start + Duration::days(1) };
+        let conn = app.events_conn().expect("failed to get events conn");
+        let entities = ["entity-1".to_string()];
+        let report = test_query(&entities, &range);
+        let stats = overall_stats(&conn, &report).expect("failed to build stats");
+
+        assert_eq!(stats.total_views, 6);
+        assert_eq!(stats.sessions, Some(3));
+        assert_eq!(stats.views_per_session, Some(2.0));
+        assert_eq!(stats.median_session_duration, Some(240.0));
+        assert_eq!(stats.exit_rate, Some(0.5));
+
+        // with a three hour timeout, the bounce of visitor a continues the first session
+        let report = ReportQuery { session_timeout: SessionTimeout::from_minutes(180), ..report };
+        let stats = overall_stats(&conn, &report).expect("failed to build stats");
+        assert_eq!(stats.sessions, Some(2));
+        assert_eq!(stats.median_session_duration, Some(3900.0));
+
+        // a later batch extends the session of visitor b instead of starting a new one
+        app.events.append(vec![test_pageview("b", minutes(25))].into_iter()).expect("failed to append events");
+        let report = ReportQuery { session_timeout: SessionTimeout::DEFAULT, ..report };
+        let stats = overall_stats(&conn, &report).expect("failed to build stats");
+        assert_eq!(stats.sessions, Some(3));
+        assert_eq!(stats.views_per_session, Some(7.0 / 3.0));
+
+        // an out-of-order event of visitor a joins the bounce to a new session after the first one
+        app.events.append(vec![test_pageview("a", minutes(100))].into_iter()).expect("failed to append events");
+        let stats = overall_stats(&conn, &report).expect("failed to build stats");
+        assert_eq!(stats.sessions, Some(3));
+        assert_eq!(stats.median_session_duration, Some(1200.0));
+        assert_eq!(stats.exit_rate, Some(3.0 / 8.0));
+    }
+
+    #[test]
+    fn session_filters_use_the_report_session_timeout() {
+        let app = Liwan::new_memory(Config::default()).expect("failed to create app");
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let pageview = |path: &str, minutes: i64| crate::app::models::Event {
+            path: Some(path.to_string()),
+            ..test_pageview("a", start + Duration::minutes(minutes))
+        };
+
+        // a session entering on /pricing, then a second one entering on / an hour later
+        let events = vec![pageview("/pricing", 0), pageview("/", 1), pageview("/", 60), pageview("/about", 61)];
+        app.events.append(events.into_iter()).expect("failed to append events");
+
+        let range = DateRange { start, end: start + Duration::days(1) };
+        let conn = app.events_conn().expect("failed to get events conn");
+        let entities = ["entity-1".to_string()];
+        let filters = [DimensionFilter {
+            dimension: Dimension::UrlEntry,
+            filter_type: FilterType::Equal,
+            inversed: None,
+            strict: None,
+            value: Some("example.com/pricing".to_string()),
+            scope: Some(FilterScope::Session),
+        }];
+        let report = ReportQuery { filters: &filters, ..test_query(&entities, &range) };
+        let stats = overall_stats(&conn, &report).expect("failed to build stats");
+        assert_eq!(stats.total_views, 2);
+        assert_eq!(stats.sessions, Some(1));
+
+        // with a two hour timeout, all pageviews belong to the session entering on /pricing
+        let report = ReportQuery { session_timeout: SessionTimeout::from_minutes(120), ..report };
+        let stats = overall_stats(&conn, &report).expect("failed to build stats");
+        assert_eq!(stats.total_views, 4);
+        assert_eq!(stats.sessions, Some(1));
+
+        // exit pages are matched against the last event of the session
+        let filters = [DimensionFilter {
+            dimension: Dimension::UrlExit,
+            value: Some("example.com/about".to_string()),
+            ..filters[0].clone()
+        }];
+        let report = ReportQuery { filters: &filters, session_timeout: SessionTimeout::DEFAULT, ..report };
+        let stats = overall_stats(&conn, &report).expect("failed to build stats");
+        assert_eq!(stats.total_views, 2);
+    }
+}
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::num::NonZeroU32;
 use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
 use anyhow::{Result, bail};
 use rusqlite::OptionalExtension;
@@ -16,8 +17,13 @@ pub struct LiwanSettings {
 #[derive(Clone)]
 pub struct LiwanProjectSettings {
     pool: SqlitePool,
+    /// Visitor group scopes of recently seen entities and when they were looked up
+    visitor_group_scopes: Arc<quick_cache::sync::Cache<String, (Instant, String)>>,
 }
 
+/// How long visitor group scopes are cached, changes to the entities of a project apply after this
+const VISITOR_GROUP_SCOPE_TTL: Duration = Duration::from_secs(60);
+
 #[derive(Debug, Clone)]
 struct SettingsCache {
     global: models::CollectionSettings,
@@ -44,6 +50,7 @@ impl LiwanSettings {
                 track_sessions: None,
                 track_utm_params: None,
                 track_geo: None,
+                track_language: None,
                 data_retention: models::DataRetention::Inherit,
                 allowed_hostnames: Vec::new(),
                 ingest_drop_rules: Vec::new(),
@@ -77,6 +84,7 @@ impl LiwanSettings {
                 track_sessions = :track_sessions,
                 track_utm_params = :track_utm_params,
                 track_geo = :track_geo,
+                track_language = :track_language,
                 history_days = :history_days,
                 ingest_drop_rules_json = :ingest_drop_rules_json
              where id = 1",
@@ -85,6 +93,7 @@ impl LiwanSettings {
                 ":track_sessions": settings.track_sessions,
                 ":track_utm_params": settings.track_utm_params,
                 ":track_geo": settings.track_geo.to_string(),
+                ":track_language": settings.track_language,
                 ":history_days": data_retention_days,
                 ":ingest_drop_rules_json": ingest_drop_rules_json,
             },
@@ -116,13 +125,14 @@ impl LiwanSettings {
         };
         let conn = self.pool.get()?;
         conn.execute(
-            "insert into entity_settings (entity_id, visitor_group_mode, track_sessions, track_utm_params, track_geo, history_mode, history_days, allowed_hostnames, ingest_drop_rules_json)
-             values (:entity_id, :visitor_group_mode, :track_sessions, :track_utm_params, :track_geo, :history_mode, :history_days, :allowed_hostnames, :ingest_drop_rules_json)
+            "insert into entity_settings (entity_id, visitor_group_mode, track_sessions, track_utm_params, track_geo, track_language, history_mode, history_days, allowed_hostnames, ingest_drop_rules_json)
+             values (:entity_id, :visitor_group_mode, :track_sessions, :track_utm_params, :track_geo, :track_language, :history_mode, :history_days, :allowed_hostnames, :ingest_drop_rules_json)
              on conflict(entity_id) do update set
                 visitor_group_mode = excluded.visitor_group_mode,
                 track_sessions = excluded.track_sessions,
                 track_utm_params = excluded.track_utm_params,
                 track_geo = excluded.track_geo,
+                track_language = excluded.track_language,
                 history_mode = excluded.history_mode,
                 history_days = excluded.history_days,
                 allowed_hostnames = excluded.allowed_hostnames,
@@ -133,6 +143,7 @@ impl LiwanSettings {
                 ":track_sessions": settings.track_sessions,
                 ":track_utm_params": settings.track_utm_params,
                 ":track_geo": settings.track_geo.map(|detail| detail.to_string()),
+                ":track_language": settings.track_language,
                 ":history_mode": history_mode,
                 ":history_days": data_retention_days,
                 ":allowed_hostnames": allowed_hostnames,
@@ -154,7 +165,7 @@ impl LiwanSettings {
 impl LiwanProjectSettings {
     /// Create a project settings store backed by SQLite
     pub fn new(pool: SqlitePool) -> Self {
-        Self { pool }
+        Self { pool, visitor_group_scopes: Arc::new(quick_cache::sync::Cache::new(512)) }
     }
 
     /// Get display settings for a project
@@ -162,7 +173,7 @@ impl LiwanProjectSettings {
         let conn = self.pool.get()?;
         let settings = conn
             .query_row(
-                "select metric_display_overrides_json, dimension_display_overrides_json from project_settings where project_id = ?",
+                "select metric_display_overrides_json, dimension_display_overrides_json, session_timeout_minutes, shared_visitor_groups from project_settings where project_id = ?",
                 [project_id],
                 |row| {
                     let metric_json: String = row.get(0)?;
@@ -173,6 +184,8 @@ impl LiwanProjectSettings {
                             .map_err(|err| sql_err(0, rusqlite::types::Type::Text, err))?,
                         dimension_display_overrides: serde_json::from_str(&dimension_json)
                             .map_err(|err| sql_err(1, rusqlite::types::Type::Text, err))?,
+                        session_timeout_minutes: row.get(2)?,
+                        shared_visitor_groups: row.get(3)?,
                     })
                 },
             )
@@ -190,26 +203,69 @@ impl LiwanProjectSettings {
         let dimension_json = serde_json::to_string(&settings.dimension_display_overrides)?;
         let conn = self.pool.get()?;
         conn.execute(
-            "insert into project_settings (project_id, metric_display_overrides_json, dimension_display_overrides_json)
-             values (:project_id, :metric_display_overrides_json, :dimension_display_overrides_json)
+            "insert into project_settings (project_id, metric_display_overrides_json, dimension_display_overrides_json, session_timeout_minutes, shared_visitor_groups)
+             values (:project_id, :metric_display_overrides_json, :dimension_display_overrides_json, :session_timeout_minutes, :shared_visitor_groups)
              on conflict(project_id) do update set
                 metric_display_overrides_json = excluded.metric_display_overrides_json,
-                dimension_display_overrides_json = excluded.dimension_display_overrides_json",
+                dimension_display_overrides_json = excluded.dimension_display_overrides_json,
+                session_timeout_minutes = excluded.session_timeout_minutes,
+                shared_visitor_groups = excluded.shared_visitor_groups",
             rusqlite::named_params! {
                 ":project_id": settings.project_id,
                 ":metric_display_overrides_json": metric_json,
                 ":dimension_display_overrides_json": dimension_json,
+                ":session_timeout_minutes": settings.session_timeout_minutes,
+                ":shared_visitor_groups": settings.shared_visitor_groups,
             },
         )?;
+        self.visitor_group_scopes.clear();
         Ok(())
     }
+
+    /// Return the project an entity shares visitor groups with, if any
+    ///
+    /// Entities can belong to several projects, the first one by id with shared visitor groups is used.
+    pub fn shared_visitor_group_project(&self, entity_id: &str) -> Result<Option<String>> {
+        let conn = self.pool.get()?;
+        let project_id = conn
+            .query_row(
+                "select pe.project_id from project_entities pe
+                 join project_settings ps on ps.project_id = pe.project_id
+                 where pe.entity_id = ? and ps.shared_visitor_groups
+                 order by pe.project_id
+                 limit 1",
+                [entity_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(project_id)
+    }
+
+    /// Return what the visitor groups of an entity are derived for
+    ///
+    /// This is the entity itself, unless it belongs to a project with shared visitor groups. Project ids are prefixed
+    /// with a character ids can't contain, so they never collide with entity ids.
+    pub fn visitor_group_scope(&self, entity_id: &str) -> Result<String> {
+        if let Some((looked_up_at, scope)) = self.visitor_group_scopes.get(entity_id)
+            && looked_up_at.elapsed() < VISITOR_GROUP_SCOPE_TTL
+        {
+            return Ok(scope);
+        }
+
+        let scope = match self.shared_visitor_group_project(entity_id)? {
+            Some(project_id) => format!("project/{project_id}"),
+            None => entity_id.to_string(),
+        };
+        self.visitor_group_scopes.insert(entity_id.to_string(), (Instant::now(), scope.clone()));
+        Ok(scope)
+    }
 }
 
 impl SettingsCache {
     fn load(pool: &SqlitePool) -> Result<Self> {
         let conn = pool.get()?;
         let global = conn.query_row(
-            "select visitor_group_mode, track_sessions, track_utm_params, track_geo, history_days, ingest_drop_rules_json from settings where id = 1",
+            "select visitor_group_mode, track_sessions, track_utm_params, track_geo, history_days, ingest_drop_rules_json, track_language from settings where id = 1",
             [],
             |row| {
                 let visitor_group_mode: String = row.get(0)?;
@@ -234,6 +290,7 @@ impl SettingsCache {
                     track_geo: track_geo
                         .parse()
                         .map_err(|err: String| sql_err(3, rusqlite::types::Type::Text, err))?,
+                    track_language: row.get(6)?,
                     data_retention,
                     ingest_drop_rules: serde_json::from_str(&ingest_drop_rules_json)
                         .map_err(|err| sql_err(5, rusqlite::types::Type::Text, err))?,
@@ -242,7 +299,7 @@ impl SettingsCache {
         )?;
 
         let mut stmt = conn.prepare(
-            "select entity_id, visitor_group_mode, track_sessions, track_utm_params, track_geo, history_mode, history_days, allowed_hostnames, ingest_drop_rules_json from entity_settings",
+            "select entity_id, visitor_group_mode, track_sessions, track_utm_params, track_geo, history_mode, history_days, allowed_hostnames, ingest_drop_rules_json, track_language from entity_settings",
         )?;
         let entities = stmt
             .query_map([], |row| {
@@ -277,6 +334,7 @@ impl SettingsCache {
                     track_geo: track_geo
                         .map(|value| value.parse().map_err(|err: String| sql_err(4, rusqlite::types::Type::Text, err)))
                         .transpose()?,
+                    track_language: row.get(9)?,
                     data_retention,
                     allowed_hostnames: allowed_hostnames
                         .split(',')
@@ -15,12 +15,14 @@ use core::{
 };
 use duckdb::DuckdbConnectionManager;
 use models::{DisplayOverride, GeoDetail};
-use reports::{Dimension, Metric};
+use reports::{Dimension, Metric, ReportCache, ReportLimiter, SessionTimeout};
 
 pub type DuckDBConn = r2d2::PooledConnection<DuckdbConnectionManager>;
 pub type DuckDBPool = r2d2::Pool<DuckdbConnectionManager>;
 pub type SqlitePool = r2d2::Pool<SqliteConnectionManager>;
 pub use core::PruneStats;
+#[cfg(feature = "geoip")]
+pub use core::{GeoIpCheckResult, GeoIpDatabaseStatus, LiwanGeoIP};
 
 pub struct Liwan {
     events_pool: r2d2::Pool<DuckdbConnectionManager>,
@@ -33,6 +35,8 @@ pub struct Liwan {
     pub projects: LiwanProjects,
     pub settings: LiwanSettings,
     pub project_settings: LiwanProjectSettings,
+    pub report_cache: Arc<ReportCache>,
+    pub report_limiter: ReportLimiter,
 
     #[cfg(feature = "geoip")]
     pub geoip: Arc<core::LiwanGeoIP>,
@@ -64,12 +68,18 @@ impl Liwan {
             config.duckdb.clone(),
             embedded::events::migrations::runner(),
         )?;
+        let report_cache = Arc::new(ReportCache::new(&config.report_cache));
 
         Ok(Self {
             #[cfg(feature = "geoip")]
             geoip: core::LiwanGeoIP::try_new(config.clone())?.into(),
 
-            events: LiwanEvents::try_new(conn_events.clone(), conn_app.clone(), config.visitor_group_rotation_hour)?,
+            events: LiwanEvents::try_new(
+                conn_events.clone(),
+                conn_app.clone(),
+                report_cache.clone(),
+                config.visitor_group_rotation_hour,
+            )?,
             onboarding: LiwanOnboarding::try_new(&conn_app)?,
             sessions: LiwanSessions::new(conn_app.clone()),
             entities: LiwanEntities::new(conn_app.clone()),
@@ -77,6 +87,8 @@ impl Liwan {
             settings: LiwanSettings::try_new(conn_app.clone())?,
             project_settings: LiwanProjectSettings::new(conn_app.clone()),
             users: LiwanUsers::new(conn_app),
+            report_cache,
+            report_limiter: ReportLimiter::new(conn_events.clone(), &config.report_limits),
 
             events_pool: conn_events,
             config,
@@ -88,12 +100,18 @@ impl Liwan {
         tracing::debug!("Initializing app in memory");
         let conn_app = db::init_sqlite_mem(embedded::app::migrations::runner())?;
         let conn_events = db::init_duckdb_mem(embedded::events::migrations::runner())?;
+        let report_cache = Arc::new(ReportCache::new(&config.report_cache));
 
         Ok(Self {
             #[cfg(feature = "geoip")]
             geoip: core::LiwanGeoIP::try_new(config.clone())?.into(),
 
-            events: LiwanEvents::try_new(conn_events.clone(), conn_app.clone(), config.visitor_group_rotation_hour)?,
+            events: LiwanEvents::try_new(
+                conn_events.clone(),
+                conn_app.clone(),
+                report_cache.clone(),
+                config.visitor_group_rotation_hour,
+            )?,
             onboarding: LiwanOnboarding::try_new(&conn_app)?,
             sessions: LiwanSessions::new(conn_app.clone()),
             entities: LiwanEntities::new(conn_app.clone()),
@@ -101,6 +119,8 @@ impl Liwan {
             settings: LiwanSettings::try_new(conn_app.clone())?,
             project_settings: LiwanProjectSettings::new(conn_app.clone()),
             users: LiwanUsers::new(conn_app),
+            report_cache,
+            report_limiter: ReportLimiter::new(conn_events.clone(), &config.report_limits),
 
             events_pool: conn_events,
             config,
@@ -115,6 +135,7 @@ impl Liwan {
     pub fn run_background_tasks(&self) {
         #[cfg(feature = "geoip")]
         tokio::task::spawn(core::keep_updated(self.geoip.clone()));
+        tokio::task::spawn(core::reports::keep_rollups_updated(self.events_pool.clone()));
     }
 
     pub fn shutdown(&self) -> Result<()> {
@@ -135,13 +156,31 @@ impl Liwan {
             DisplayOverride::Hide => true,
             DisplayOverride::Auto => match metric {
                 Metric::Views | Metric::UniqueVisitors => false,
-                Metric::BounceRate | Metric::AvgTimeOnSite => {
+                Metric::Sessions
+                | Metric::ViewsPerSession
+                | Metric::BounceRate
+                | Metric::AvgTimeOnSite
+                | Metric::MedianSessionDuration
+                | Metric::ExitRate => {
                     entities.iter().any(|entity_id| !self.settings.resolved_for_entity(entity_id).track_sessions)
                 }
             },
         }
     }
 
+    /// Return the session timeout configured for a project
+    pub fn session_timeout(&self, project_id: &str) -> SessionTimeout {
+        self.project_settings
+            .get(project_id)
+            .ok()
+            .and_then(|settings| settings.session_timeout_minutes)
+            .map_or_else(SessionTimeout::default, SessionTimeout::from_minutes)
+    }
+
+    pub fn shared_visitor_groups(&self, project_id: &str) -> bool {
+        self.project_settings.get(project_id).is_ok_and(|settings| settings.shared_visitor_groups)
+    }
+
     pub fn is_dimension_hidden(&self, project_id: &str, entities: &[String], dimension: Dimension) -> bool {
         match self
             .project_settings
@@ -156,12 +195,15 @@ impl Liwan {
                 Dimension::UrlEntry | Dimension::UrlExit => {
                     entities.iter().any(|entity_id| !self.settings.resolved_for_entity(entity_id).track_sessions)
                 }
-                Dimension::Country => entities
+                Dimension::Country | Dimension::Continent | Dimension::AsnOrg => entities
                     .iter()
                     .any(|entity_id| self.settings.resolved_for_entity(entity_id).track_geo == GeoDetail::None),
-                Dimension::City => entities
+                Dimension::City | Dimension::Region | Dimension::TimeZone => entities
                     .iter()
                     .any(|entity_id| self.settings.resolved_for_entity(entity_id).track_geo != GeoDetail::City),
+                Dimension::Language => {
+                    entities.iter().any(|entity_id| !self.settings.resolved_for_entity(entity_id).track_language)
+                }
                 Dimension::UtmSource
                 | Dimension::UtmMedium
                 | Dimension::UtmCampaign
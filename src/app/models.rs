@@ -26,6 +26,16 @@ pub struct Event {
     pub utm_term: Option<String>,
     pub screen_width: Option<String>,
     pub orientation: Option<String>,
+    pub browser_version: Option<String>,
+    pub platform_version: Option<String>,
+    pub device_class: Option<String>,
+    pub language: Option<String>,
+    pub channel: Option<String>,
+    pub region: Option<String>,
+    pub continent: Option<String>,
+    pub time_zone: Option<String>,
+    pub asn: Option<u32>,
+    pub asn_org: Option<String>,
     pub track_sessions: bool,
 }
 
@@ -183,10 +193,16 @@ pub struct CollectionSettings {
     pub track_sessions: bool,
     pub track_utm_params: bool,
     pub track_geo: GeoDetail,
+    #[serde(default = "default_true")]
+    pub track_language: bool,
     pub data_retention: DataRetention,
     pub ingest_drop_rules: Vec<IngestDropRule>,
 }
 
+fn default_true() -> bool {
+    true
+}
+
 impl Default for CollectionSettings {
     fn default() -> Self {
         Self {
@@ -194,6 +210,7 @@ impl Default for CollectionSettings {
             track_sessions: true,
             track_utm_params: true,
             track_geo: GeoDetail::City,
+            track_language: true,
             data_retention: DataRetention::All,
             ingest_drop_rules: Vec::new(),
         }
@@ -208,6 +225,8 @@ pub struct EntityCollectionSettings {
     pub track_sessions: Option<bool>,
     pub track_utm_params: Option<bool>,
     pub track_geo: Option<GeoDetail>,
+    #[serde(default)]
+    pub track_language: Option<bool>,
     pub data_retention: DataRetention,
     #[serde(default)]
     pub allowed_hostnames: Vec<String>,
@@ -221,6 +240,7 @@ pub struct ResolvedCollectionSettings {
     pub track_sessions: bool,
     pub track_utm_params: bool,
     pub track_geo: GeoDetail,
+    pub track_language: bool,
     pub data_retention: DataRetention,
     pub allowed_hostnames: Vec<String>,
     pub ingest_drop_rules: Vec<IngestDropRule>,
@@ -233,6 +253,7 @@ impl From<CollectionSettings> for ResolvedCollectionSettings {
             track_sessions: settings.track_sessions,
             track_utm_params: settings.track_utm_params,
             track_geo: settings.track_geo,
+            track_language: settings.track_language,
             data_retention: settings.data_retention,
             allowed_hostnames: Vec::new(),
             ingest_drop_rules: settings.ingest_drop_rules,
@@ -254,6 +275,7 @@ impl ResolvedCollectionSettings {
             track_sessions: entity.track_sessions.unwrap_or(global.track_sessions),
             track_utm_params: entity.track_utm_params.unwrap_or(global.track_utm_params),
             track_geo: entity.track_geo.unwrap_or(global.track_geo),
+            track_language: entity.track_language.unwrap_or(global.track_language),
             data_retention: match entity.data_retention {
                 DataRetention::Inherit => global.data_retention,
                 retention => retention,
@@ -341,6 +363,12 @@ pub struct ProjectDisplaySettings {
     pub project_id: String,
     pub metric_display_overrides: BTreeMap<String, DisplayOverride>,
     pub dimension_display_overrides: BTreeMap<String, DisplayOverride>,
+    /// Minutes of inactivity after which a new session starts, defaults to 30
+    #[serde(default)]
+    pub session_timeout_minutes: Option<u32>,
+    /// Derive visitor groups per project instead of per entity, so visitors and sessions span its entities
+    #[serde(default)]
+    pub shared_visitor_groups: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -364,6 +392,7 @@ mod tests {
                 track_sessions: None,
                 track_utm_params: None,
                 track_geo: None,
+                track_language: None,
                 data_retention: DataRetention::Days(NonZeroU32::new(30).unwrap()),
                 allowed_hostnames: Vec::new(),
                 ingest_drop_rules: Vec::new(),
@@ -434,7 +463,7 @@ impl TryFrom<String> for UserRole {
 
 #[macro_export]
 macro_rules! event_params {
-    ($event:expr) => {
+    ($event:expr, $time_from_last_event:expr, $time_to_next_event:expr) => {
         duckdb::params![
             $event.entity_id,
             $event.visitor_group_id,
@@ -453,10 +482,20 @@ macro_rules! event_params {
             $event.utm_campaign,
             $event.utm_content,
             $event.utm_term,
-            None::<std::time::Duration>,
-            None::<std::time::Duration>,
+            $time_from_last_event,
+            $time_to_next_event,
             $event.screen_width,
             $event.orientation,
+            $event.browser_version,
+            $event.platform_version,
+            $event.device_class,
+            $event.language,
+            $event.channel,
+            $event.region,
+            $event.continent,
+            $event.time_zone,
+            $event.asn,
+            $event.asn_org,
         ]
     };
 }
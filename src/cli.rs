@@ -1,6 +1,6 @@
 use crate::{
     app::{Liwan, models::UserRole},
-    config::{Config, DEFAULT_CONFIG, GeoIpConfig},
+    config::{Config, DEFAULT_CONFIG},
 };
 use anyhow::Result;
 use argh::FromArgs;
@@ -32,10 +32,20 @@ pub enum Command {
     AddUser(AddUser),
     Users(ListUsers),
     Prune(Prune),
+    Geoip(Geoip),
     #[cfg(debug_assertions)]
     Dev(Dev),
 }
 
+#[derive(FromArgs)]
+#[argh(subcommand, name = "geoip")]
+/// Show the status of the GeoIP databases
+pub struct Geoip {
+    #[argh(switch)]
+    /// check for database updates before showing the status
+    update: bool,
+}
+
 #[derive(FromArgs)]
 #[argh(subcommand, name = "prune")]
 /// Prune collection data according to current UI-managed settings
@@ -126,9 +136,13 @@ pub struct AddUser {
 }
 
 pub fn handle_command(mut config: Config, cmd: Command) -> Result<()> {
-    config.geoip = GeoIpConfig::default(); // disable GeoIP in CLI commands
+    let geoip_config = std::mem::take(&mut config.geoip); // disable GeoIP in CLI commands
 
     match cmd {
+        Command::Geoip(geoip) => {
+            config.geoip = geoip_config;
+            geoip_command(config, geoip)?;
+        }
         Command::UpdatePassword(update) => {
             let app = Liwan::try_new(config)?;
             app.users.update_password(&update.username, &update.password)?;
@@ -176,27 +190,30 @@ pub fn handle_command(mut config: Config, cmd: Command) -> Result<()> {
                 let settings = app.settings.resolved_for_entity(&entity.id);
                 let stats = app.events.prune_entity(&entity.id, &settings, prune.dry_run)?;
                 println!(
-                    "{}: total={}, delete={}, clear_utm={}, clear_geo={}, clear_sessions={}",
+                    "{}: total={}, delete={}, clear_utm={}, clear_geo={}, clear_sessions={}, clear_language={}",
                     entity.id,
                     stats.total_events,
                     stats.deleted_events,
                     stats.cleared_utm_events,
                     stats.cleared_geo_events,
-                    stats.cleared_session_events
+                    stats.cleared_session_events,
+                    stats.cleared_language_events
                 );
                 totals.total_events += stats.total_events;
                 totals.deleted_events += stats.deleted_events;
                 totals.cleared_utm_events += stats.cleared_utm_events;
                 totals.cleared_geo_events += stats.cleared_geo_events;
                 totals.cleared_session_events += stats.cleared_session_events;
+                totals.cleared_language_events += stats.cleared_language_events;
             }
             println!(
-                "total: total={}, delete={}, clear_utm={}, clear_geo={}, clear_sessions={}",
+                "total: total={}, delete={}, clear_utm={}, clear_geo={}, clear_sessions={}, clear_language={}",
                 totals.total_events,
                 totals.deleted_events,
                 totals.cleared_utm_events,
                 totals.cleared_geo_events,
-                totals.cleared_session_events
+                totals.cleared_session_events,
+                totals.cleared_language_events
             );
             if prune.dry_run {
                 println!("Dry run only. Re-run without --dry-run to apply changes.");
@@ -237,3 +254,49 @@ pub fn handle_command(mut config: Config, cmd: Command) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(feature = "geoip")]
+fn geoip_command(config: Config, cmd: Geoip) -> Result<()> {
+    use crate::app::{GeoIpCheckResult, LiwanGeoIP};
+
+    let geoip = LiwanGeoIP::try_new(config)?;
+    if cmd.update {
+        // the result is shown in the status below
+        let _ = tokio::task::block_in_place(|| tokio::runtime::Handle::current().block_on(geoip.check_for_updates()));
+    }
+
+    let databases = geoip.status();
+    if databases.is_empty() {
+        println!("GeoIP is not configured");
+        return Ok(());
+    }
+
+    let format_time =
+        |time: Option<chrono::DateTime<chrono::Utc>>| time.map_or("never".to_string(), |t| t.to_rfc3339());
+    for db in databases {
+        println!("{}:", db.edition);
+        println!("  path: {}", db.path);
+        println!("  source: {}", db.source);
+        println!("  loaded: {}", db.loaded);
+        println!("  type: {}", db.database_type.as_deref().unwrap_or("unknown"));
+        println!("  built: {}", format_time(db.build_epoch));
+        println!("  last reload: {}", format_time(db.last_reload));
+        if let Some(error) = db.reload_error {
+            println!("  reload error: {error}");
+        }
+        let result = match db.last_check_result {
+            Some(GeoIpCheckResult::Updated) => "updated".to_string(),
+            Some(GeoIpCheckResult::UpToDate) => "up to date".to_string(),
+            Some(GeoIpCheckResult::Failed(error)) => format!("failed: {error}"),
+            None => "-".to_string(),
+        };
+        println!("  last check: {} ({result})", format_time(db.last_check));
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "geoip"))]
+fn geoip_command(_config: Config, _cmd: Geoip) -> Result<()> {
+    anyhow::bail!("GeoIP support is not enabled in this build")
+}
@@ -32,6 +32,12 @@ pub struct Config {
     #[serde(default)]
     pub duckdb: DuckdbConfig,
 
+    #[serde(default)]
+    pub report_cache: ReportCacheConfig,
+
+    #[serde(default)]
+    pub report_limits: ReportLimitsConfig,
+
     /// Client IP header names or provider presets.
     /// Presets: `cloudflare`, `fastly`, `fly`, `cloudfront`, and `akamai`.
     #[serde(default, alias = "trusted_headers")]
@@ -51,6 +57,8 @@ impl Default for Config {
             data_dir: default_data_dir(),
             geoip: Default::default(),
             duckdb: Default::default(),
+            report_cache: Default::default(),
+            report_limits: Default::default(),
             disable_favicons: false,
             listen: None,
             port: None,
@@ -75,6 +83,32 @@ pub struct GeoIpConfig {
     pub maxmind_license_key: Option<String>,
     #[serde(default = "default_maxmind_edition")]
     pub maxmind_edition: String,
+    /// Optional ASN database edition, e.g. `GeoLite2-ASN`
+    #[serde(default)]
+    pub maxmind_asn_edition: Option<String>,
+    #[serde(default)]
+    pub maxmind_asn_db_path: Option<String>,
+    /// Mirror of the MaxMind update API, defaults to `https://updates.maxmind.com`
+    #[serde(default)]
+    pub maxmind_base_url: Option<String>,
+    /// Plain `.mmdb`, `.mmdb.gz` or `.tar.gz` download URL for the city database
+    #[serde(default)]
+    pub db_url: Option<String>,
+    /// URL of an MD5 or SHA-256 checksum file for `db_url`
+    #[serde(default)]
+    pub db_checksum_url: Option<String>,
+    /// Plain `.mmdb`, `.mmdb.gz` or `.tar.gz` download URL for the ASN database
+    #[serde(default)]
+    pub asn_db_url: Option<String>,
+    /// URL of an MD5 or SHA-256 checksum file for `asn_db_url`
+    #[serde(default)]
+    pub asn_db_checksum_url: Option<String>,
+    /// Allow `db_url` and `asn_db_url` downloads without a checksum URL, and `db_dir` files without a checksum file
+    #[serde(default)]
+    pub allow_unverified_downloads: bool,
+    /// Local directory to pick up `{edition}.mmdb` or `{edition}.mmdb.gz` files from
+    #[serde(default)]
+    pub db_dir: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -85,6 +119,45 @@ pub struct DuckdbConfig {
     pub threads: Option<NonZeroU16>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportCacheConfig {
+    /// Maximum number of cached report results, `0` disables the cache
+    #[serde(default = "default_report_cache_capacity")]
+    pub capacity: usize,
+    /// Seconds results of ranges touching the current time are cached for
+    #[serde(default = "default_report_cache_ttl_secs")]
+    pub ttl_secs: u64,
+}
+
+impl Default for ReportCacheConfig {
+    fn default() -> Self {
+        Self { capacity: default_report_cache_capacity(), ttl_secs: default_report_cache_ttl_secs() }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportLimitsConfig {
+    /// Seconds a single report query may run before it is interrupted
+    #[serde(default = "default_report_query_timeout_secs")]
+    pub query_timeout_secs: u64,
+    /// Maximum number of report queries running at the same time
+    #[serde(default = "default_report_max_concurrent_queries")]
+    pub max_concurrent_queries: usize,
+    /// Seconds a report query waits for one of the concurrent slots before the request is rejected
+    #[serde(default = "default_report_queue_timeout_secs")]
+    pub queue_timeout_secs: u64,
+}
+
+impl Default for ReportLimitsConfig {
+    fn default() -> Self {
+        Self {
+            query_timeout_secs: default_report_query_timeout_secs(),
+            max_concurrent_queries: default_report_max_concurrent_queries(),
+            queue_timeout_secs: default_report_queue_timeout_secs(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(untagged)]
 pub enum MaxMindAccountId {
@@ -131,6 +204,26 @@ fn default_visitor_group_rotation_hour() -> u8 {
     4
 }
 
+fn default_report_cache_capacity() -> usize {
+    1000
+}
+
+fn default_report_cache_ttl_secs() -> u64 {
+    60
+}
+
+fn default_report_query_timeout_secs() -> u64 {
+    30
+}
+
+fn default_report_max_concurrent_queries() -> usize {
+    8
+}
+
+fn default_report_queue_timeout_secs() -> u64 {
+    10
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(untagged)]
 pub enum ListenAddr {
@@ -198,6 +291,9 @@ impl Config {
         if config.visitor_group_rotation_hour > 23 {
             bail!("Invalid visitor_group_rotation_hour: must be between 0 and 23");
         }
+        if config.report_limits.max_concurrent_queries == 0 {
+            bail!("Invalid report_limits.max_concurrent_queries: must be at least 1");
+        }
 
         Ok(config)
     }
@@ -216,7 +312,17 @@ fn map_env_key(key: &str) -> Option<String> {
     if key == "geoip_headers" {
         return Some("geoip.headers".to_string());
     }
-    const NESTED_PREFIXES: &[(&str, &str)] = &[("maxmind_", "geoip.maxmind_"), ("duckdb_", "duckdb.")];
+    // MaxMind options are only read from `LIWAN_MAXMIND_*`
+    if key.starts_with("geoip_maxmind_") {
+        return Some(key);
+    }
+    const NESTED_PREFIXES: &[(&str, &str)] = &[
+        ("maxmind_", "geoip.maxmind_"),
+        ("geoip_", "geoip."),
+        ("duckdb_", "duckdb."),
+        ("report_cache_", "report_cache."),
+        ("report_limits_", "report_limits."),
+    ];
 
     for (prefix, mapped_prefix) in NESTED_PREFIXES {
         if let Some(rest) = key.strip_prefix(prefix) {
@@ -274,6 +380,8 @@ mod test {
             ("GEOIP_MAXMIND_EDITION", "test3"),
             ("LIWAN_DUCKDB_MEMORY_LIMIT", "2GB"),
             ("LIWAN_DUCKDB_THREADS", "4"),
+            ("LIWAN_REPORT_CACHE_TTL_SECS", "30"),
+            ("LIWAN_REPORT_LIMITS_MAX_CONCURRENT_QUERIES", "2"),
             ("LIWAN_MAXMIND_LICENSE_KEY", "test"),
             ("LIWAN_MAXMIND_ACCOUNT_ID", "test"),
             ("LIWAN_MAXMIND_DB_PATH", "test"),
@@ -290,6 +398,10 @@ mod test {
         assert_eq!(config.listen_addr(), "0.0.0.0:9042");
         assert_eq!(config.duckdb.memory_limit, Some("2GB".to_string()));
         assert_eq!(config.duckdb.threads, Some(NonZeroU16::new(4).unwrap()));
+        assert_eq!(config.report_cache.ttl_secs, 30);
+        assert_eq!(config.report_cache.capacity, 1000);
+        assert_eq!(config.report_limits.max_concurrent_queries, 2);
+        assert_eq!(config.report_limits.query_timeout_secs, 30);
     }
 
     #[test]
@@ -337,6 +449,8 @@ mod test {
             ("LIWAN_DATA_DIR", "/data"),
             ("LIWAN_BASE_URL", "https://example.com"),
             ("LIWAN_MAXMIND_ACCOUNT_ID", "123"),
+            ("LIWAN_MAXMIND_ASN_EDITION", "GeoLite2-ASN"),
+            ("LIWAN_GEOIP_DB_URL", "https://mirror.example.com/dbip-city-lite.mmdb.gz"),
             ("LIWAN_TRUSTED_HEADERS", "X_Forwarded_For,Forwarded"),
             ("LIWAN_TRUSTED_PROXIES", "127.0.0.1,10.0.0.0/8"),
         ];
@@ -345,6 +459,8 @@ mod test {
         assert_eq!(config.data_dir, "/data");
         assert_eq!(config.base_url, "https://example.com");
         assert_eq!(config.geoip.maxmind_account_id, Some(MaxMindAccountId::Number(123)));
+        assert_eq!(config.geoip.maxmind_asn_edition, Some("GeoLite2-ASN".to_string()));
+        assert_eq!(config.geoip.db_url, Some("https://mirror.example.com/dbip-city-lite.mmdb.gz".to_string()));
         assert_eq!(
             config.client_ip_headers.as_ref(),
             &[
@@ -0,0 +1,2 @@
+alter table settings add column track_language boolean not null default true;
+alter table entity_settings add column track_language boolean;
@@ -0,0 +1 @@
+alter table project_settings add column session_timeout_minutes integer;
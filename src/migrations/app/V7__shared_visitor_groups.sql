@@ -0,0 +1 @@
+alter table project_settings add column shared_visitor_groups boolean not null default false;
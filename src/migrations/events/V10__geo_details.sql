@@ -0,0 +1,3 @@
+alter table events add column region text;
+alter table events add column continent text;
+alter table events add column time_zone text;
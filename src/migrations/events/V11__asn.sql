@@ -0,0 +1,2 @@
+alter table events add column asn uinteger;
+alter table events add column asn_org text;
@@ -0,0 +1,34 @@
+create table rollups (
+    granularity text not null,
+    bucket timestamp not null,
+    entity_id text not null,
+    event text not null,
+
+    -- empty for entity totals
+    dimension text not null,
+    dimension_value text,
+
+    views bigint not null,
+    visitors bigint not null,
+);
+
+-- rollups are complete for all buckets before the watermark
+create table rollup_state (
+    granularity text primary key,
+    watermark timestamp not null,
+);
+
+-- HyperLogLog registers of the visitor groups in a daily rollup bucket, only registers with a visitor are stored
+create table rollup_sketches (
+    granularity text not null,
+    bucket timestamp not null,
+    entity_id text not null,
+    event text not null,
+
+    -- empty for entity totals
+    dimension text not null,
+    dimension_value text,
+
+    register usmallint not null,
+    rank utinyint not null,
+);
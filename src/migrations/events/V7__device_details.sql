@@ -0,0 +1,3 @@
+alter table events add column browser_version text;
+alter table events add column platform_version text;
+alter table events add column device_class text;
@@ -0,0 +1,279 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::utils::referrer::get_referer_name;
+
+/// Traffic channel an event is attributed to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Direct,
+    OrganicSearch,
+    PaidSearch,
+    Social,
+    Email,
+    Referral,
+    Campaign,
+}
+
+impl Channel {
+    /// Human-readable channel name, e.g. `Organic Search`
+    pub const fn display_name(self) -> &'static str {
+        match self {
+            Self::Direct => "Direct",
+            Self::OrganicSearch => "Organic Search",
+            Self::PaidSearch => "Paid Search",
+            Self::Social => "Social",
+            Self::Email => "Email",
+            Self::Referral => "Referral",
+            Self::Campaign => "Campaign",
+        }
+    }
+}
+
+impl Display for Channel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Direct => "direct",
+            Self::OrganicSearch => "organic_search",
+            Self::PaidSearch => "paid_search",
+            Self::Social => "social",
+            Self::Email => "email",
+            Self::Referral => "referral",
+            Self::Campaign => "campaign",
+        })
+    }
+}
+
+impl FromStr for Channel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "direct" => Ok(Self::Direct),
+            "organic_search" => Ok(Self::OrganicSearch),
+            "paid_search" => Ok(Self::PaidSearch),
+            "social" => Ok(Self::Social),
+            "email" => Ok(Self::Email),
+            "referral" => Ok(Self::Referral),
+            "campaign" => Ok(Self::Campaign),
+            _ => Err(format!("invalid channel: {s}")),
+        }
+    }
+}
+
+// names as listed in referrers.txt
+const SEARCH_ENGINES: &[&str] = &[
+    "Baidu",
+    "Bing",
+    "Brave Search",
+    "DuckDuckGo",
+    "Ecosia",
+    "Google",
+    "Google (Android Quick Search)",
+    "Kagi",
+    "Naver",
+    "Qwant",
+    "Seznam",
+    "Startpage",
+    "Yahoo",
+    "Yandex",
+];
+
+const SOCIAL_NETWORKS: &[&str] = &[
+    "Badoo",
+    "Bluesky",
+    "Bluesky (App)",
+    "Douban",
+    "Dribbble",
+    "Facebook",
+    "Facebook Messenger",
+    "Flickr",
+    "Foursquare",
+    "Hacker News",
+    "Instagram",
+    "Last.fm",
+    "LinkedIn",
+    "LiveJournal",
+    "Mastodon",
+    "Odnoklassniki",
+    "Pinterest",
+    "Pixelfed",
+    "reddit",
+    "reddit (App)",
+    "Renren",
+    "Snapchat",
+    "Telegram",
+    "Telegram (App)",
+    "Threads",
+    "TikTok",
+    "tumblr",
+    "twitch",
+    "V2EX",
+    "Viadeo",
+    "Vimeo",
+    "Vkontakte",
+    "Weibo",
+    "Workplace",
+    "X",
+    "XING",
+    "YouTube",
+];
+
+const WEBMAIL_HOSTS: &[&str] = &[
+    "mail.google.com",
+    "outlook.live.com",
+    "outlook.office.com",
+    "outlook.office365.com",
+    "mail.yahoo.com",
+    "mail.proton.me",
+    "app.fastmail.com",
+    "mail.yandex.ru",
+    "e.mail.ru",
+];
+
+const PAID_MEDIUMS: &[&str] = &["cpc", "ppc", "paid", "paidsearch", "paid_search", "paid-search", "sem"];
+const SOCIAL_MEDIUMS: &[&str] = &["social", "social-network", "social_network", "social-media", "social_media", "sm"];
+const EMAIL_MEDIUMS: &[&str] = &["email", "e-mail", "e_mail", "newsletter"];
+
+/// Query parameters added by ad networks to paid search clicks
+const PAID_SEARCH_CLICK_IDS: &[&str] = &["gclid", "gbraid", "wbraid", "dclid", "msclkid", "yclid"];
+/// Query parameters added by social networks to outgoing links and ad clicks
+const SOCIAL_CLICK_IDS: &[&str] = &["fbclid", "igshid", "ttclid", "twclid", "li_fat_id", "rdt_cid", "sccid"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SourceKind {
+    Search,
+    Social,
+    Email,
+    Other,
+}
+
+fn source_kind(source: &str) -> SourceKind {
+    let host = source.trim().trim_start_matches("www.").to_ascii_lowercase();
+    if WEBMAIL_HOSTS.contains(&host.as_str()) {
+        return SourceKind::Email;
+    }
+
+    let name = get_referer_name(&host).unwrap_or(host);
+    if SEARCH_ENGINES.iter().any(|engine| engine.eq_ignore_ascii_case(&name)) {
+        SourceKind::Search
+    } else if SOCIAL_NETWORKS.iter().any(|network| network.eq_ignore_ascii_case(&name)) {
+        SourceKind::Social
+    } else {
+        SourceKind::Other
+    }
+}
+
+fn is_same_host(referrer: &str, host: &str) -> bool {
+    referrer.trim().trim_start_matches("www.").eq_ignore_ascii_case(host.trim().trim_start_matches("www."))
+}
+
+/// Attribute an event to a traffic channel
+///
+/// `host` is the host of the tracked URL, referrers from the same host are navigations within the site and don't
+/// count as referrals. `query` is the raw query string of the tracked URL, which is checked for ad click IDs.
+pub fn classify_channel(
+    referrer: Option<&str>,
+    host: &str,
+    utm_source: Option<&str>,
+    utm_medium: Option<&str>,
+    utm_campaign: Option<&str>,
+    query: Option<&str>,
+) -> Channel {
+    let referrer = referrer.filter(|referrer| !is_same_host(referrer, host));
+    let medium = utm_medium.map(|medium| medium.trim().to_ascii_lowercase());
+    let medium = medium.as_deref();
+
+    let click_ids: Vec<String> = query
+        .map(|query| url::form_urlencoded::parse(query.as_bytes()).map(|(key, _)| key.to_ascii_lowercase()).collect())
+        .unwrap_or_default();
+    let has_click_id = |ids: &[&str]| click_ids.iter().any(|key| ids.contains(&key.as_str()));
+
+    // an explicit utm_source takes precedence over the referrer
+    let kind = utm_source
+        .map(source_kind)
+        .filter(|kind| *kind != SourceKind::Other)
+        .or_else(|| referrer.map(source_kind))
+        .unwrap_or(SourceKind::Other);
+
+    if medium.is_some_and(|medium| PAID_MEDIUMS.contains(&medium)) || has_click_id(PAID_SEARCH_CLICK_IDS) {
+        return if kind == SourceKind::Social { Channel::Social } else { Channel::PaidSearch };
+    }
+    if kind == SourceKind::Email || medium.is_some_and(|medium| EMAIL_MEDIUMS.contains(&medium)) {
+        return Channel::Email;
+    }
+    if kind == SourceKind::Social
+        || medium.is_some_and(|medium| SOCIAL_MEDIUMS.contains(&medium))
+        || has_click_id(SOCIAL_CLICK_IDS)
+    {
+        return Channel::Social;
+    }
+    if kind == SourceKind::Search || medium == Some("organic") {
+        return Channel::OrganicSearch;
+    }
+    if utm_source.is_some() || medium.is_some() || utm_campaign.is_some() {
+        return if medium == Some("referral") { Channel::Referral } else { Channel::Campaign };
+    }
+    if referrer.is_some() {
+        return Channel::Referral;
+    }
+
+    Channel::Direct
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_classify_channel() {
+        assert_eq!(classify_channel(None, "example.com", None, None, None, None), Channel::Direct);
+        assert_eq!(classify_channel(Some("google.de"), "example.com", None, None, None, None), Channel::OrganicSearch);
+        assert_eq!(
+            classify_channel(Some("bing.com"), "example.com", None, None, None, Some("msclkid=abc")),
+            Channel::PaidSearch
+        );
+        assert_eq!(classify_channel(None, "example.com", Some("google"), Some("cpc"), None, None), Channel::PaidSearch);
+        assert_eq!(classify_channel(None, "example.com", Some("facebook"), Some("cpc"), None, None), Channel::Social);
+        assert_eq!(classify_channel(Some("t.co"), "example.com", None, None, None, None), Channel::Social);
+        assert_eq!(
+            classify_channel(None, "example.com", None, None, None, Some("fbclid=123&foo=bar")),
+            Channel::Social
+        );
+        assert_eq!(classify_channel(Some("mail.google.com"), "example.com", None, None, None, None), Channel::Email);
+        assert_eq!(
+            classify_channel(None, "example.com", Some("newsletter"), Some("Email"), Some("spring"), None),
+            Channel::Email
+        );
+        assert_eq!(classify_channel(Some("github.com"), "example.com", None, None, None, None), Channel::Referral);
+        assert_eq!(
+            classify_channel(None, "example.com", Some("partner"), Some("referral"), None, None),
+            Channel::Referral
+        );
+        assert_eq!(
+            classify_channel(Some("github.com"), "example.com", Some("producthunt"), None, None, None),
+            Channel::Campaign
+        );
+        assert_eq!(classify_channel(None, "example.com", None, None, Some("launch"), None), Channel::Campaign);
+        assert_eq!(classify_channel(Some("example.com"), "www.example.com", None, None, None, None), Channel::Direct);
+        assert_eq!(
+            classify_channel(Some("example.com"), "example.com", Some("newsletter"), Some("email"), None, None),
+            Channel::Email
+        );
+    }
+
+    #[test]
+    fn test_channel_roundtrip() {
+        for channel in [
+            Channel::Direct,
+            Channel::OrganicSearch,
+            Channel::PaidSearch,
+            Channel::Social,
+            Channel::Email,
+            Channel::Referral,
+            Channel::Campaign,
+        ] {
+            assert_eq!(channel.to_string().parse::<Channel>(), Ok(channel));
+        }
+    }
+}
@@ -16,3 +16,17 @@ pub fn get_country_name(iso_2_code: &str) -> Option<String> {
 
     COUNTRIES.get(iso_2_code).map(ToString::to_string)
 }
+
+pub fn get_continent_name(code: &str) -> Option<String> {
+    let name = match code {
+        "AF" => "Africa",
+        "AN" => "Antarctica",
+        "AS" => "Asia",
+        "EU" => "Europe",
+        "NA" => "North America",
+        "OC" => "Oceania",
+        "SA" => "South America",
+        _ => return None,
+    };
+    Some(name.to_string())
+}
@@ -19,22 +19,17 @@ pub fn verify_password(password: &str, hash: &str) -> Result<()> {
     argon2.verify_password(password.as_bytes(), &hash).context("Failed to verify password")
 }
 
-pub fn visitor_group_id(ip: &IpAddr, user_agent: &str, daily_salt: &str, entity_id: &str) -> String {
-    hash_visitor_group(&[ip.to_string().as_bytes(), user_agent.as_bytes(), daily_salt.as_bytes(), entity_id.as_bytes()])
+/// Hash a visitor into a visitor group, `scope` is the entity or project visitor groups are derived for
+pub fn visitor_group_id(ip: &IpAddr, user_agent: &str, daily_salt: &str, scope: &str) -> String {
+    hash_visitor_group(&[ip.to_string().as_bytes(), user_agent.as_bytes(), daily_salt.as_bytes(), scope.as_bytes()])
 }
 
-pub fn visitor_group_id_cidr(
-    ip: &IpAddr,
-    ipv4_prefix: u8,
-    ipv6_prefix: u8,
-    daily_salt: &str,
-    entity_id: &str,
-) -> String {
+pub fn visitor_group_id_cidr(ip: &IpAddr, ipv4_prefix: u8, ipv6_prefix: u8, daily_salt: &str, scope: &str) -> String {
     let masked_ip = match ip {
         IpAddr::V4(ip) => IpAddr::V4(mask_ipv4(*ip, ipv4_prefix)),
         IpAddr::V6(ip) => IpAddr::V6(mask_ipv6(*ip, ipv6_prefix)),
     };
-    hash_visitor_group(&[masked_ip.to_string().as_bytes(), daily_salt.as_bytes(), entity_id.as_bytes()])
+    hash_visitor_group(&[masked_ip.to_string().as_bytes(), daily_salt.as_bytes(), scope.as_bytes()])
 }
 
 fn hash_visitor_group(parts: &[&[u8]]) -> String {
@@ -0,0 +1,104 @@
+use ahash::HashMap;
+use std::sync::LazyLock;
+
+static LANGUAGES: LazyLock<HashMap<String, String>> = LazyLock::new(|| {
+    include_str!("../../data/languages.txt")
+        .lines()
+        .map(|line| {
+            let mut parts = line.split('=');
+            let name = parts.next().expect("languages.txt is malformed").to_string();
+            let code = parts.next().expect("languages.txt is malformed").to_string();
+            (code, name)
+        })
+        .collect()
+});
+
+/// Return a human-readable name for a normalized language tag, e.g. `de-AT` becomes `German (Austria)`
+pub fn get_language_name(tag: &str) -> Option<String> {
+    let mut subtags = tag.split('-');
+    let language = LANGUAGES.get(subtags.next()?)?;
+    let region = subtags.find(|subtag| subtag.len() == 2).and_then(crate::utils::geo::get_country_name);
+
+    Some(match region {
+        Some(region) => format!("{language} ({region})"),
+        None => language.clone(),
+    })
+}
+
+/// Return the preferred language from an `Accept-Language` header as a normalized BCP 47 tag
+///
+/// Only the language, script and region subtags are kept, e.g. `zh-hant-tw` becomes `zh-Hant-TW`.
+pub fn parse_accept_language(header: &str) -> Option<String> {
+    let mut best: Option<(f32, String)> = None;
+
+    for entry in header.split(',') {
+        let mut parts = entry.split(';');
+        let Some(tag) = parts.next().and_then(normalize_language_tag) else {
+            continue;
+        };
+
+        let quality = parts
+            .find_map(|param| param.trim().strip_prefix("q="))
+            .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok().filter(|q| (0.0..=1.0).contains(q)));
+        let Some(quality) = quality.filter(|q| *q > 0.0) else {
+            continue;
+        };
+
+        // earlier entries win ties, since browsers list languages in preference order
+        if best.as_ref().is_none_or(|(best_quality, _)| quality > *best_quality) {
+            best = Some((quality, tag));
+        }
+    }
+
+    best.map(|(_, tag)| tag)
+}
+
+fn normalize_language_tag(tag: &str) -> Option<String> {
+    let mut subtags = tag.trim().split(['-', '_']);
+    let language =
+        subtags.next().filter(|l| (2..=3).contains(&l.len()) && l.bytes().all(|b| b.is_ascii_alphabetic()))?;
+    let mut normalized = language.to_ascii_lowercase();
+
+    let mut next = subtags.next();
+    if let Some(script) = next.filter(|s| s.len() == 4 && s.bytes().all(|b| b.is_ascii_alphabetic())) {
+        normalized.push('-');
+        normalized.push_str(&script[..1].to_ascii_uppercase());
+        normalized.push_str(&script[1..].to_ascii_lowercase());
+        next = subtags.next();
+    }
+
+    if let Some(region) = next.filter(|r| {
+        (r.len() == 2 && r.bytes().all(|b| b.is_ascii_alphabetic()))
+            || (r.len() == 3 && r.bytes().all(|b| b.is_ascii_digit()))
+    }) {
+        normalized.push('-');
+        normalized.push_str(&region.to_ascii_uppercase());
+    }
+
+    Some(normalized)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_accept_language() {
+        assert_eq!(parse_accept_language("en-US,en;q=0.9,de;q=0.8"), Some("en-US".to_string()));
+        assert_eq!(parse_accept_language("de;q=0.5, fr-ch;q=0.9"), Some("fr-CH".to_string()));
+        assert_eq!(parse_accept_language("zh-hant-tw"), Some("zh-Hant-TW".to_string()));
+        assert_eq!(parse_accept_language("es-419"), Some("es-419".to_string()));
+        assert_eq!(parse_accept_language("pt_br"), Some("pt-BR".to_string()));
+        assert_eq!(parse_accept_language("*"), None);
+        assert_eq!(parse_accept_language("en;q=0"), None);
+        assert_eq!(parse_accept_language(""), None);
+    }
+
+    #[test]
+    fn test_get_language_name() {
+        assert_eq!(get_language_name("en-US"), Some("English (United States of America)".to_string()));
+        assert_eq!(get_language_name("de"), Some("German".to_string()));
+        assert_eq!(get_language_name("zh-Hant-TW"), Some("Chinese (Taiwan)".to_string()));
+        assert_eq!(get_language_name("xx"), None);
+    }
+}
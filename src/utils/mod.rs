@@ -1,7 +1,9 @@
+pub mod channel;
 pub mod duckdb;
 pub mod geo;
 pub mod hash;
 pub mod ip_headers;
+pub mod language;
 pub mod r2d2_sqlite;
 pub mod referrer;
 pub mod refinery_duckdb;
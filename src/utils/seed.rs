@@ -6,16 +6,29 @@ const PATHS: &[&str] = &["/", "/about", "/contact", "/pricing", "/blog", "/login
 const REFERRERS: &[&str] = &["", "google.com", "twitter.com", "liwan.dev", "example.com", "henrygressmann.de"];
 const PLATFORMS: &[&str] = &["", "Windows", "macOS", "Linux", "Android", "iOS"];
 const BROWSERS: &[&str] = &["", "Chrome", "Firefox", "Safari", "Edge", "Opera"];
-const CITIES: &[(&str, &str)] = &[
-    ("", ""),
-    ("Paris", "FR"),
-    ("London", "GB"),
-    ("Berlin", "DE"),
-    ("Frankfurt", "DE"),
-    ("New York", "US"),
-    ("San Francisco", "US"),
-    ("Tokyo", "JP"),
-    ("Sydney", "AU"),
+const BROWSER_VERSIONS: &[&str] = &["", "15", "16", "17", "120", "124", "128"];
+const PLATFORM_VERSIONS: &[&str] = &["", "10", "11", "14", "15", "17"];
+const DEVICE_CLASSES: &[&str] = &["desktop", "mobile", "tablet", "tv", "console"];
+const CHANNELS: &[&str] = &["direct", "organic_search", "referral", "social", "campaign", "email", "paid_search"];
+const LANGUAGES: &[&str] = &["en-US", "en-GB", "de-DE", "fr-FR", "es-ES", "ja-JP", "pt-BR", ""];
+const CITIES: &[(&str, &str, &str, &str, &str)] = &[
+    ("", "", "", "", ""),
+    ("Paris", "FR", "Île-de-France", "EU", "Europe/Paris"),
+    ("London", "GB", "England", "EU", "Europe/London"),
+    ("Berlin", "DE", "Land Berlin", "EU", "Europe/Berlin"),
+    ("Frankfurt", "DE", "Hesse", "EU", "Europe/Berlin"),
+    ("New York", "US", "New York", "NA", "America/New_York"),
+    ("San Francisco", "US", "California", "NA", "America/Los_Angeles"),
+    ("Tokyo", "JP", "Tokyo", "AS", "Asia/Tokyo"),
+    ("Sydney", "AU", "New South Wales", "OC", "Australia/Sydney"),
+];
+const ASNS: &[(u32, &str)] = &[
+    (0, ""),
+    (3320, "Deutsche Telekom AG"),
+    (7922, "Comcast Cable Communications, LLC"),
+    (2856, "British Telecommunications PLC"),
+    (16509, "Amazon.com, Inc."),
+    (24940, "Hetzner Online GmbH"),
 ];
 const UTM_CAMPAIGNS: &[&str] = &["", "summer_sale", "black_friday", "christmas", "new_year"];
 const UTM_CONTENTS: &[&str] = &["", "banner", "sidebar", "footer", "popup"];
@@ -106,10 +119,16 @@ pub fn random_events(
         let referrer = random_el(REFERRERS, 0.9);
         let platform = random_el(PLATFORMS, -0.3);
         let browser = random_el(BROWSERS, 0.0);
+        let browser_version = random_el(BROWSER_VERSIONS, 0.0);
+        let platform_version = random_el(PLATFORM_VERSIONS, 0.0);
+        let device_class = random_el(DEVICE_CLASSES, 0.7);
         let mobile = rng.random_bool(0.48);
-        let (city, country) = random_el(CITIES, 0.8);
+        let (city, country, region, continent, time_zone) = random_el(CITIES, 0.8);
         let screen_width = random_el(SCREEN_WIDTH_BUCKETS, 0.0);
         let orientation = random_el(ORIENTATIONS, 0.0);
+        let language = random_el(LANGUAGES, 0.5);
+        let channel = random_el(CHANNELS, 0.6);
+        let (asn, asn_org) = random_el(ASNS, 0.5);
 
         Some(Event {
             browser: if browser.is_empty() { None } else { Some(browser.to_string()) },
@@ -131,6 +150,16 @@ pub fn random_events(
             utm_term: Some(random_el(UTM_TERMS, 0.6).to_string()),
             screen_width: Some(screen_width.to_string()),
             orientation: Some(orientation.to_string()),
+            browser_version: if browser_version.is_empty() { None } else { Some(browser_version.to_string()) },
+            platform_version: if platform_version.is_empty() { None } else { Some(platform_version.to_string()) },
+            device_class: Some(device_class.to_string()),
+            language: if language.is_empty() { None } else { Some(language.to_string()) },
+            channel: Some(channel.to_string()),
+            region: if region.is_empty() { None } else { Some(region.to_string()) },
+            continent: if continent.is_empty() { None } else { Some(continent.to_string()) },
+            time_zone: if time_zone.is_empty() { None } else { Some(time_zone.to_string()) },
+            asn: if *asn == 0 { None } else { Some(*asn) },
+            asn_org: if asn_org.is_empty() { None } else { Some(asn_org.to_string()) },
             track_sessions: true,
         })
     })
@@ -1,14 +1,54 @@
 use quick_cache::sync::Cache;
+use std::fmt::Display;
 use std::{io::Cursor, sync::LazyLock};
 use ua_parser::Extractor;
 
 #[derive(Clone, Debug, Default)]
 pub struct UserAgent {
     pub device_family: Option<String>,
+    pub device_class: DeviceClass,
     pub os_family: Option<String>,
+    pub os_version: Option<String>,
     pub ua_family: Option<String>,
+    pub ua_version: Option<String>,
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DeviceClass {
+    #[default]
+    Desktop,
+    Mobile,
+    Tablet,
+    Tv,
+    Console,
+}
+
+impl Display for DeviceClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Desktop => "desktop",
+            Self::Mobile => "mobile",
+            Self::Tablet => "tablet",
+            Self::Tv => "tv",
+            Self::Console => "console",
+        })
+    }
+}
+
+/// User-Agent client hints (`Sec-CH-UA*` headers) sent by Chromium-based browsers
+#[derive(Clone, Debug, Default)]
+pub struct ClientHints {
+    /// Brand names and their major versions, in header order
+    pub brands: Vec<(String, String)>,
+    pub mobile: Option<bool>,
+    pub platform: Option<String>,
+    pub platform_version: Option<String>,
+}
+
+const TV_TOKENS: &[&str] = &["smart-tv", "smarttv", "googletv", "appletv", "hbbtv", "crkey", "roku", "web0s"];
+const CONSOLE_TOKENS: &[&str] = &["playstation", "xbox", "nintendo"];
+const TABLET_TOKENS: &[&str] = &["ipad", "tablet", "kindle", "silk/"];
+
 static PARSER: LazyLock<Extractor<'static>> = LazyLock::new(|| {
     let data = zstd::decode_all(Cursor::new(include_bytes!("../../data/ua_regexes.json.zstd"))).expect("valid data");
     let regexes: ua_parser::Regexes = serde_json::from_slice(&data).expect("valid data");
@@ -30,9 +70,17 @@ pub fn parse(header: &str) -> UserAgent {
         return client;
     };
     let (ua, os, device) = PARSER.extract(header);
+    let os_family = os.as_ref().map(|os| os.os.replace("Mac OS X", "macOS").replace("Other", "Unknown"));
+    let os_version = os.as_ref().and_then(|os| {
+        format_os_version(os_family.as_deref().unwrap_or_default(), os.major.as_deref(), os.minor.as_deref())
+    });
+    let device_family = device.map(|d| d.device.to_string());
     let uap = UserAgent {
-        device_family: device.map(|d| d.device.to_string()),
-        os_family: os.map(|os| os.os.replace("Mac OS X", "macOS").replace("Other", "Unknown")),
+        device_class: classify_device(header, os_family.as_deref(), device_family.as_deref()),
+        device_family,
+        os_family,
+        os_version,
+        ua_version: ua.as_ref().and_then(|ua| ua.major.as_ref()).map(ToString::to_string),
         ua_family: ua.map(|ua| ua.family.to_string()),
     };
 
@@ -40,6 +88,95 @@ pub fn parse(header: &str) -> UserAgent {
     uap
 }
 
+fn format_os_version(os_family: &str, major: Option<&str>, minor: Option<&str>) -> Option<String> {
+    let major = major.filter(|major| !major.is_empty())?;
+    match minor {
+        // macOS 10.x releases are only distinguishable by their minor version
+        Some(minor) if os_family == "macOS" && major == "10" => Some(format!("{major}.{minor}")),
+        _ => Some(major.to_string()),
+    }
+}
+
+fn classify_device(header: &str, os_family: Option<&str>, device_family: Option<&str>) -> DeviceClass {
+    let header = header.to_ascii_lowercase();
+    if TV_TOKENS.iter().any(|token| header.contains(token)) || device_family.is_some_and(|d| d.contains("TV")) {
+        DeviceClass::Tv
+    } else if CONSOLE_TOKENS.iter().any(|token| header.contains(token)) {
+        DeviceClass::Console
+    } else if TABLET_TOKENS.iter().any(|token| header.contains(token))
+        || device_family == Some("iPad")
+        || (os_family == Some("Android") && !header.contains("mobile"))
+    {
+        DeviceClass::Tablet
+    } else if matches!(os_family, Some("iOS" | "Android")) || header.contains("mobi") {
+        DeviceClass::Mobile
+    } else {
+        DeviceClass::Desktop
+    }
+}
+
+impl ClientHints {
+    /// Parse client hints from the raw `Sec-CH-UA`, `Sec-CH-UA-Mobile`, `Sec-CH-UA-Platform` and `Sec-CH-UA-Platform-Version` values
+    pub fn parse(
+        brands: Option<&str>,
+        mobile: Option<&str>,
+        platform: Option<&str>,
+        platform_version: Option<&str>,
+    ) -> Self {
+        let unquote = |value: &str| value.trim().trim_matches('"').to_string();
+        let brands = brands
+            .map(|brands| {
+                brands
+                    .split(',')
+                    .filter_map(|brand| {
+                        let mut parts = brand.split(';');
+                        let name = unquote(parts.next()?);
+                        let version = parts.find_map(|part| part.trim().strip_prefix("v=").map(unquote))?;
+                        (!name.is_empty() && !version.is_empty()).then_some((name, version))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            brands,
+            mobile: mobile.and_then(|mobile| match mobile.trim() {
+                "?1" => Some(true),
+                "?0" => Some(false),
+                _ => None,
+            }),
+            platform: platform.map(unquote).filter(|platform| !platform.is_empty()),
+            platform_version: platform_version.map(unquote).filter(|version| !version.is_empty()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.brands.is_empty() && self.mobile.is_none() && self.platform.is_none() && self.platform_version.is_none()
+    }
+
+    /// Return the major version of the most specific brand, skipping GREASE entries and the generic Chromium brand
+    fn browser_version(&self) -> Option<&str> {
+        let brands = || self.brands.iter().filter(|(name, _)| !name.contains("Brand") && !name.starts_with("Not"));
+        brands()
+            .find(|(name, _)| name != "Chromium")
+            .or_else(|| brands().next())
+            .map(|(_, version)| version.split('.').next().unwrap_or(version.as_str()))
+    }
+
+    fn os_version(&self, os_family: &str) -> Option<String> {
+        let version = self.platform_version.as_deref()?;
+        let mut parts = version.split('.');
+        let major = parts.next()?.parse::<u32>().ok()?;
+        match os_family {
+            // https://learn.microsoft.com/en-us/microsoft-edge/web-platform/how-to-detect-win11
+            "Windows" if major >= 13 => Some("11".to_string()),
+            "Windows" if major > 0 => Some("10".to_string()),
+            "Windows" => None,
+            _ => format_os_version(os_family, Some(&major.to_string()), parts.next()),
+        }
+    }
+}
+
 pub fn is_crawler_header(header: &str) -> bool {
     let header = header.to_ascii_lowercase();
     CRAWLER_TOKENS.iter().any(|crawler| header.contains(crawler))
@@ -53,6 +190,33 @@ impl UserAgent {
         parse(header)
     }
 
+    /// Prefer client hints over the (partially frozen) User-Agent string where available
+    pub fn with_client_hints(mut self, hints: &ClientHints) -> Self {
+        if hints.is_empty() {
+            return self;
+        }
+
+        if let Some(version) = hints.browser_version() {
+            self.ua_version = Some(version.to_string());
+        }
+
+        if let Some(platform) = &hints.platform
+            && self.os_family.as_deref().is_none_or(|os| os == "Unknown")
+        {
+            self.os_family = Some(platform.clone());
+        }
+
+        if let Some(version) = hints.os_version(self.os_family.as_deref().unwrap_or_default()) {
+            self.os_version = Some(version);
+        }
+
+        if hints.mobile == Some(true) && self.device_class == DeviceClass::Desktop {
+            self.device_class = DeviceClass::Mobile;
+        }
+
+        self
+    }
+
     pub fn is_bot(&self) -> bool {
         self.device_family == Some("Spider".into()) || self.ua_family == Some("HeadlessChrome".into())
     }
@@ -74,6 +238,41 @@ mod test {
         assert_eq!(client.device_family, Some("iPhone".into()), "Expected device family to be iPhone");
         assert!(client.is_mobile(), "Expected device to be mobile");
         assert!(!client.is_bot(), "Expected device to not be a bot");
+        assert_eq!(client.ua_version, Some("13".into()), "Expected browser version to be 13");
+        assert_eq!(client.os_version, Some("13".into()), "Expected OS version to be 13");
+        assert_eq!(client.device_class, DeviceClass::Mobile, "Expected device class to be mobile");
+    }
+
+    #[test]
+    fn device_class_detects_tablets_and_desktops() {
+        let ipad = "Mozilla/5.0 (iPad; CPU OS 15_4 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/15.4 Mobile/15E148 Safari/604.1";
+        let android_tablet = "Mozilla/5.0 (Linux; Android 13; SM-X700) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
+        let mac = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/15.6 Safari/605.1.15";
+
+        assert_eq!(parse(ipad).device_class, DeviceClass::Tablet);
+        assert_eq!(parse(android_tablet).device_class, DeviceClass::Tablet);
+
+        let mac = parse(mac);
+        assert_eq!(mac.device_class, DeviceClass::Desktop);
+        assert_eq!(mac.ua_version, Some("15".into()));
+        assert_eq!(mac.os_version, Some("10.15".into()));
+    }
+
+    #[test]
+    fn client_hints_override_frozen_versions() {
+        let user_agent = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36 Edg/124.0.0.0";
+        let hints = ClientHints::parse(
+            Some(r#""Chromium";v="124", "Microsoft Edge";v="124", "Not-A.Brand";v="99""#),
+            Some("?0"),
+            Some(r#""Windows""#),
+            Some(r#""15.0.0""#),
+        );
+
+        assert_eq!(hints.brands.len(), 3);
+        let client = parse(user_agent).with_client_hints(&hints);
+        assert_eq!(client.ua_version, Some("124".into()));
+        assert_eq!(client.os_version, Some("11".into()), "Windows 11 reports platform version 13 or higher");
+        assert_eq!(client.device_class, DeviceClass::Desktop);
     }
 
     #[test]
@@ -1,5 +1,10 @@
 use crate::app::models::{Project, User, UserRole};
 pub const MAX_DATAPOINTS: u32 = 2000;
+pub const MAX_BREAKDOWN_SERIES: usize = 20;
+pub const MAX_PIVOT_VALUES: usize = 50;
+pub const MAX_MOVERS: usize = 100;
+pub const MAX_JOURNEY_STEPS: usize = 10;
+pub const MAX_JOURNEY_PAGES: usize = 50;
 
 pub fn is_valid_id(id: &str) -> bool {
     id.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.' || c == ':')
@@ -17,7 +17,7 @@ use crate::{
             CollectionSettings, Entity, EntityCollectionSettings, Project, ProjectDisplaySettings,
             ResolvedCollectionSettings, UserRole,
         },
-        reports::{Dimension, Metric},
+        reports::{Dimension, Metric, SessionTimeout},
     },
     utils::validate::{can_enumerate_project, can_view_project},
     web::{
@@ -50,6 +50,20 @@ pub fn router() -> ApiRouter<RouterState> {
         .api_route("/settings", get(settings_handler))
         .api_route("/settings", put(settings_update_handler))
         .api_route("/settings/prune", post(prune_handler))
+        .merge(geoip_router())
+}
+
+#[cfg(feature = "geoip")]
+fn geoip_router() -> ApiRouter<RouterState> {
+    ApiRouter::new()
+        .api_route("/geoip", get(geoip_status_handler))
+        .api_route("/geoip/update", post(geoip_update_handler))
+        .api_route("/geoip/reload", post(geoip_reload_handler))
+}
+
+#[cfg(not(feature = "geoip"))]
+fn geoip_router() -> ApiRouter<RouterState> {
+    ApiRouter::new()
 }
 
 pub struct AdminAPI;
@@ -223,6 +237,7 @@ struct PruneEntityStats {
     cleared_utm_events: u64,
     cleared_geo_events: u64,
     cleared_session_events: u64,
+    cleared_language_events: u64,
 }
 
 #[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
@@ -471,12 +486,14 @@ async fn prune_handler(
                 cleared_utm_events: stats.cleared_utm_events,
                 cleared_geo_events: stats.cleared_geo_events,
                 cleared_session_events: stats.cleared_session_events,
+                cleared_language_events: stats.cleared_language_events,
             };
             response.total.total_events += entity_stats.total_events;
             response.total.deleted_events += entity_stats.deleted_events;
             response.total.cleared_utm_events += entity_stats.cleared_utm_events;
             response.total.cleared_geo_events += entity_stats.cleared_geo_events;
             response.total.cleared_session_events += entity_stats.cleared_session_events;
+            response.total.cleared_language_events += entity_stats.cleared_language_events;
             response.entities.push(entity_stats);
         }
         anyhow::Ok(response)
@@ -488,6 +505,52 @@ async fn prune_handler(
     Ok(Json(response))
 }
 
+#[cfg(feature = "geoip")]
+#[derive(Serialize, JsonSchema, Debug, Clone)]
+struct GeoIpStatusResponse {
+    databases: Vec<crate::app::GeoIpDatabaseStatus>,
+}
+
+#[cfg(feature = "geoip")]
+async fn geoip_status_handler(
+    app: State<RouterState>,
+    Auth(user): Auth,
+) -> ApiResult<UseApi<impl IntoApiResponse, Json<GeoIpStatusResponse>>> {
+    if user.role != UserRole::Admin {
+        http_bail!(StatusCode::FORBIDDEN, "Forbidden")
+    }
+
+    let resp = Json(GeoIpStatusResponse { databases: app.geoip.status() });
+    Ok(([(http::header::CACHE_CONTROL, "private")], resp).into())
+}
+
+#[cfg(feature = "geoip")]
+async fn geoip_update_handler(app: State<RouterState>, Auth(user): Auth) -> ApiResult<Json<GeoIpStatusResponse>> {
+    if user.role != UserRole::Admin {
+        http_bail!(StatusCode::FORBIDDEN, "Forbidden")
+    }
+    if app.geoip.is_updating() {
+        http_bail!(StatusCode::CONFLICT, "GeoIP update already in progress")
+    }
+
+    // failures are reported in the status of each database
+    let _ = app.geoip.check_for_updates().await;
+    Ok(Json(GeoIpStatusResponse { databases: app.geoip.status() }))
+}
+
+#[cfg(feature = "geoip")]
+async fn geoip_reload_handler(app: State<RouterState>, Auth(user): Auth) -> ApiResult<Json<GeoIpStatusResponse>> {
+    if user.role != UserRole::Admin {
+        http_bail!(StatusCode::FORBIDDEN, "Forbidden")
+    }
+    if app.geoip.is_updating() {
+        http_bail!(StatusCode::CONFLICT, "GeoIP update already in progress")
+    }
+
+    // failures are reported in the status of each database
+    Ok(Json(GeoIpStatusResponse { databases: app.geoip.reload() }))
+}
+
 async fn project_settings_handler(
     app: State<RouterState>,
     Path(project_id): Path<String>,
@@ -517,6 +580,13 @@ async fn project_settings_update_handler(
     }
 
     app.projects.get(&project_id).http_status(StatusCode::NOT_FOUND)?;
+    if settings.session_timeout_minutes.is_some_and(|minutes| minutes == 0 || minutes > SessionTimeout::MAX.minutes()) {
+        http_bail!(
+            StatusCode::BAD_REQUEST,
+            "Session timeout must be between 1 and {} minutes",
+            SessionTimeout::MAX.minutes()
+        )
+    }
     settings.project_id = project_id;
     app.project_settings
         .update(&settings)
@@ -1,8 +1,15 @@
-use crate::app::reports::{self, DateRange, Dimension, DimensionFilter, GraphInterval, Metric, ReportStats};
+use crate::app::DuckDBConn;
+use crate::app::reports::{
+    self, CacheableReport, Comparison, DateRange, Dimension, DimensionFilter, DimensionTableOptions, GraphInterval,
+    JourneyDirection, JourneyOptions, Metric, MoversOptions, MoversOrder, ReportCacheKey, ReportKind, ReportLimitError,
+    ReportQuery, ReportStats, ReportTableRow, SortOrder, WeekStart,
+};
 use crate::utils::validate::{self, can_view_project};
 use crate::web::RouterState;
 use crate::web::session::MaybeAuth;
-use crate::web::webext::{ApiResult, AxumErrExt, http_bail};
+use crate::web::webext::{ApiError, ApiResult, AxumErrExt, http_bail};
+
+use std::collections::HashMap;
 
 use aide::axum::{ApiRouter, routing::*};
 use axum::Json;
@@ -11,25 +18,112 @@ use chrono::{DateTime, Utc};
 use http::StatusCode;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use tokio::task::spawn_blocking;
 
 pub fn router() -> ApiRouter<RouterState> {
     ApiRouter::new()
         .api_route("/project/{project_id}/earliest", get(project_earliest_handler))
         .api_route("/project/{project_id}/graph", post(project_graph_handler))
+        .api_route("/project/{project_id}/graph/breakdown", post(project_breakdown_graph_handler))
         .api_route("/project/{project_id}/stats", post(project_stats_handler))
         .api_route("/project/{project_id}/dimension", post(project_detailed_handler))
+        .api_route("/project/{project_id}/pivot", post(project_pivot_handler))
+        .api_route("/project/{project_id}/heatmap", post(project_heatmap_handler))
+        .api_route("/project/{project_id}/movers", post(project_movers_handler))
+        .api_route("/project/{project_id}/journey", post(project_journey_handler))
+}
+
+/// Map a failed report query to a 503 if the server is busy, a 504 if it timed out, and a 500 otherwise
+fn report_error(err: anyhow::Error) -> ApiError {
+    let (status, message) = match err.downcast_ref::<ReportLimitError>() {
+        Some(ReportLimitError::Busy) => {
+            (StatusCode::SERVICE_UNAVAILABLE, "Too many reports are being generated, please try again later")
+        }
+        Some(ReportLimitError::TimedOut) => (StatusCode::GATEWAY_TIMEOUT, "Report took too long to generate"),
+        None => {
+            tracing::error!("failed to generate report: {err}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into();
+        }
+    };
+    tracing::warn!("{err}");
+    ApiError { message: message.to_string(), status }
+}
+
+/// Return the cached report for `key`, or compute it in one of the limited report query slots
+///
+/// Cache hits are answered right away, so they don't wait for a slot and aren't rejected while all slots are busy.
+async fn cached_report<T: CacheableReport + Send + 'static>(
+    app: &RouterState,
+    key: ReportCacheKey,
+    compute: impl FnOnce(&DuckDBConn, &ReportQuery) -> anyhow::Result<T> + Send + 'static,
+) -> ApiResult<T> {
+    if let Some(report) = app.report_cache.get(&key) {
+        return Ok(report);
+    }
+
+    let cache = app.report_cache.clone();
+    app.report_limiter
+        .run(move |conn| {
+            let query = key.query();
+            cache.get_or_compute(key.clone(), || compute(conn, &query))
+        })
+        .await
+        .map_err(report_error)
+}
+
+/// Return the range `range` is compared against, or a 400 if it is invalid
+fn comparison_range(comparison: &Comparison, range: &DateRange) -> ApiResult<DateRange> {
+    match comparison.range(range) {
+        Some(comparison_range) if comparison_range.start < comparison_range.end => Ok(comparison_range),
+        _ => http_bail!(StatusCode::BAD_REQUEST, "Invalid comparison range"),
+    }
 }
 
 #[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(rename_all = "camelCase")]
 struct GraphResponse {
     data: reports::ReportGraph,
+    /// Comparison values, aligned by index to `data`
+    comparison: Option<reports::ReportGraph>,
+    comparison_range: Option<DateRange>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(rename_all = "camelCase")]
+struct BreakdownGraphRequest {
+    range: DateRange,
+    filters: Vec<DimensionFilter>,
+    interval: GraphInterval,
+    timezone: Option<String>,
+    /// First day of weekly buckets, defaults to ISO weeks starting on Monday
+    #[serde(default)]
+    week_start: WeekStart,
+    metric: Metric,
+    dimension: Dimension,
+    /// Number of top dimension values with their own series, all other values are combined
+    #[serde(default = "default_breakdown_limit")]
+    limit: usize,
+    #[serde(default)]
+    approximate: bool,
+}
+
+const fn default_breakdown_limit() -> usize {
+    5
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+struct BreakdownGraphResponse {
+    data: reports::ReportBreakdownGraph,
 }
 
 #[derive(Serialize, Deserialize, JsonSchema, Clone)]
 struct StatsRequest {
     range: DateRange,
     filters: Vec<DimensionFilter>,
+    #[serde(default)]
+    approximate: bool,
+    /// Range of `stats_prev`, defaults to the previous period
+    #[serde(default)]
+    comparison: Comparison,
 }
 
 #[derive(Serialize, Deserialize, JsonSchema, Clone)]
@@ -39,7 +133,13 @@ struct GraphRequest {
     filters: Vec<DimensionFilter>,
     interval: GraphInterval,
     timezone: Option<String>,
+    /// First day of weekly buckets, defaults to ISO weeks starting on Monday
+    #[serde(default)]
+    week_start: WeekStart,
     metric: Metric,
+    #[serde(default)]
+    approximate: bool,
+    comparison: Option<Comparison>,
 }
 
 #[derive(Serialize, Deserialize, JsonSchema, Clone)]
@@ -48,6 +148,7 @@ struct StatsResponse {
     current_visitors: u64,
     stats: ReportStats,
     stats_prev: ReportStats,
+    comparison_range: DateRange,
 }
 
 #[derive(Serialize, Deserialize, JsonSchema, Clone)]
@@ -57,12 +158,144 @@ struct DimensionRequest {
     filters: Vec<DimensionFilter>,
     metric: Metric,
     dimension: Dimension,
+    #[serde(default)]
+    approximate: bool,
+    /// Maximum number of rows, all rows if not set
+    limit: Option<usize>,
+    /// Number of rows to skip
+    #[serde(default)]
+    offset: usize,
+    #[serde(default)]
+    order: SortOrder,
+    /// Only include values containing this text, ignoring case
+    search: Option<String>,
+    /// Combine all rows after the returned ones into `other`
+    #[serde(default)]
+    include_other: bool,
+    comparison: Option<Comparison>,
 }
 
 #[derive(Serialize, Deserialize, JsonSchema, Clone)]
 #[serde(rename_all = "camelCase")]
 struct DimensionResponse {
     data: Vec<DimensionTableRow>,
+    /// Number of rows matching the search across all pages
+    total: u64,
+    /// Metric value of all rows after the returned ones, if requested
+    other: Option<f64>,
+    comparison_range: Option<DateRange>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(rename_all = "camelCase")]
+struct PivotRequest {
+    range: DateRange,
+    filters: Vec<DimensionFilter>,
+    metric: Metric,
+    /// Dimension grouping the rows of the matrix
+    rows: Dimension,
+    /// Dimension grouping the columns of the matrix
+    columns: Dimension,
+    /// Number of top values of each dimension to include
+    #[serde(default = "default_pivot_limit")]
+    limit: usize,
+    #[serde(default)]
+    approximate: bool,
+}
+
+const fn default_pivot_limit() -> usize {
+    10
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+struct PivotResponse {
+    data: reports::ReportPivot,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(rename_all = "camelCase")]
+struct HeatmapRequest {
+    range: DateRange,
+    filters: Vec<DimensionFilter>,
+    metric: Metric,
+    /// Timezone of the weekdays and hours, defaults to UTC
+    timezone: Option<String>,
+    #[serde(default)]
+    approximate: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+struct HeatmapResponse {
+    data: reports::ReportHeatmap,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(rename_all = "camelCase")]
+struct MoversRequest {
+    range: DateRange,
+    filters: Vec<DimensionFilter>,
+    metric: Metric,
+    dimension: Dimension,
+    /// Range to compare against, defaults to the previous period
+    #[serde(default)]
+    comparison: Comparison,
+    #[serde(default = "default_movers_limit")]
+    limit: usize,
+    /// Minimum views in either range, or the metric itself for counts, to leave out noise
+    #[serde(default = "default_movers_min_volume")]
+    min_volume: u64,
+    #[serde(default)]
+    order: MoversOrder,
+    #[serde(default)]
+    approximate: bool,
+}
+
+const fn default_movers_limit() -> usize {
+    10
+}
+
+const fn default_movers_min_volume() -> u64 {
+    10
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(rename_all = "camelCase")]
+struct MoversResponse {
+    data: reports::ReportMovers,
+    comparison_range: DateRange,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(rename_all = "camelCase")]
+struct JourneyRequest {
+    range: DateRange,
+    filters: Vec<DimensionFilter>,
+    /// URL of the page to start from, e.g. `example.com/docs`
+    url: String,
+    #[serde(default)]
+    direction: JourneyDirection,
+    /// Number of pageviews to follow from the page
+    #[serde(default = "default_journey_steps")]
+    steps: usize,
+    /// Number of top pages per step, all other pages are combined
+    #[serde(default = "default_journey_limit")]
+    limit: usize,
+    /// Only follow sessions that start on the page, for forward journeys
+    #[serde(default)]
+    entry_only: bool,
+}
+
+const fn default_journey_steps() -> usize {
+    3
+}
+
+const fn default_journey_limit() -> usize {
+    10
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+struct JourneyResponse {
+    data: reports::ReportJourney,
 }
 
 #[derive(Serialize, Deserialize, JsonSchema, Clone)]
@@ -72,6 +305,8 @@ struct DimensionTableRow {
     value: f64,
     display_name: Option<String>,
     icon: Option<String>,
+    /// Metric value of the same dimension value in the comparison range
+    comparison: Option<f64>,
 }
 
 #[derive(Serialize, JsonSchema)]
@@ -114,22 +349,118 @@ async fn project_graph_handler(
         http_bail!(StatusCode::BAD_REQUEST, "Metric is hidden for this project")
     }
 
-    let conn = app.events_conn().http_status(StatusCode::INTERNAL_SERVER_ERROR)?;
-    let buckets = reports::build_graph_buckets(&req.range, req.interval, req.timezone.as_deref())
-        .http_status(StatusCode::BAD_REQUEST)?;
+    let max_buckets = validate::MAX_DATAPOINTS as usize / if req.comparison.is_some() { 2 } else { 1 };
+    let buckets =
+        reports::build_graph_buckets(&req.range, req.interval, req.timezone.as_deref(), req.week_start, max_buckets)
+            .http_status(StatusCode::BAD_REQUEST)?
+            .http_err("Too many data points", StatusCode::BAD_REQUEST)?;
+
+    let comparison = match &req.comparison {
+        Some(comparison) => {
+            let range = comparison_range(comparison, &req.range)?;
+            let buckets = comparison
+                .buckets(&req.range, &buckets)
+                .http_err("Invalid comparison range", StatusCode::BAD_REQUEST)?;
+            Some((range, buckets))
+        }
+        None => None,
+    };
+
+    let query = ReportQuery {
+        entities: &entities,
+        event: "pageview",
+        range: &req.range,
+        filters: &req.filters,
+        session_timeout: app.session_timeout(&project.id),
+        approximate: req.approximate,
+        shared_visitor_groups: app.shared_visitor_groups(&project.id),
+    };
+    let kind = |aligned_to: Option<DateRange>| ReportKind::Graph {
+        metric: req.metric,
+        interval: req.interval,
+        timezone: req.timezone.clone(),
+        week_start: req.week_start,
+        aligned_to,
+    };
+    let metric = req.metric;
+
+    let key = ReportCacheKey::new(&project.id, &query, kind(None));
+    let report =
+        cached_report(&app, key, move |conn, query| reports::overall_report(conn, query, &buckets, &metric)).await?;
+
+    let comparison_range = comparison.as_ref().map(|(range, _)| range.clone());
+    let comparison = match comparison {
+        Some((range, buckets)) => {
+            let key = ReportCacheKey::new(
+                &project.id,
+                &ReportQuery { range: &range, ..query },
+                kind(Some(req.range.clone())),
+            );
+            Some(
+                cached_report(&app, key, move |conn, query| reports::overall_report(conn, query, &buckets, &metric))
+                    .await?,
+            )
+        }
+        None => None,
+    };
 
-    if buckets.len() > validate::MAX_DATAPOINTS as usize {
-        http_bail!(StatusCode::BAD_REQUEST, "Too many data points")
+    Ok(Json(GraphResponse { data: report, comparison, comparison_range }))
+}
+
+async fn project_breakdown_graph_handler(
+    app: State<RouterState>,
+    Path(project_id): Path<String>,
+    MaybeAuth(user): MaybeAuth,
+    Json(req): Json<BreakdownGraphRequest>,
+) -> ApiResult<Json<BreakdownGraphResponse>> {
+    let project = app.projects.get(&project_id).http_status(StatusCode::NOT_FOUND)?;
+    let entities = app.projects.entity_ids(&project.id).http_status(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if !can_view_project(&project, user.as_ref()) {
+        http_bail!(StatusCode::NOT_FOUND, "Project not found")
+    }
+
+    if app.is_metric_hidden(&project.id, &entities, req.metric) {
+        http_bail!(StatusCode::BAD_REQUEST, "Metric is hidden for this project")
+    }
+    if app.is_dimension_hidden(&project.id, &entities, req.dimension) {
+        http_bail!(StatusCode::BAD_REQUEST, "Dimension is hidden for this project")
+    }
+    if !(1..=validate::MAX_BREAKDOWN_SERIES).contains(&req.limit) {
+        http_bail!(StatusCode::BAD_REQUEST, "Limit must be between 1 and {}", validate::MAX_BREAKDOWN_SERIES)
     }
 
-    let report = spawn_blocking(move || {
-        reports::overall_report(&conn, &entities, "pageview", &req.range, &buckets, &req.filters, &req.metric)
+    // one series per top value and one for all other values
+    let max_buckets = validate::MAX_DATAPOINTS as usize / (req.limit + 1);
+    let buckets =
+        reports::build_graph_buckets(&req.range, req.interval, req.timezone.as_deref(), req.week_start, max_buckets)
+            .http_status(StatusCode::BAD_REQUEST)?
+            .http_err("Too many data points", StatusCode::BAD_REQUEST)?;
+
+    let query = ReportQuery {
+        entities: &entities,
+        event: "pageview",
+        range: &req.range,
+        filters: &req.filters,
+        session_timeout: app.session_timeout(&project.id),
+        approximate: req.approximate,
+        shared_visitor_groups: app.shared_visitor_groups(&project.id),
+    };
+    let kind = ReportKind::Breakdown {
+        metric: req.metric,
+        dimension: req.dimension,
+        limit: req.limit,
+        interval: req.interval,
+        timezone: req.timezone.clone(),
+        week_start: req.week_start,
+    };
+    let (dimension, metric, limit) = (req.dimension, req.metric, req.limit);
+    let report = cached_report(&app, ReportCacheKey::new(&project.id, &query, kind), move |conn, query| {
+        reports::breakdown_report(conn, query, &buckets, &dimension, &metric, limit)
     })
-    .await
-    .http_status(StatusCode::INTERNAL_SERVER_ERROR)?
-    .http_status(StatusCode::INTERNAL_SERVER_ERROR)?;
+    .await?;
 
-    Ok(Json(GraphResponse { data: report }))
+    Ok(Json(BreakdownGraphResponse { data: report }))
 }
 
 async fn project_stats_handler(
@@ -144,39 +475,36 @@ async fn project_stats_handler(
     }
 
     let entities = app.projects.entity_ids(&project.id).http_status(StatusCode::INTERNAL_SERVER_ERROR)?;
-    let (entities2, entities3) = (entities.clone(), entities.clone());
-
-    let conn = app.events_conn().http_status(StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let req2 = req.clone();
-    let conn2 = app.events_conn().http_status(StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let (stats, stats_prev) = tokio::try_join!(
-        spawn_blocking(move || { reports::overall_stats(&conn, &entities, "pageview", &req.range, &req.filters) }),
-        spawn_blocking(move || {
-            reports::overall_stats(&conn2, &entities2, "pageview", &req2.range.prev(), &req2.filters)
-        })
-    )
-    .http_status(StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let (mut stats, mut stats_prev) = (
-        stats.http_status(StatusCode::INTERNAL_SERVER_ERROR)?,
-        stats_prev.http_status(StatusCode::INTERNAL_SERVER_ERROR)?,
-    );
-
-    if app.is_metric_hidden(&project.id, &entities3, Metric::BounceRate) {
-        stats.bounce_rate = None;
-        stats_prev.bounce_rate = None;
-    }
-    if app.is_metric_hidden(&project.id, &entities3, Metric::AvgTimeOnSite) {
-        stats.avg_time_on_site = None;
-        stats_prev.avg_time_on_site = None;
+    let comparison_range = comparison_range(&req.comparison, &req.range)?;
+
+    let query = ReportQuery {
+        entities: &entities,
+        event: "pageview",
+        range: &req.range,
+        filters: &req.filters,
+        session_timeout: app.session_timeout(&project.id),
+        approximate: req.approximate,
+        shared_visitor_groups: app.shared_visitor_groups(&project.id),
+    };
+    let key = ReportCacheKey::new(&project.id, &query, ReportKind::Stats);
+    let key_prev =
+        ReportCacheKey::new(&project.id, &ReportQuery { range: &comparison_range, ..query }, ReportKind::Stats);
+    let (mut stats, mut stats_prev) = tokio::try_join!(
+        cached_report(&app, key, reports::overall_stats),
+        cached_report(&app, key_prev, reports::overall_stats),
+    )?;
+
+    for metric in Metric::all().iter().filter(|metric| metric.is_session_metric()) {
+        if app.is_metric_hidden(&project.id, &entities, *metric) {
+            stats.clear_metric(*metric);
+            stats_prev.clear_metric(*metric);
+        }
     }
 
-    let online = reports::online_users(&app.events_conn().http_status(StatusCode::INTERNAL_SERVER_ERROR)?, &entities3)
+    let online = reports::online_users(&app.events_conn().http_status(StatusCode::INTERNAL_SERVER_ERROR)?, &entities)
         .http_status(StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    Ok(Json(StatsResponse { current_visitors: online, stats, stats_prev }))
+    Ok(Json(StatsResponse { current_visitors: online, stats, stats_prev, comparison_range }))
 }
 
 async fn project_detailed_handler(
@@ -199,17 +527,62 @@ async fn project_detailed_handler(
         http_bail!(StatusCode::BAD_REQUEST, "Dimension is hidden for this project")
     }
 
-    let conn = app.events_conn().http_status(StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let stats = spawn_blocking(move || {
-        reports::dimension_report(&conn, &entities, "pageview", &req.range, &req.dimension, &req.filters, &req.metric)
-    })
-    .await
-    .http_status(StatusCode::INTERNAL_SERVER_ERROR)?
-    .http_status(StatusCode::INTERNAL_SERVER_ERROR)?;
+    // entity rows are labeled with the display names of the project's entities
+    let entity_names = match req.dimension {
+        Dimension::Entity => app
+            .projects
+            .entities(&project.id)
+            .http_status(StatusCode::INTERNAL_SERVER_ERROR)?
+            .into_iter()
+            .map(|entity| (entity.id, entity.display_name))
+            .collect(),
+        _ => HashMap::new(),
+    };
+
+    let options = DimensionTableOptions {
+        limit: req.limit,
+        offset: req.offset,
+        order: req.order,
+        search: req.search.clone().filter(|search| !search.is_empty()),
+        include_other: req.include_other,
+        values: None,
+    };
+    let comparison_range =
+        req.comparison.as_ref().map(|comparison| comparison_range(comparison, &req.range)).transpose()?;
+
+    let query = ReportQuery {
+        entities: &entities,
+        event: "pageview",
+        range: &req.range,
+        filters: &req.filters,
+        session_timeout: app.session_timeout(&project.id),
+        approximate: req.approximate,
+        shared_visitor_groups: app.shared_visitor_groups(&project.id),
+    };
+    let (dimension, metric) = (req.dimension, req.metric);
+    let table = |query: &ReportQuery, options: DimensionTableOptions| {
+        let kind = ReportKind::Dimension { metric, dimension, options: options.clone() };
+        cached_report(&app, ReportCacheKey::new(&project.id, query, kind), move |conn, query| {
+            reports::dimension_report(conn, query, &dimension, &metric, &options)
+        })
+    };
+
+    let options_limited = options.limit.is_some() || options.offset > 0;
+    let stats = table(&query, options).await?;
+    let comparison_values: HashMap<_, _> = match &comparison_range {
+        Some(range) => {
+            // only compare the rows of the returned page, unless it already contains all rows
+            let values = options_limited.then(|| stats.rows.iter().map(|row| row.dimension_value.clone()).collect());
+            let options = DimensionTableOptions { values, ..Default::default() };
+            let rows = table(&ReportQuery { range, ..query }, options).await?.rows;
+            rows.into_iter().map(|row| (row.dimension_value, row.value)).collect()
+        }
+        None => HashMap::new(),
+    };
 
     let mut data = Vec::new();
-    for (key, value) in stats {
+    for ReportTableRow { dimension_value: key, value } in stats.rows {
+        let comparison = comparison_range.is_some().then(|| comparison_values.get(&key).copied().unwrap_or_default());
         match req.dimension {
             Dimension::Referrer => {
                 let display_name = crate::utils::referrer::get_referer_name(&key);
@@ -218,37 +591,270 @@ async fn project_detailed_handler(
                 } else {
                     None
                 };
-                data.push(DimensionTableRow { dimension_value: key, value, display_name, icon });
+                data.push(DimensionTableRow { dimension_value: key, value, display_name, icon, comparison });
             }
             Dimension::Browser => {
                 let display_name = match key.as_str() {
                     "Edge" => Some("Microsoft Edge".to_string()),
                     _ => None,
                 };
-                data.push(DimensionTableRow { dimension_value: key, value, display_name, icon: None });
+                data.push(DimensionTableRow { dimension_value: key, value, display_name, icon: None, comparison });
+            }
+            Dimension::BrowserVersion => {
+                let display_name = key.strip_prefix("Edge ").map(|version| format!("Microsoft Edge {version}"));
+                data.push(DimensionTableRow { dimension_value: key, value, display_name, icon: None, comparison });
+            }
+            Dimension::DeviceClass => {
+                let display_name = match key.as_str() {
+                    "desktop" => Some("Desktop".to_string()),
+                    "mobile" => Some("Mobile".to_string()),
+                    "tablet" => Some("Tablet".to_string()),
+                    "tv" => Some("TV".to_string()),
+                    "console" => Some("Console".to_string()),
+                    _ => None,
+                };
+                data.push(DimensionTableRow { dimension_value: key, value, display_name, icon: None, comparison });
             }
             Dimension::Country => {
                 let display_name = crate::utils::geo::get_country_name(&key);
-                data.push(DimensionTableRow { dimension_value: key, value, display_name, icon: None });
+                data.push(DimensionTableRow { dimension_value: key, value, display_name, icon: None, comparison });
+            }
+            Dimension::Channel => {
+                let display_name = key
+                    .parse::<crate::utils::channel::Channel>()
+                    .ok()
+                    .map(|channel| channel.display_name().to_string());
+                data.push(DimensionTableRow { dimension_value: key, value, display_name, icon: None, comparison });
+            }
+            Dimension::Language => {
+                let display_name = crate::utils::language::get_language_name(&key);
+                data.push(DimensionTableRow { dimension_value: key, value, display_name, icon: None, comparison });
             }
-            Dimension::City => {
+            Dimension::Continent => {
+                let display_name = crate::utils::geo::get_continent_name(&key);
+                data.push(DimensionTableRow { dimension_value: key, value, display_name, icon: None, comparison });
+            }
+            Dimension::City | Dimension::Region => {
                 let (country, city) = key
                     .clone()
                     .split_at_checked(2)
                     .map_or((None, None), |(a, b)| (Some(a.to_string()), Some(b.to_string())));
                 let city = city.filter(|city| !city.is_empty());
-                data.push(DimensionTableRow { dimension_value: key, value, display_name: city, icon: country });
+                data.push(DimensionTableRow {
+                    dimension_value: key,
+                    value,
+                    display_name: city,
+                    icon: country,
+                    comparison,
+                });
+            }
+            Dimension::Entity => {
+                let display_name = entity_names.get(&key).cloned();
+                data.push(DimensionTableRow { dimension_value: key, value, display_name, icon: None, comparison });
             }
             Dimension::ScreenWidth | Dimension::Orientation => {
                 let display_name =
                     key.chars().next().map(|c| c.to_uppercase().collect::<String>() + &key[c.len_utf8()..]);
-                data.push(DimensionTableRow { dimension_value: key, value, display_name, icon: None });
+                data.push(DimensionTableRow { dimension_value: key, value, display_name, icon: None, comparison });
             }
             _ => {
-                data.push(DimensionTableRow { dimension_value: key, value, display_name: None, icon: None });
+                data.push(DimensionTableRow {
+                    dimension_value: key,
+                    value,
+                    display_name: None,
+                    icon: None,
+                    comparison,
+                });
             }
         }
     }
 
-    Ok(Json(DimensionResponse { data }))
+    Ok(Json(DimensionResponse { data, total: stats.total_rows, other: stats.other, comparison_range }))
+}
+
+async fn project_pivot_handler(
+    app: State<RouterState>,
+    MaybeAuth(user): MaybeAuth,
+    Path(project_id): Path<String>,
+    Json(req): Json<PivotRequest>,
+) -> ApiResult<Json<PivotResponse>> {
+    let project = app.projects.get(&project_id).http_status(StatusCode::NOT_FOUND)?;
+    let entities = app.projects.entity_ids(&project.id).http_status(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if !can_view_project(&project, user.as_ref()) {
+        http_bail!(StatusCode::NOT_FOUND, "Project not found")
+    }
+
+    if app.is_metric_hidden(&project.id, &entities, req.metric) {
+        http_bail!(StatusCode::BAD_REQUEST, "Metric is hidden for this project")
+    }
+    if app.is_dimension_hidden(&project.id, &entities, req.rows)
+        || app.is_dimension_hidden(&project.id, &entities, req.columns)
+    {
+        http_bail!(StatusCode::BAD_REQUEST, "Dimension is hidden for this project")
+    }
+    if req.rows == req.columns {
+        http_bail!(StatusCode::BAD_REQUEST, "Rows and columns must use different dimensions")
+    }
+    if !(1..=validate::MAX_PIVOT_VALUES).contains(&req.limit) {
+        http_bail!(StatusCode::BAD_REQUEST, "Limit must be between 1 and {}", validate::MAX_PIVOT_VALUES)
+    }
+
+    let query = ReportQuery {
+        entities: &entities,
+        event: "pageview",
+        range: &req.range,
+        filters: &req.filters,
+        session_timeout: app.session_timeout(&project.id),
+        approximate: req.approximate,
+        shared_visitor_groups: app.shared_visitor_groups(&project.id),
+    };
+    let kind = ReportKind::Pivot { metric: req.metric, rows: req.rows, columns: req.columns, limit: req.limit };
+    let (rows, columns, metric, limit) = (req.rows, req.columns, req.metric, req.limit);
+    let pivot = cached_report(&app, ReportCacheKey::new(&project.id, &query, kind), move |conn, query| {
+        reports::pivot_report(conn, query, &rows, &columns, &metric, limit)
+    })
+    .await?;
+
+    Ok(Json(PivotResponse { data: pivot }))
+}
+
+async fn project_heatmap_handler(
+    app: State<RouterState>,
+    MaybeAuth(user): MaybeAuth,
+    Path(project_id): Path<String>,
+    Json(req): Json<HeatmapRequest>,
+) -> ApiResult<Json<HeatmapResponse>> {
+    let project = app.projects.get(&project_id).http_status(StatusCode::NOT_FOUND)?;
+    let entities = app.projects.entity_ids(&project.id).http_status(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if !can_view_project(&project, user.as_ref()) {
+        http_bail!(StatusCode::NOT_FOUND, "Project not found")
+    }
+
+    if app.is_metric_hidden(&project.id, &entities, req.metric) {
+        http_bail!(StatusCode::BAD_REQUEST, "Metric is hidden for this project")
+    }
+
+    let timezone = reports::parse_timezone(req.timezone.as_deref()).http_status(StatusCode::BAD_REQUEST)?;
+
+    let query = ReportQuery {
+        entities: &entities,
+        event: "pageview",
+        range: &req.range,
+        filters: &req.filters,
+        session_timeout: app.session_timeout(&project.id),
+        approximate: req.approximate,
+        shared_visitor_groups: app.shared_visitor_groups(&project.id),
+    };
+    let kind = ReportKind::Heatmap { metric: req.metric, timezone: timezone.name().to_string() };
+    let metric = req.metric;
+    let heatmap = cached_report(&app, ReportCacheKey::new(&project.id, &query, kind), move |conn, query| {
+        reports::heatmap_report(conn, query, &metric, timezone)
+    })
+    .await?;
+
+    Ok(Json(HeatmapResponse { data: heatmap }))
+}
+
+async fn project_movers_handler(
+    app: State<RouterState>,
+    MaybeAuth(user): MaybeAuth,
+    Path(project_id): Path<String>,
+    Json(req): Json<MoversRequest>,
+) -> ApiResult<Json<MoversResponse>> {
+    let project = app.projects.get(&project_id).http_status(StatusCode::NOT_FOUND)?;
+    let entities = app.projects.entity_ids(&project.id).http_status(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if !can_view_project(&project, user.as_ref()) {
+        http_bail!(StatusCode::NOT_FOUND, "Project not found")
+    }
+
+    if app.is_metric_hidden(&project.id, &entities, req.metric) {
+        http_bail!(StatusCode::BAD_REQUEST, "Metric is hidden for this project")
+    }
+    if app.is_dimension_hidden(&project.id, &entities, req.dimension) {
+        http_bail!(StatusCode::BAD_REQUEST, "Dimension is hidden for this project")
+    }
+    if !(1..=validate::MAX_MOVERS).contains(&req.limit) {
+        http_bail!(StatusCode::BAD_REQUEST, "Limit must be between 1 and {}", validate::MAX_MOVERS)
+    }
+
+    let comparison_range = comparison_range(&req.comparison, &req.range)?;
+    let options = MoversOptions { limit: req.limit, min_volume: req.min_volume, order: req.order };
+
+    let query = ReportQuery {
+        entities: &entities,
+        event: "pageview",
+        range: &req.range,
+        filters: &req.filters,
+        session_timeout: app.session_timeout(&project.id),
+        approximate: req.approximate,
+        shared_visitor_groups: app.shared_visitor_groups(&project.id),
+    };
+    let kind = ReportKind::Movers {
+        metric: req.metric,
+        dimension: req.dimension,
+        comparison_range: comparison_range.clone(),
+        options: options.clone(),
+    };
+    let (dimension, metric, range) = (req.dimension, req.metric, comparison_range.clone());
+    let movers = cached_report(&app, ReportCacheKey::new(&project.id, &query, kind), move |conn, query| {
+        reports::movers_report(conn, query, &range, &dimension, &metric, &options)
+    })
+    .await?;
+
+    Ok(Json(MoversResponse { data: movers, comparison_range }))
+}
+
+async fn project_journey_handler(
+    app: State<RouterState>,
+    MaybeAuth(user): MaybeAuth,
+    Path(project_id): Path<String>,
+    Json(req): Json<JourneyRequest>,
+) -> ApiResult<Json<JourneyResponse>> {
+    let project = app.projects.get(&project_id).http_status(StatusCode::NOT_FOUND)?;
+    let entities = app.projects.entity_ids(&project.id).http_status(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if !can_view_project(&project, user.as_ref()) {
+        http_bail!(StatusCode::NOT_FOUND, "Project not found")
+    }
+
+    if app.is_metric_hidden(&project.id, &entities, Metric::Sessions) {
+        http_bail!(StatusCode::BAD_REQUEST, "Journeys need session tracking")
+    }
+    if !(1..=validate::MAX_JOURNEY_STEPS).contains(&req.steps) {
+        http_bail!(StatusCode::BAD_REQUEST, "Steps must be between 1 and {}", validate::MAX_JOURNEY_STEPS)
+    }
+    if !(1..=validate::MAX_JOURNEY_PAGES).contains(&req.limit) {
+        http_bail!(StatusCode::BAD_REQUEST, "Limit must be between 1 and {}", validate::MAX_JOURNEY_PAGES)
+    }
+    if req.entry_only && req.direction == JourneyDirection::Backward {
+        http_bail!(StatusCode::BAD_REQUEST, "Entry pages can only be followed forward")
+    }
+
+    let options = JourneyOptions {
+        url: req.url.clone(),
+        direction: req.direction,
+        steps: req.steps,
+        limit: req.limit,
+        entry_only: req.entry_only,
+    };
+
+    let query = ReportQuery {
+        entities: &entities,
+        event: "pageview",
+        range: &req.range,
+        filters: &req.filters,
+        session_timeout: app.session_timeout(&project.id),
+        approximate: false,
+        shared_visitor_groups: app.shared_visitor_groups(&project.id),
+    };
+    let kind = ReportKind::Journey(options.clone());
+    let journey = cached_report(&app, ReportCacheKey::new(&project.id, &query, kind), move |conn, query| {
+        reports::journey_report(conn, query, &options)
+    })
+    .await?;
+
+    Ok(Json(JourneyResponse { data: journey }))
 }
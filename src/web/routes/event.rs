@@ -2,11 +2,14 @@ use crate::app::models::{
     FilterType, GeoDetail, IngestDropRule, IngestFilter, ResolvedCollectionSettings, VisitorGroupMode, hostname_allowed,
 };
 use crate::app::{Liwan, models::Event};
+use crate::utils::channel::classify_channel;
 use crate::utils::hash::{visitor_group_id, visitor_group_id_cidr, visitor_group_id_fallback};
 use crate::utils::referrer::{Referrer, process_referer};
-use crate::utils::useragent;
+use crate::utils::useragent::{self, ClientHints};
 use crate::web::RouterState;
-use crate::web::webext::{ApiResult, AxumErrExt, ClientIp, GeoLocationHeaders, empty_response};
+use crate::web::webext::{
+    AcceptLanguage, ApiResult, AxumErrExt, ClientHintHeaders, ClientIp, GeoLocationHeaders, empty_response,
+};
 
 use aide::axum::routing::post;
 use aide::axum::{ApiRouter, IntoApiResponse};
@@ -146,6 +149,8 @@ async fn event_handler(
     state: State<RouterState>,
     ClientIp(ip): ClientIp,
     geo_headers: GeoLocationHeaders,
+    ClientHintHeaders(client_hints): ClientHintHeaders,
+    AcceptLanguage(language): AcceptLanguage,
     TypedHeader(user_agent): TypedHeader<headers::UserAgent>,
     event: Bytes,
 ) -> ApiResult<impl IntoApiResponse> {
@@ -157,8 +162,10 @@ async fn event_handler(
     let events = state.events.clone();
     event.validate().context("invalid event").http_err("invalid event", StatusCode::BAD_REQUEST)?;
 
+    let client = RequestClient { ip, geo_headers, client_hints, language, user_agent };
+
     // blocking a bit to give some slight backpressure to the caller
-    let res = tokio::task::spawn_blocking(move || process_event(app, event, url, ip, geo_headers, user_agent))
+    let res = tokio::task::spawn_blocking(move || process_event(app, event, url, client))
         .await
         .http_status(StatusCode::INTERNAL_SERVER_ERROR)?;
 
@@ -176,14 +183,17 @@ async fn event_handler(
     Ok(empty_response())
 }
 
-fn process_event(
-    app: Arc<Liwan>,
-    event: EventRequest,
-    mut url: Url,
+/// Request metadata about the client that sent an event
+struct RequestClient {
     ip: Option<IpAddr>,
     geo_headers: GeoLocationHeaders,
+    client_hints: ClientHints,
+    language: Option<String>,
     user_agent: headers::UserAgent,
-) -> Result<Option<Event>> {
+}
+
+fn process_event(app: Arc<Liwan>, event: EventRequest, mut url: Url, request: RequestClient) -> Result<Option<Event>> {
+    let RequestClient { ip, geo_headers, client_hints, language, user_agent } = request;
     let referrer = match process_referer(event.referrer.as_deref()) {
         Referrer::Fqdn(fqdn) => Some(fqdn),
         Referrer::Unknown(r) => r,
@@ -215,9 +225,11 @@ fn process_event(
     if client.is_bot() {
         return Ok(None);
     }
+    let client = client.with_client_hints(&client_hints);
 
+    let scope = app.project_settings.visitor_group_scope(&event.entity_id)?;
     let visitor_group_id =
-        resolve_visitor_group_id(&settings, ip, user_agent.as_str(), &app.events.get_salt()?, &event.entity_id);
+        resolve_visitor_group_id(&settings, ip, user_agent.as_str(), &app.events.get_salt()?, &scope);
 
     let (country, city) = match settings.track_geo {
         GeoDetail::None => (None, None),
@@ -225,23 +237,38 @@ fn process_event(
         GeoDetail::City => (geo_headers.country, geo_headers.city),
     };
 
+    #[cfg(not(feature = "geoip"))]
+    let (region, continent, time_zone, asn, asn_org) = (None, None, None, None, None);
+
     #[cfg(feature = "geoip")]
-    let (country, city) = match settings.track_geo {
-        GeoDetail::None => (None, None),
-        GeoDetail::Country => {
-            let maxmind_country = ip.and_then(|ip| app.geoip.lookup(&ip).ok()).and_then(|lookup| lookup.country_code);
-            (maxmind_country.or(country), None)
-        }
-        GeoDetail::City => {
-            let lookup = ip.and_then(|ip| app.geoip.lookup(&ip).ok());
-            (
-                lookup.as_ref().and_then(|value| value.country_code.clone()).or(country),
-                lookup.and_then(|value| value.city).or(city),
-            )
-        }
+    let (country, city, region, continent, time_zone, asn, asn_org) = {
+        let lookup = match settings.track_geo {
+            GeoDetail::None => Default::default(),
+            GeoDetail::Country | GeoDetail::City => ip.and_then(|ip| app.geoip.lookup(&ip).ok()).unwrap_or_default(),
+        };
+        let city_level = |value: Option<String>| value.filter(|_| settings.track_geo == GeoDetail::City);
+        (
+            lookup.country_code.or(country),
+            city_level(lookup.city.or(city)),
+            city_level(lookup.region),
+            lookup.continent_code,
+            city_level(lookup.time_zone),
+            lookup.asn,
+            lookup.asn_org,
+        )
     };
 
-    let utm = if settings.track_utm_params { extract_utm(&mut url) } else { Utm::default() };
+    let language = if settings.track_language { language } else { None };
+    let utm = extract_utm(&mut url);
+    let utm = if settings.track_utm_params { utm } else { Utm::default() };
+    let channel = classify_channel(
+        referrer.as_deref(),
+        &fqdn,
+        utm.source.as_deref(),
+        utm.medium.as_deref(),
+        utm.campaign.as_deref(),
+        url.query(),
+    );
     url.set_query(None);
     let path = url.path().to_string();
     let path = if path.len() > 1 && path.ends_with('/') { path.trim_end_matches('/').to_string() } else { path };
@@ -251,9 +278,17 @@ fn process_event(
         referrer,
         country,
         city,
+        region,
+        continent,
+        time_zone,
+        asn,
+        asn_org,
         mobile: Some(client.is_mobile()),
+        device_class: Some(client.device_class.to_string()),
         browser: client.ua_family,
+        browser_version: client.ua_version,
         platform: client.os_family,
+        platform_version: client.os_version,
         created_at: Utc::now(),
         entity_id: event.entity_id,
         event: event.name,
@@ -266,6 +301,8 @@ fn process_event(
         utm_term: utm.term,
         screen_width: event.screen_width,
         orientation: event.orientation,
+        language,
+        channel: Some(channel.to_string()),
         track_sessions: settings.track_sessions,
     };
 
@@ -291,6 +328,7 @@ fn ingest_filter_matches(event: &Event, filter: &IngestFilter) -> bool {
     }
 
     let url;
+    let asn;
     let value = match filter.dimension.as_str() {
         "event" => Some(event.event.as_str()),
         "url" => {
@@ -302,8 +340,21 @@ fn ingest_filter_matches(event: &Event, filter: &IngestFilter) -> bool {
         "referrer" => event.referrer.as_deref(),
         "country" => event.country.as_deref(),
         "city" => event.city.as_deref(),
+        "region" => event.region.as_deref(),
+        "continent" => event.continent.as_deref(),
+        "time_zone" => event.time_zone.as_deref(),
+        "asn" => {
+            asn = event.asn.map(|asn| asn.to_string());
+            asn.as_deref()
+        }
+        "asn_org" => event.asn_org.as_deref(),
         "platform" => event.platform.as_deref(),
         "browser" => event.browser.as_deref(),
+        "browser_version" => event.browser_version.as_deref(),
+        "platform_version" => event.platform_version.as_deref(),
+        "device_class" => event.device_class.as_deref(),
+        "language" => event.language.as_deref(),
+        "channel" => event.channel.as_deref(),
         "utm_source" => event.utm_source.as_deref(),
         "utm_medium" => event.utm_medium.as_deref(),
         "utm_campaign" => event.utm_campaign.as_deref(),
@@ -337,16 +388,16 @@ fn resolve_visitor_group_id(
     ip: Option<IpAddr>,
     user_agent: &str,
     daily_salt: &str,
-    entity_id: &str,
+    scope: &str,
 ) -> String {
     match (settings.visitor_group_mode, ip) {
         (VisitorGroupMode::RandomPerRequest, _) | (_, None) => visitor_group_id_fallback(),
-        (VisitorGroupMode::Accurate, Some(ip)) => visitor_group_id(&ip, user_agent, daily_salt, entity_id),
+        (VisitorGroupMode::Accurate, Some(ip)) => visitor_group_id(&ip, user_agent, daily_salt, scope),
         (mode, Some(ip)) => {
             let Some((ipv4_prefix, ipv6_prefix)) = mode.cidr_prefixes() else {
                 return visitor_group_id_fallback();
             };
-            visitor_group_id_cidr(&ip, ipv4_prefix, ipv6_prefix, daily_salt, entity_id)
+            visitor_group_id_cidr(&ip, ipv4_prefix, ipv6_prefix, daily_salt, scope)
         }
     }
 }
@@ -395,6 +446,16 @@ mod test {
             utm_term: None,
             screen_width: None,
             orientation: None,
+            browser_version: None,
+            platform_version: None,
+            device_class: None,
+            language: None,
+            channel: None,
+            region: None,
+            continent: None,
+            time_zone: None,
+            asn: None,
+            asn_org: None,
             track_sessions: true,
         };
 
@@ -426,6 +487,16 @@ mod test {
             utm_term: None,
             screen_width: None,
             orientation: None,
+            browser_version: None,
+            platform_version: None,
+            device_class: None,
+            language: None,
+            channel: None,
+            region: None,
+            continent: None,
+            time_zone: None,
+            asn: None,
+            asn_org: None,
             track_sessions: true,
         };
 
@@ -484,9 +555,96 @@ mod test {
             utm_term: None,
             screen_width: None,
             orientation: None,
+            browser_version: None,
+            platform_version: None,
+            device_class: None,
+            language: None,
+            channel: None,
+            region: None,
+            continent: None,
+            time_zone: None,
+            asn: None,
+            asn_org: None,
             track_sessions: true,
         };
 
         assert!(!ingest_drop_rule_matches(&event, &IngestDropRule { filters: Vec::new() }));
     }
+
+    #[test]
+    fn language_is_dropped_when_not_tracked() {
+        let app = Liwan::new_memory(crate::config::Config::default()).expect("failed to create app");
+        let entity =
+            crate::app::models::Entity { id: "language-entity".to_string(), display_name: "Language".to_string() };
+        app.entities.create(&entity, &[]).expect("failed to create entity");
+
+        let event = |app: &Arc<Liwan>| {
+            let request = EventRequest {
+                entity_id: entity.id.clone(),
+                name: "pageview".to_string(),
+                url: "https://example.com/".to_string(),
+                referrer: None,
+                screen_width: None,
+                orientation: None,
+            };
+            let client = RequestClient {
+                ip: None,
+                geo_headers: GeoLocationHeaders::default(),
+                client_hints: ClientHints::default(),
+                language: Some("de".to_string()),
+                user_agent: headers::UserAgent::from_static(
+                    "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0",
+                ),
+            };
+            let url = Url::parse(&request.url).expect("valid url");
+            process_event(app.clone(), request, url, client).expect("failed to process event").expect("event dropped")
+        };
+        assert_eq!(event(&app).language.as_deref(), Some("de"));
+
+        let mut settings = app.settings.entity(&entity.id);
+        settings.track_language = Some(false);
+        app.settings.update_entity(&settings).expect("failed to update settings");
+        assert_eq!(event(&app).language, None);
+    }
+
+    #[test]
+    fn shared_visitor_groups_use_the_project_scope() {
+        let app = Liwan::new_memory(crate::config::Config::default()).expect("failed to create app");
+        let project = crate::app::models::Project {
+            id: "shared-project".to_string(),
+            display_name: "Shared Project".to_string(),
+            public: false,
+            unlisted: false,
+            secret: None,
+        };
+        app.projects.create(&project, &[]).expect("failed to create project");
+        for entity_id in ["shared-entity-1", "shared-entity-2"] {
+            let entity = crate::app::models::Entity { id: entity_id.to_string(), display_name: entity_id.to_string() };
+            app.entities.create(&entity, std::slice::from_ref(&project.id)).expect("failed to create entity");
+        }
+        let mut settings = app.project_settings.get(&project.id).expect("failed to get settings");
+        settings.shared_visitor_groups = true;
+        app.project_settings.update(&settings).expect("failed to update settings");
+
+        let scopes = ["shared-entity-1", "shared-entity-2"]
+            .map(|entity_id| app.project_settings.visitor_group_scope(entity_id).expect("failed to resolve scope"));
+        assert_eq!(scopes, ["project/shared-project".to_string(), "project/shared-project".to_string()]);
+        assert_eq!(
+            app.project_settings.visitor_group_scope("unshared-entity").expect("failed to resolve scope"),
+            "unshared-entity"
+        );
+
+        let ip = IpAddr::from([192, 0, 2, 1]);
+        let [first, second] = scopes.map(|scope| visitor_group_id(&ip, "agent", "salt", &scope));
+        assert_eq!(first, second);
+        assert_ne!(first, visitor_group_id(&ip, "agent", "salt", "shared-entity-1"));
+
+        // cached scopes are dropped when the project settings change
+        settings.shared_visitor_groups = false;
+        app.project_settings.update(&settings).expect("failed to update settings");
+        assert_eq!(
+            app.project_settings.visitor_group_scope("shared-entity-1").expect("failed to resolve scope"),
+            "shared-entity-1"
+        );
+    }
 }
@@ -6,6 +6,8 @@ use std::pin::Pin;
 use std::task::{Context, Poll};
 
 use crate::utils::ip_headers::{parse_geoip_headers, parse_header_ip, should_trust_proxy_headers};
+use crate::utils::language::parse_accept_language;
+use crate::utils::useragent::ClientHints;
 use crate::web::Files;
 use crate::web::RouterState;
 use aide::axum::IntoApiResponse;
@@ -317,3 +319,40 @@ impl FromRequestParts<RouterState> for GeoLocationHeaders {
         Ok(Self { country: values.country, city: values.city })
     }
 }
+
+#[derive(Debug, Clone, Default)]
+pub struct ClientHintHeaders(pub ClientHints);
+impl OperationInput for ClientHintHeaders {}
+
+impl FromRequestParts<RouterState> for ClientHintHeaders {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut http::request::Parts,
+        _state: &RouterState,
+    ) -> Result<Self, Self::Rejection> {
+        let header = |name: &str| parts.headers.get(name).and_then(|value| value.to_str().ok());
+        Ok(Self(ClientHints::parse(
+            header("sec-ch-ua"),
+            header("sec-ch-ua-mobile"),
+            header("sec-ch-ua-platform"),
+            header("sec-ch-ua-platform-version"),
+        )))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AcceptLanguage(pub Option<String>);
+impl OperationInput for AcceptLanguage {}
+
+impl FromRequestParts<RouterState> for AcceptLanguage {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut http::request::Parts,
+        _state: &RouterState,
+    ) -> Result<Self, Self::Rejection> {
+        let header = parts.headers.get(header::ACCEPT_LANGUAGE).and_then(|value| value.to_str().ok());
+        Ok(Self(header.and_then(parse_accept_language)))
+    }
+}
@@ -16,17 +16,25 @@ async fn test_dashboard() -> Result<()> {
     let stats_path = format!("{api_prefix}/stats");
     let graph_path = format!("{api_prefix}/graph");
     let dimension_path = format!("{api_prefix}/dimension");
+    let breakdown_path = format!("{api_prefix}/graph/breakdown");
+    let pivot_path = format!("{api_prefix}/pivot");
 
     let start_date = (Utc::now() - Duration::days(365)).to_rfc3339();
     let end_date = Utc::now().to_rfc3339();
 
     let stats_requests = [
         json!({"range":{"start": start_date ,"end": end_date},"filters":[]}),
+        json!({"range":{"start": start_date ,"end": end_date},"filters":[{"dimension":"url_entry","filterType":"equal","value":"example.org/","scope":"session"}]}),
         json!({"range":{"start": start_date ,"end": end_date},"filters":[{"dimension":"fqdn","filterType":"equal","value":"example.org"},{"dimension":"url","filterType":"equal","value":"example.org/contact"},{"dimension":"referrer","filterType":"equal","value":"liwan.dev"},{"dimension":"country","filterType":"equal","value":"AU"},{"dimension":"city","filterType":"equal","value":"Sydney"},{"dimension":"platform","filterType":"equal","value":"iOS"},{"dimension":"browser","filterType":"equal","value":"Safari"}]}),
+        json!({"range":{"start": start_date ,"end": end_date},"filters":[],"approximate":true}),
     ];
 
     let graph_requests = [
         json!({"range":{"start": start_date ,"end": end_date},"metric":"views","interval":"day","timezone":"UTC","filters":[]}),
+        json!({"range":{"start": start_date ,"end": end_date},"metric":"median_session_duration","interval":"day","timezone":"UTC","filters":[]}),
+        json!({"range":{"start": start_date ,"end": end_date},"metric":"unique_visitors","interval":"day","timezone":"UTC","filters":[],"approximate":true}),
+        json!({"range":{"start": start_date ,"end": end_date},"metric":"views","interval":"week","weekStart":"sunday","timezone":"Europe/Berlin","filters":[]}),
+        json!({"range":{"start": start_date ,"end": end_date},"metric":"unique_visitors","interval":"month","timezone":"UTC","filters":[]}),
         json!({"range":{"start": start_date ,"end": end_date},"metric":"views","interval":"day","timezone":"UTC","filters":[{"dimension":"fqdn","filterType":"equal","value":"example.org"},{"dimension":"url","filterType":"equal","value":"example.org/contact"},{"dimension":"referrer","filterType":"equal","value":"liwan.dev"},{"dimension":"country","filterType":"equal","value":"AU"},{"dimension":"city","filterType":"equal","value":"Sydney"},{"dimension":"platform","filterType":"equal","value":"iOS"},{"dimension":"browser","filterType":"equal","value":"Safari"}]}),
     ];
 
@@ -36,7 +44,25 @@ async fn test_dashboard() -> Result<()> {
         json!({"dimension":"city","filters":[{"dimension":"fqdn","filterType":"equal","value":"example.org"},{"dimension":"url","filterType":"equal","value":"example.org/contact"},{"dimension":"referrer","filterType":"equal","value":"liwan.dev"},{"dimension":"country","filterType":"equal","value":"AU"},{"dimension":"city","filterType":"equal","value":"Sydney"},{"dimension":"platform","filterType":"equal","value":"iOS"},{"dimension":"browser","filterType":"equal","value":"Safari"},{"dimension":"mobile","filterType":"is_true"}],"metric":"views","range":{"start": start_date ,"end": end_date}}),
         json!({"dimension":"browser","filters":[{"dimension":"fqdn","filterType":"equal","value":"example.org"},{"dimension":"url","filterType":"equal","value":"example.org/contact"},{"dimension":"referrer","filterType":"equal","value":"liwan.dev"},{"dimension":"country","filterType":"equal","value":"AU"},{"dimension":"city","filterType":"equal","value":"Sydney"},{"dimension":"platform","filterType":"equal","value":"iOS"},{"dimension":"browser","filterType":"equal","value":"Safari"},{"dimension":"mobile","filterType":"is_true"}],"metric":"views","range":{"start": start_date ,"end": end_date}}),
         json!({"dimension":"screen_width","filters":[],"metric":"views","range":{"start": start_date ,"end": end_date}}),
+        json!({"dimension":"browser_version","filters":[{"dimension":"device_class","filterType":"equal","value":"tablet"}],"metric":"views","range":{"start": start_date ,"end": end_date}}),
         json!({"dimension":"url","filters":[{"dimension":"screen_width","filterType":"equal","value":"xs"}],"metric":"views","range":{"start": start_date ,"end": end_date}}),
+        json!({"dimension":"channel","filters":[{"dimension":"channel","filterType":"equal","value":"organic_search"}],"metric":"views","range":{"start": start_date ,"end": end_date}}),
+        json!({"dimension":"region","filters":[{"dimension":"continent","filterType":"equal","value":"NA"}],"metric":"views","range":{"start": start_date ,"end": end_date}}),
+        json!({"dimension":"time_zone","filters":[],"metric":"unique_visitors","range":{"start": start_date ,"end": end_date}}),
+        json!({"dimension":"country","filters":[],"metric":"unique_visitors","range":{"start": start_date ,"end": end_date},"approximate":true}),
+        json!({"dimension":"url_exit","filters":[],"metric":"exit_rate","range":{"start": start_date ,"end": end_date}}),
+        json!({"dimension":"url","filters":[{"dimension":"channel","filterType":"equal","value":"organic_search","scope":"session"},{"dimension":"country","filterType":"equal","value":"AU"}],"metric":"sessions","range":{"start": start_date ,"end": end_date}}),
+        json!({"dimension":"channel","filters":[],"metric":"views_per_session","range":{"start": start_date ,"end": end_date}}),
+        json!({"dimension":"language","filters":[{"dimension":"language","filterType":"starts_with","value":"en"}],"metric":"views","range":{"start": start_date ,"end": end_date}}),
+        json!({"dimension":"url","filters":[],"metric":"views","range":{"start": start_date ,"end": end_date},"limit":5,"offset":5,"includeOther":true}),
+        json!({"dimension":"country","filters":[],"metric":"unique_visitors","range":{"start": start_date ,"end": end_date},"limit":3,"order":"asc","includeOther":true}),
+        json!({"dimension":"path","filters":[],"metric":"views","range":{"start": start_date ,"end": end_date},"limit":10,"search":"contact"}),
+    ];
+
+    let breakdown_requests = [
+        json!({"range":{"start": start_date ,"end": end_date},"metric":"views","dimension":"country","interval":"week","timezone":"UTC","filters":[]}),
+        json!({"range":{"start": start_date ,"end": end_date},"metric":"bounce_rate","dimension":"utm_campaign","interval":"month","timezone":"UTC","limit":3,"filters":[{"dimension":"fqdn","filterType":"equal","value":"example.org"}]}),
+        json!({"range":{"start": start_date ,"end": end_date},"metric":"sessions","dimension":"url_entry","interval":"day","timezone":"UTC","limit":2,"filters":[]}),
     ];
 
     for request in stats_requests.iter() {
@@ -54,5 +80,105 @@ async fn test_dashboard() -> Result<()> {
         res.assert_status_success();
     }
 
+    for request in breakdown_requests.iter() {
+        let res = client.post(&breakdown_path, request.clone()).await;
+        res.assert_status_success();
+    }
+
+    let pivot_requests = [
+        json!({"range":{"start": start_date ,"end": end_date},"metric":"views","rows":"country","columns":"browser","filters":[]}),
+        json!({"range":{"start": start_date ,"end": end_date},"metric":"bounce_rate","rows":"utm_campaign","columns":"path","limit":5,"filters":[{"dimension":"fqdn","filterType":"equal","value":"example.org"}]}),
+        json!({"range":{"start": start_date ,"end": end_date},"metric":"unique_visitors","rows":"url_entry","columns":"channel","filters":[],"approximate":true}),
+    ];
+
+    for request in pivot_requests.iter() {
+        let res = client.post(&pivot_path, request.clone()).await;
+        res.assert_status_success();
+    }
+
+    let same_dimensions = json!({"range":{"start": start_date ,"end": end_date},"metric":"views","rows":"path","columns":"path","filters":[]});
+    client.post(&pivot_path, same_dimensions).await.assert_status_bad_request();
+
+    let comparison_requests = [
+        (
+            &stats_path,
+            json!({"range":{"start": start_date ,"end": end_date},"filters":[],"comparison":{"mode":"previous_year"}}),
+        ),
+        (
+            &graph_path,
+            json!({"range":{"start": start_date ,"end": end_date},"metric":"views","interval":"week","timezone":"UTC","filters":[],"comparison":{"mode":"previous_period"}}),
+        ),
+        (
+            &graph_path,
+            json!({"range":{"start": start_date ,"end": end_date},"metric":"views","interval":"month","timezone":"UTC","filters":[],"comparison":{"mode":"custom","start": start_date,"end": end_date}}),
+        ),
+        (
+            &dimension_path,
+            json!({"dimension":"path","filters":[],"metric":"views","range":{"start": start_date ,"end": end_date},"limit":5,"comparison":{"mode":"previous_year"}}),
+        ),
+        (
+            &dimension_path,
+            json!({"dimension":"country","filters":[],"metric":"unique_visitors","range":{"start": start_date ,"end": end_date},"comparison":{"mode":"previous_period"}}),
+        ),
+    ];
+
+    for (path, request) in comparison_requests.iter() {
+        let res = client.post(path, request.clone()).await;
+        res.assert_status_success();
+    }
+
+    let empty_comparison = json!({"range":{"start": start_date ,"end": end_date},"filters":[],"comparison":{"mode":"custom","start": end_date,"end": start_date}});
+    client.post(&stats_path, empty_comparison).await.assert_status_bad_request();
+
+    let heatmap_path = format!("{api_prefix}/heatmap");
+    let heatmap_requests = [
+        json!({"range":{"start": start_date ,"end": end_date},"metric":"views","timezone":"Europe/Berlin","filters":[]}),
+        json!({"range":{"start": start_date ,"end": end_date},"metric":"bounce_rate","filters":[{"dimension":"country","filterType":"equal","value":"AU"}]}),
+        json!({"range":{"start": start_date ,"end": end_date},"metric":"unique_visitors","timezone":"America/New_York","filters":[],"approximate":true}),
+    ];
+
+    for request in heatmap_requests.iter() {
+        let res = client.post(&heatmap_path, request.clone()).await;
+        res.assert_status_success();
+        let heatmap: serde_json::Value = res.json();
+        assert_eq!(heatmap["data"].as_array().map(Vec::len), Some(7));
+    }
+
+    let invalid_timezone = json!({"range":{"start": start_date ,"end": end_date},"metric":"views","timezone":"Mars/Olympus_Mons","filters":[]});
+    client.post(&heatmap_path, invalid_timezone).await.assert_status_bad_request();
+
+    let movers_path = format!("{api_prefix}/movers");
+    let movers_requests = [
+        json!({"range":{"start": start_date ,"end": end_date},"metric":"views","dimension":"referrer","filters":[]}),
+        json!({"range":{"start": start_date ,"end": end_date},"metric":"bounce_rate","dimension":"path","filters":[],"comparison":{"mode":"previous_year"},"order":"relative","minVolume":1}),
+        json!({"range":{"start": start_date ,"end": end_date},"metric":"sessions","dimension":"url_entry","filters":[{"dimension":"country","filterType":"equal","value":"AU"}],"limit":5}),
+    ];
+
+    for request in movers_requests.iter() {
+        let res = client.post(&movers_path, request.clone()).await;
+        res.assert_status_success();
+    }
+
+    let too_many_movers = json!({"range":{"start": start_date ,"end": end_date},"metric":"views","dimension":"path","filters":[],"limit":1000});
+    client.post(&movers_path, too_many_movers).await.assert_status_bad_request();
+
+    let journey_path = format!("{api_prefix}/journey");
+    let journey_requests = [
+        json!({"range":{"start": start_date ,"end": end_date},"url":"example.com/","filters":[]}),
+        json!({"range":{"start": start_date ,"end": end_date},"url":"example.com/contact","direction":"backward","steps":2,"limit":5,"filters":[]}),
+        json!({"range":{"start": start_date ,"end": end_date},"url":"example.com/","entryOnly":true,"filters":[{"dimension":"country","filterType":"equal","value":"AU"}]}),
+    ];
+
+    for request in journey_requests.iter() {
+        let res = client.post(&journey_path, request.clone()).await;
+        res.assert_status_success();
+    }
+
+    let backward_entries = json!({"range":{"start": start_date ,"end": end_date},"url":"example.com/","direction":"backward","entryOnly":true,"filters":[]});
+    client.post(&journey_path, backward_entries).await.assert_status_bad_request();
+
+    let too_many_points = json!({"range":{"start": start_date ,"end": end_date},"metric":"views","dimension":"country","interval":"day","timezone":"UTC","filters":[]});
+    client.post(&breakdown_path, too_many_points).await.assert_status_bad_request();
+
     Ok(())
 }